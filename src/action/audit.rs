@@ -0,0 +1,95 @@
+use crate::action::schema::SystemOperation;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// One attempted system operation, whether or not it ran
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub timestamp: DateTime<Utc>,
+    /// History id of the action
+    pub action_id: String,
+    pub caller: String,
+    pub operation: SystemOperation,
+    /// `allowed`, `approved`, `rejected` or `denied`
+    pub decision: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rule: Option<String>,
+    pub success: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Append-only JSON-lines log of system operations
+///
+/// Unlike the action history it is never compacted and is kept even when
+/// memory persistence is off.
+pub struct AuditLog {
+    path: PathBuf,
+    // Serializes appends so concurrent entries don't interleave
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn open(path: PathBuf) -> Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create {}", parent.display()))?;
+        }
+        Ok(Self {
+            path,
+            lock: Mutex::new(()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn record(&self, entry: &AuditEntry) {
+        let Ok(_guard) = self.lock.lock() else {
+            return;
+        };
+        if let Err(e) = append(&self.path, entry) {
+            warn!("Failed to write audit log {}: {:#}", self.path.display(), e);
+        }
+    }
+
+    /// The `limit` most recent entries, newest first
+    pub fn recent(&self, limit: usize) -> Result<Vec<AuditEntry>> {
+        if limit == 0 || !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let file = fs::File::open(&self.path)
+            .with_context(|| format!("Failed to open {}", self.path.display()))?;
+        let mut entries = VecDeque::with_capacity(limit);
+        for line in BufReader::new(file).lines() {
+            let Ok(entry) = serde_json::from_str::<AuditEntry>(&line?) else {
+                continue;
+            };
+            if entries.len() == limit {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+        Ok(entries.into_iter().rev().collect())
+    }
+}
+
+fn append(path: &Path, entry: &AuditEntry) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    Ok(())
+}
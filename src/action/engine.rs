@@ -1,165 +1,698 @@
+use crate::action::audit::{AuditEntry, AuditLog};
+use crate::action::file_ops::FileOperations;
+use crate::action::history::{ActionHistory, HistoryEntry};
+use crate::action::input::InputController;
+use crate::action::policy::{PolicyEngine, Verdict};
+use crate::action::process_ops::ProcessManager;
+use crate::action::schema::{
+    A11yFindParams, A11yPressParams, A11yQuery, A11ySetTextParams, A11yTreeParams, Action,
+    AnnotateScreenParams, ClickImageParams, ClickMarkParams, ClickParams, ClickTextParams,
+    ClipboardGetParams, ClipboardSetParams, FileOperation, FindImageParams, KeyParams, MouseButton,
+    MouseMoveParams, NoExtraFields, ProcessOperation, ReadTextParams, ScreenDiffParams,
+    ScreenshotParams, SystemOperation, TypeParams, UndoOperation, WindowOperation, WindowSelector,
+};
+use crate::action::simulate::{Prediction, Simulator};
+use crate::action::system_ops::SystemOperations;
+use crate::action::undo::{Prepared, UndoLog};
+use crate::action::wait::{Condition, Waiter};
+use crate::event::{Event, EventSystem};
+use crate::state::{ClipboardSystem, WindowSystem};
+use crate::vision::{A11yOperation, TemplateMatch, VisionSystem};
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
-use std::collections::VecDeque;
-use tracing::{info, error};
-use chrono;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Action {
-    pub action_type: String,
-    pub params: serde_json::Value,
-}
+use std::sync::Arc;
+use std::time::Instant;
+use tracing::{info, warn};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActionResult {
     pub success: bool,
     pub result: serde_json::Value,
     pub error: Option<String>,
+    /// History id of the action, for `undo` and `/api/history`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action_id: Option<String>,
+    /// Whether the action can be undone; absent when it changed nothing
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reversible: Option<bool>,
+}
+
+impl ActionResult {
+    pub fn success(result: serde_json::Value) -> Self {
+        Self {
+            success: true,
+            result,
+            error: None,
+            action_id: None,
+            reversible: None,
+        }
+    }
+
+    /// The policy engine refused to run the action
+    pub fn denied(rule: &str, reason: &str) -> Self {
+        Self {
+            success: false,
+            result: serde_json::json!({"policy": {"verdict": "deny", "rule": rule, "reason": reason}}),
+            error: Some(format!("Denied by policy rule {}: {}", rule, reason)),
+            action_id: None,
+            reversible: None,
+        }
+    }
+
+    /// Report for a dry run: succeeds when the action would be allowed and
+    /// nothing predicts it failing
+    pub fn predicted(action: &Action, verdict: Verdict, prediction: Prediction) -> Self {
+        let (policy, denial) = match verdict {
+            Verdict::Allow => (serde_json::json!({"verdict": "allow"}), None),
+            Verdict::Deny { rule, reason } => (
+                serde_json::json!({"verdict": "deny", "rule": rule, "reason": reason}),
+                Some(format!("Denied by policy rule {}: {}", rule, reason)),
+            ),
+            Verdict::Confirm { rule, reason } => (
+                serde_json::json!({"verdict": "confirm", "rule": rule, "reason": reason}),
+                None,
+            ),
+        };
+        let error = denial.or_else(|| prediction.problems.first().cloned());
+        Self {
+            success: error.is_none(),
+            result: serde_json::json!({
+                "dry_run": true,
+                "action_type": action.name(),
+                "policy": policy,
+                "effects": prediction.effects,
+                "problems": prediction.problems,
+                "warnings": prediction.warnings,
+            }),
+            error,
+            action_id: None,
+            reversible: None,
+        }
+    }
+
+    pub fn failure(error: impl std::fmt::Display) -> Self {
+        Self {
+            success: false,
+            result: serde_json::Value::Null,
+            // `{:#}` includes the full context chain for anyhow errors
+            error: Some(format!("{:#}", error)),
+            action_id: None,
+            reversible: None,
+        }
+    }
 }
 
 pub struct ActionEngine {
-    history: VecDeque<(Action, ActionResult)>,
-    max_history: usize,
+    history: ActionHistory,
+    policy: PolicyEngine,
+    undo: UndoLog,
+    audit: AuditLog,
+    processes: Arc<ProcessManager>,
+    system: SystemOperations,
+    vision: Arc<VisionSystem>,
+    events: Arc<EventSystem>,
 }
 
 impl ActionEngine {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(
+        vision: Arc<VisionSystem>,
+        history: ActionHistory,
+        policy: PolicyEngine,
+        undo: UndoLog,
+        audit: AuditLog,
+        events: Arc<EventSystem>,
+    ) -> Result<Self> {
         Ok(Self {
-            history: VecDeque::new(),
-            max_history: 10000,
+            history,
+            policy,
+            undo,
+            audit,
+            processes: Arc::new(ProcessManager::new()),
+            system: SystemOperations::new(),
+            vision,
+            events,
         })
     }
 
+    pub fn history(&self) -> &ActionHistory {
+        &self.history
+    }
+
+    pub fn policy(&self) -> &PolicyEngine {
+        &self.policy
+    }
+
+    pub fn undo_log(&self) -> &UndoLog {
+        &self.undo
+    }
+
+    pub fn audit_log(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    pub fn system(&self) -> &SystemOperations {
+        &self.system
+    }
+
+    /// Processes spawned through `process_operation`
+    pub fn processes(&self) -> Arc<ProcessManager> {
+        self.processes.clone()
+    }
+
     pub async fn execute(&self, action: Action) -> Result<ActionResult> {
-        info!("Executing action: {:?}", action.action_type);
-
-        let result = match action.action_type.as_str() {
-            "click" => self.execute_click(&action.params).await,
-            "type" => self.execute_type(&action.params).await,
-            "key" => self.execute_key(&action.params).await,
-            "screenshot" => self.execute_screenshot(&action.params).await,
-            "window_operation" => self.execute_window_operation(&action.params).await,
-            "file_operation" => self.execute_file_operation(&action.params).await,
-            "process_operation" => self.execute_process_operation(&action.params).await,
-            "system_operation" => self.execute_system_operation(&action.params).await,
-            _ => ActionResult {
-                success: false,
-                result: serde_json::Value::Null,
-                error: Some(format!("Unknown action type: {}", action.action_type)),
-            },
+        self.execute_as(action, "internal").await
+    }
+
+    /// Execute an action and record it in the history under `caller`
+    pub async fn execute_as(&self, action: Action, caller: &str) -> Result<ActionResult> {
+        info!("Executing action: {} (caller: {})", action.name(), caller);
+        let id = uuid::Uuid::new_v4().to_string();
+        let timestamp = chrono::Utc::now();
+        let started = Instant::now();
+
+        let (mut result, decision, rule) = match self.policy.evaluate(&action).await {
+            Verdict::Allow => (self.run(&id, &action).await, "allowed", None),
+            Verdict::Deny { rule, reason } => {
+                warn!("Denied {} by policy rule {}: {}", action.name(), rule, reason);
+                (ActionResult::denied(&rule, &reason), "denied", Some(rule))
+            }
+            Verdict::Confirm { rule, reason } => {
+                if self.policy.confirm(&action, caller, &rule, &reason).await {
+                    (self.run(&id, &action).await, "approved", Some(rule))
+                } else {
+                    let result = ActionResult::denied(&rule, &format!("{} (not approved)", reason));
+                    (result, "rejected", Some(rule))
+                }
+            }
         };
+        result.action_id = Some(id.clone());
+
+        if let Action::SystemOp(operation) = &action {
+            self.audit.record(&AuditEntry {
+                timestamp,
+                action_id: id.clone(),
+                caller: caller.to_string(),
+                operation: operation.redacted(),
+                decision: decision.to_string(),
+                rule,
+                success: result.success,
+                error: result.error.clone(),
+            });
+        }
 
+        let duration_ms = started.elapsed().as_millis() as u64;
+        self.events.publish(Event::ActionExecuted {
+            timestamp: chrono::Utc::now(),
+            action_id: id.clone(),
+            action_type: action.name().to_string(),
+            caller: caller.to_string(),
+            success: result.success,
+            error: result.error.clone(),
+            duration_ms,
+        });
+        self.history.record(HistoryEntry {
+            id,
+            timestamp,
+            duration_ms,
+            caller: caller.to_string(),
+            action,
+            result: result.clone(),
+        });
         Ok(result)
     }
 
-    async fn execute_click(&self, params: &serde_json::Value) -> ActionResult {
-        // Use enigo or inputbot for cross-platform mouse control
-        if let (Some(x), Some(y)) = (
-            params.get("x").and_then(|v| v.as_i64()),
-            params.get("y").and_then(|v| v.as_i64()),
-        ) {
-            // TODO: Implement actual click using enigo
-            info!("Clicking at ({}, {})", x, y);
-            ActionResult {
-                success: true,
-                result: serde_json::json!({"x": x, "y": y}),
-                error: None,
+    /// Predict what `actions` would do, in order, without running them
+    ///
+    /// Nothing is executed, parked for approval, counted against rate limits
+    /// or recorded in the history.
+    pub async fn dry_run(&self, actions: &[Action]) -> Vec<ActionResult> {
+        let mut simulator = Simulator::new();
+        let mut results = Vec::with_capacity(actions.len());
+        for action in actions {
+            info!("Dry run: {}", action.name());
+            let verdict = self.policy.check(action).await;
+            let prediction = simulator
+                .predict(action, &self.processes, self.vision.screenshot_dir())
+                .await;
+            results.push(ActionResult::predicted(action, verdict, prediction));
+        }
+        results
+    }
+
+    /// Dispatch an allowed action, keeping its undo record if it succeeds
+    async fn run(&self, id: &str, action: &Action) -> ActionResult {
+        let prepared = self.undo.prepare(id, action).await;
+        let mut result = self.dispatch(action).await;
+        match prepared {
+            Prepared::ReadOnly => {}
+            Prepared::Irreversible(reason) => {
+                if result.success {
+                    info!("{} is not reversible: {}", action.name(), reason);
+                    result.reversible = Some(false);
+                }
             }
-        } else {
-            ActionResult {
-                success: false,
-                result: serde_json::Value::Null,
-                error: Some("Missing x or y parameter".to_string()),
+            Prepared::Reversible(record) => {
+                if result.success {
+                    self.undo.commit(record);
+                    result.reversible = Some(true);
+                } else {
+                    self.undo.discard(record);
+                }
             }
         }
+        result
+    }
+
+    async fn dispatch(&self, action: &Action) -> ActionResult {
+        match action {
+            Action::MouseMove(params) => self.execute_mouse_move(params).await,
+            Action::Click(params) => self.execute_click(params).await,
+            Action::Type(params) => self.execute_type(params).await,
+            Action::Key(params) => self.execute_key(params).await,
+            Action::Screenshot(params) => self.execute_screenshot(params).await,
+            Action::WindowOp(params) => self.execute_window_operation(params).await,
+            Action::FileOp(params) => self.execute_file_operation(params).await,
+            Action::ProcessOp(params) => self.execute_process_operation(params).await,
+            Action::SystemOp(operation) => self.execute_system_operation(operation).await,
+            Action::Undo(operation) => self.execute_undo(operation).await,
+            Action::ClipboardGet(params) => self.execute_clipboard_get(params).await,
+            Action::ClipboardSet(params) => self.execute_clipboard_set(params).await,
+            Action::WaitForWindow(_)
+            | Action::WaitForProcess(_)
+            | Action::WaitForFile(_)
+            | Action::WaitForPixelChange(_)
+            | Action::WaitForText(_)
+            | Action::AssertWindow(_)
+            | Action::AssertProcess(_)
+            | Action::AssertFile(_)
+            | Action::AssertPixelChange(_)
+            | Action::AssertText(_) => match Condition::of(action) {
+                Some(condition) => self.execute_wait(condition, action.is_assertion()).await,
+                None => ActionResult::failure(format!("{} has no condition", action.name())),
+            },
+            Action::ReadText(params) => self.execute_read_text(params).await,
+            Action::ClickText(params) => self.execute_click_text(params).await,
+            Action::FindImage(params) => self.execute_find_image(params).await,
+            Action::ClickImage(params) => self.execute_click_image(params).await,
+            Action::ScreenDiff(params) => self.execute_screen_diff(params).await,
+            Action::AnnotateScreen(params) => self.execute_annotate_screen(params).await,
+            Action::ClickMark(params) => self.execute_click_mark(params).await,
+            Action::A11yTree(params) => self.execute_a11y_tree(params).await,
+            Action::A11yFind(params) => self.execute_a11y_find(params).await,
+            Action::A11yPress(params) => self.execute_a11y_press(params).await,
+            Action::A11ySetText(params) => self.execute_a11y_set_text(params).await,
+            Action::A11yFocus(query) => self.execute_a11y(query, A11yOperation::Focus).await,
+        }
+    }
+
+    async fn execute_mouse_move(&self, params: &MouseMoveParams) -> ActionResult {
+        info!("Moving pointer to ({}, {})", params.x, params.y);
+        match InputController::move_pointer(params.x, params.y).await {
+            Ok(()) => ActionResult::success(serde_json::json!({"x": params.x, "y": params.y})),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_click(&self, params: &ClickParams) -> ActionResult {
+        info!("Clicking {:?} at ({}, {})", params.button, params.x, params.y);
+        match InputController::click(params.x, params.y, params.button, params.double).await {
+            Ok(()) => ActionResult::success(serde_json::json!({
+                "x": params.x,
+                "y": params.y,
+                "button": params.button,
+                "double": params.double,
+            })),
+            Err(e) => ActionResult::failure(e),
+        }
     }
 
-    async fn execute_type(&self, params: &serde_json::Value) -> ActionResult {
-        if let Some(text) = params.get("text").and_then(|v| v.as_str()) {
-            // TODO: Implement actual typing using enigo
-            info!("Typing: {}", text);
-            ActionResult {
-                success: true,
-                result: serde_json::json!({"text_length": text.len()}),
-                error: None,
+    async fn execute_type(&self, params: &TypeParams) -> ActionResult {
+        info!("Typing {} characters", params.text.chars().count());
+        match InputController::type_text(params.text.clone()).await {
+            Ok(()) => {
+                ActionResult::success(serde_json::json!({"text_length": params.text.chars().count()}))
             }
-        } else {
-            ActionResult {
-                success: false,
-                result: serde_json::Value::Null,
-                error: Some("Missing text parameter".to_string()),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_key(&self, params: &KeyParams) -> ActionResult {
+        info!("Pressing key: {}", params.key);
+        match InputController::press_chord(&params.key).await {
+            Ok(()) => ActionResult::success(serde_json::json!({"key": params.key})),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_screenshot(&self, params: &ScreenshotParams) -> ActionResult {
+        match self.vision.capture_screen(params).await {
+            Ok(screenshot) => ActionResult::success(serde_json::json!(screenshot)),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_window_operation(&self, operation: &WindowOperation) -> ActionResult {
+        info!("Window operation: {:?}", operation);
+        match WindowSystem::execute(operation.clone()).await {
+            Ok(result) => ActionResult::success(result),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_file_operation(&self, operation: &FileOperation) -> ActionResult {
+        info!("File operation: {:?}", operation.redacted());
+        match FileOperations::execute(operation.clone()).await {
+            Ok(result) => ActionResult::success(result),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_process_operation(&self, operation: &ProcessOperation) -> ActionResult {
+        info!("Process operation: {:?}", operation.redacted());
+        match self.processes.execute(operation.clone()).await {
+            Ok(result) => ActionResult::success(result),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_system_operation(&self, operation: &SystemOperation) -> ActionResult {
+        info!("System operation: {}", operation.name());
+        match self.system.execute(operation.clone(), &self.processes).await {
+            Ok(result) => ActionResult::success(result),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_undo(&self, operation: &UndoOperation) -> ActionResult {
+        info!("Undo: {:?}", operation);
+        match self.undo.undo(operation).await {
+            Ok(result) => ActionResult::success(result),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_clipboard_get(&self, params: &ClipboardGetParams) -> ActionResult {
+        info!("Reading clipboard as {:?}", params.format);
+        match ClipboardSystem::get(params.clone()).await {
+            Ok(result) => ActionResult::success(result),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_clipboard_set(&self, params: &ClipboardSetParams) -> ActionResult {
+        info!("Setting clipboard");
+        match ClipboardSystem::set(params.clone()).await {
+            Ok(result) => ActionResult::success(result),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_read_text(&self, params: &ReadTextParams) -> ActionResult {
+        info!("Reading text on screen");
+        match self
+            .vision
+            .recognize_text(params.monitor, params.region, params.min_confidence)
+            .await
+        {
+            Ok(result) => ActionResult::success(serde_json::json!({
+                "engine": result.engine,
+                "text": result.text,
+                "word_count": result.word_count(),
+                "lines": result.lines,
+            })),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    /// A denial if the policy keeps input from the window at a point found on screen
+    async fn refused_click(&self, x: i32, y: i32) -> Option<ActionResult> {
+        match self.policy.check_click(x, y).await {
+            Verdict::Allow => None,
+            Verdict::Deny { rule, reason } | Verdict::Confirm { rule, reason } => {
+                warn!("Refused click at ({}, {}) by policy rule {}: {}", x, y, rule, reason);
+                Some(ActionResult::denied(&rule, &reason))
             }
         }
     }
 
-    async fn execute_key(&self, params: &serde_json::Value) -> ActionResult {
-        // TODO: Implement key press
-        ActionResult {
-            success: true,
-            result: serde_json::Value::Null,
-            error: None,
+    async fn execute_click_text(&self, params: &ClickTextParams) -> ActionResult {
+        info!("Clicking text {:?}", params.text);
+        let recognized = match self
+            .vision
+            .recognize_text(params.monitor, params.region, params.min_confidence)
+            .await
+        {
+            Ok(result) => result,
+            Err(e) => return ActionResult::failure(e),
+        };
+        let matches = recognized.find(&params.text, params.case_sensitive);
+        let Some(target) = matches.get(params.occurrence) else {
+            return ActionResult::failure(format!(
+                "Text {:?} not found on screen ({} match(es), wanted occurrence {})",
+                params.text,
+                matches.len(),
+                params.occurrence
+            ));
+        };
+        let (x, y) = target.center;
+        if let Some(refused) = self.refused_click(x, y).await {
+            return refused;
+        }
+        match InputController::click(x, y, params.button, params.double).await {
+            Ok(()) => ActionResult::success(serde_json::json!({
+                "clicked": target,
+                "matches": matches.len(),
+                "button": params.button,
+                "double": params.double,
+            })),
+            Err(e) => ActionResult::failure(e),
         }
     }
 
-    async fn execute_screenshot(&self, params: &serde_json::Value) -> ActionResult {
-        // TODO: Implement screenshot with cursor reticle
-        let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-        let path = format!("screenshot_{}.png", timestamp);
-        
-        ActionResult {
-            success: true,
-            result: serde_json::json!({"path": path}),
-            error: None,
+    async fn find_image(&self, params: &FindImageParams) -> Result<Vec<TemplateMatch>> {
+        self.vision
+            .find_template(
+                &params.path,
+                params.monitor,
+                params.region,
+                params.threshold,
+                &params.scales,
+            )
+            .await
+    }
+
+    async fn execute_find_image(&self, params: &FindImageParams) -> ActionResult {
+        info!("Finding image {} on screen", params.path);
+        match self.find_image(params).await {
+            Ok(matches) => ActionResult::success(serde_json::json!({
+                "count": matches.len(),
+                "matches": matches,
+            })),
+            Err(e) => ActionResult::failure(e),
         }
     }
 
-    async fn execute_window_operation(&self, params: &serde_json::Value) -> ActionResult {
-        // TODO: Implement window operations
-        ActionResult {
-            success: true,
-            result: serde_json::Value::Null,
-            error: None,
+    async fn execute_click_image(&self, params: &ClickImageParams) -> ActionResult {
+        info!("Clicking image {}", params.image.path);
+        let matches = match self.find_image(&params.image).await {
+            Ok(matches) => matches,
+            Err(e) => return ActionResult::failure(e),
+        };
+        let Some(target) = matches.first() else {
+            return ActionResult::failure(format!(
+                "Image {} not found on screen (threshold {})",
+                params.image.path, params.image.threshold
+            ));
+        };
+        let (x, y) = target.center;
+        if let Some(refused) = self.refused_click(x, y).await {
+            return refused;
+        }
+        match InputController::click(x, y, params.button, params.double).await {
+            Ok(()) => ActionResult::success(serde_json::json!({
+                "clicked": target,
+                "matches": matches.len(),
+                "button": params.button,
+                "double": params.double,
+            })),
+            Err(e) => ActionResult::failure(e),
         }
     }
 
-    async fn execute_file_operation(&self, params: &serde_json::Value) -> ActionResult {
-        // TODO: Implement file operations
-        ActionResult {
-            success: true,
-            result: serde_json::Value::Null,
-            error: None,
+    async fn execute_screen_diff(&self, params: &ScreenDiffParams) -> ActionResult {
+        info!(
+            "Comparing {} with {}",
+            params.before,
+            params.after.as_deref().unwrap_or("the screen")
+        );
+        match self
+            .vision
+            .diff_screen(
+                &params.before,
+                params.after.as_deref(),
+                params.monitor,
+                params.region,
+            )
+            .await
+        {
+            Ok(changes) => {
+                ActionResult::success(serde_json::to_value(changes).unwrap_or_default())
+            }
+            Err(e) => ActionResult::failure(e),
         }
     }
 
-    async fn execute_process_operation(&self, params: &serde_json::Value) -> ActionResult {
-        // TODO: Implement process operations
-        ActionResult {
-            success: true,
-            result: serde_json::Value::Null,
-            error: None,
+    async fn execute_annotate_screen(&self, params: &AnnotateScreenParams) -> ActionResult {
+        info!("Annotating screenshot with {:?} marks", params.marks);
+        match self.vision.annotate(params).await {
+            Ok(annotation) => {
+                ActionResult::success(serde_json::to_value(&*annotation).unwrap_or_default())
+            }
+            Err(e) => ActionResult::failure(e),
         }
     }
 
-    async fn execute_system_operation(&self, params: &serde_json::Value) -> ActionResult {
-        // TODO: Implement system operations
-        ActionResult {
-            success: true,
-            result: serde_json::Value::Null,
-            error: None,
+    async fn execute_click_mark(&self, params: &ClickMarkParams) -> ActionResult {
+        info!("Clicking mark {}", params.mark);
+        let (annotation, (x, y)) = match self
+            .vision
+            .annotation(params.annotation.as_deref())
+            .and_then(|annotation| {
+                let point = annotation.resolve(&params.mark)?;
+                Ok((annotation, point))
+            }) {
+            Ok(resolved) => resolved,
+            Err(e) => return ActionResult::failure(e),
+        };
+        if let Some(refused) = self.refused_click(x, y).await {
+            return refused;
+        }
+        let mut focused = None;
+        if let Some(window) = annotation.window(&params.mark) {
+            let found = tokio::task::spawn_blocking(move || WindowSystem::at(x, y)).await;
+            let under_mark = match found {
+                Ok(Ok(Some(found))) if found.id == window => Some(found),
+                _ => None,
+            };
+            let plain = params.button == MouseButton::Left && !params.double;
+            // Windows moved since the annotation, so the centre may hit another
+            // one; focusing the window is then all a plain click can do
+            if under_mark.is_none() && !plain {
+                return ActionResult::failure(format!(
+                    "Window {} is no longer under mark {}, so it can't be clicked",
+                    window, params.mark
+                ));
+            }
+            if !under_mark.as_ref().is_some_and(|w| w.focused) {
+                let focus = WindowOperation::Focus {
+                    window: WindowSelector {
+                        id: Some(window),
+                        ..Default::default()
+                    },
+                    extra: NoExtraFields,
+                };
+                match WindowSystem::execute(focus).await {
+                    Ok(result) => focused = Some(result),
+                    Err(e) => return ActionResult::failure(e),
+                }
+            }
+            if under_mark.is_none() {
+                return ActionResult::success(serde_json::json!({
+                    "mark": params.mark,
+                    "annotation": annotation.id,
+                    "focused": focused,
+                }));
+            }
+        }
+        match InputController::click(x, y, params.button, params.double).await {
+            Ok(()) => ActionResult::success(serde_json::json!({
+                "mark": params.mark,
+                "annotation": annotation.id,
+                "x": x,
+                "y": y,
+                "button": params.button,
+                "double": params.double,
+                "focused": focused,
+            })),
+            Err(e) => ActionResult::failure(e),
         }
     }
 
-    pub fn get_available_actions(&self) -> Vec<String> {
-        vec![
-            "click".to_string(),
-            "type".to_string(),
-            "key".to_string(),
-            "screenshot".to_string(),
-            "window_operation".to_string(),
-            "file_operation".to_string(),
-            "process_operation".to_string(),
-            "system_operation".to_string(),
-        ]
+    async fn execute_a11y_tree(&self, params: &A11yTreeParams) -> ActionResult {
+        info!(
+            "Reading accessibility tree of {}",
+            params.app.as_deref().unwrap_or("all applications")
+        );
+        match self
+            .vision
+            .accessibility_tree(params.app.as_deref(), params.max_depth)
+            .await
+        {
+            Ok(applications) => ActionResult::success(serde_json::json!({
+                "count": applications.len(),
+                "applications": applications,
+            })),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_a11y_find(&self, params: &A11yFindParams) -> ActionResult {
+        info!("Finding accessible nodes matching {:?}", params.query);
+        match self.vision.find_accessible(&params.query, params.limit).await {
+            Ok(nodes) => ActionResult::success(serde_json::json!({
+                "count": nodes.len(),
+                "nodes": nodes,
+            })),
+            Err(e) => ActionResult::failure(e),
+        }
+    }
+
+    async fn execute_a11y_press(&self, params: &A11yPressParams) -> ActionResult {
+        let operation = A11yOperation::Press(params.action.clone());
+        self.execute_a11y(&params.target, operation).await
+    }
+
+    async fn execute_a11y_set_text(&self, params: &A11ySetTextParams) -> ActionResult {
+        let operation = A11yOperation::SetText(params.text.clone());
+        self.execute_a11y(&params.target, operation).await
+    }
+
+    async fn execute_a11y(&self, target: &A11yQuery, operation: A11yOperation) -> ActionResult {
+        match self.vision.act_accessible(target, operation).await {
+            Ok(node) => ActionResult::success(serde_json::json!({"node": node})),
+            Err(e) => ActionResult::failure(e),
+        }
     }
-}
 
+    /// Poll a condition; a failed assertion also captures a diagnostic screenshot
+    async fn execute_wait(&self, condition: Condition, assertion: bool) -> ActionResult {
+        info!("Waiting for {}", condition.describe());
+        let error = match Waiter::wait(&condition, assertion, &self.vision).await {
+            Ok(result) => return ActionResult::success(result),
+            Err(e) => e,
+        };
+        if !assertion {
+            return ActionResult::failure(error);
+        }
+
+        warn!("Assertion failed: {:#}", error);
+        let mut result = ActionResult::failure(&error);
+        result.result = match self.vision.capture_screen(&ScreenshotParams::default()).await {
+            Ok(screenshot) => serde_json::json!({
+                "assertion": condition.describe(),
+                "diagnostic_screenshot": screenshot,
+            }),
+            Err(e) => serde_json::json!({
+                "assertion": condition.describe(),
+                "diagnostic_screenshot": null,
+                "screenshot_error": format!("{:#}", e),
+            }),
+        };
+        result
+    }
+
+    /// Available action types together with the JSON Schema of their parameters
+    pub fn get_available_actions(&self) -> Vec<serde_json::Value> {
+        Action::catalog()
+    }
+}
@@ -0,0 +1,327 @@
+use crate::action::schema::{FileEncoding, FileOperation};
+use crate::core::paths::{expand_home, resolve_existing};
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde_json::{json, Value};
+use std::fs;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// File Operations - Executes `file_operation` actions against the local filesystem
+pub struct FileOperations;
+
+impl FileOperations {
+    pub async fn execute(operation: FileOperation) -> Result<Value> {
+        tokio::task::spawn_blocking(move || Self::run(operation)).await?
+    }
+
+    fn run(operation: FileOperation) -> Result<Value> {
+        match operation {
+            FileOperation::Read { path, encoding, offset, length } => {
+                Self::read(&path, encoding, offset, length)
+            }
+            FileOperation::Write { path, content, encoding, create_dirs } => {
+                Self::write(&path, &content, encoding, create_dirs, false)
+            }
+            FileOperation::Append { path, content, encoding } => {
+                Self::write(&path, &content, encoding, false, true)
+            }
+            FileOperation::Mkdir { path, parents } => {
+                let target = expand_home(&path);
+                if parents {
+                    fs::create_dir_all(&target)
+                } else {
+                    fs::create_dir(&target)
+                }
+                .with_context(|| format!("Failed to create directory {}", target.display()))?;
+                Ok(json!({"path": target}))
+            }
+            FileOperation::Copy { from, to, overwrite } => Self::copy(&from, &to, overwrite),
+            FileOperation::Move { from, to, overwrite } => Self::rename(&from, &to, overwrite),
+            FileOperation::Delete { path, recursive } => Self::delete(&path, recursive),
+            FileOperation::Stat { path } => {
+                let target = expand_home(&path);
+                Self::stat(&target)
+            }
+            FileOperation::List { path, include_hidden } => Self::list(&path, include_hidden),
+            FileOperation::Glob { pattern, limit } => Self::glob(&pattern, limit),
+        }
+    }
+
+    fn read(
+        path: &str,
+        encoding: FileEncoding,
+        offset: Option<u64>,
+        length: Option<u64>,
+    ) -> Result<Value> {
+        let target = expand_home(path);
+        let mut file = fs::File::open(&target)
+            .with_context(|| format!("Failed to open {}", target.display()))?;
+        let total = file.metadata()?.len();
+
+        let offset = offset.unwrap_or(0);
+        if offset > 0 {
+            file.seek(SeekFrom::Start(offset))?;
+        }
+        let mut bytes = Vec::new();
+        match length {
+            Some(length) => file.take(length).read_to_end(&mut bytes)?,
+            None => file.read_to_end(&mut bytes)?,
+        };
+
+        let bytes_read = bytes.len();
+        let content = match encoding {
+            FileEncoding::Text => String::from_utf8(bytes).with_context(|| {
+                format!("{} is not valid UTF-8; read it with encoding \"base64\"", target.display())
+            })?,
+            FileEncoding::Base64 => BASE64.encode(&bytes),
+        };
+
+        Ok(json!({
+            "path": target,
+            "content": content,
+            "encoding": encoding,
+            "offset": offset,
+            "bytes_read": bytes_read,
+            "total_size": total,
+        }))
+    }
+
+    fn write(
+        path: &str,
+        content: &str,
+        encoding: FileEncoding,
+        create_dirs: bool,
+        append: bool,
+    ) -> Result<Value> {
+        let target = expand_home(path);
+        let bytes = match encoding {
+            FileEncoding::Text => content.as_bytes().to_vec(),
+            FileEncoding::Base64 => BASE64.decode(content).context("Invalid base64 content")?,
+        };
+
+        if create_dirs {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append)
+            .open(&target)
+            .with_context(|| format!("Failed to open {} for writing", target.display()))?;
+        file.write_all(&bytes)?;
+
+        Ok(json!({
+            "path": target,
+            "bytes_written": bytes.len(),
+            "appended": append,
+        }))
+    }
+
+    fn copy(from: &str, to: &str, overwrite: bool) -> Result<Value> {
+        let (source, dest) = (expand_home(from), expand_home(to));
+        if dest.exists() && !overwrite {
+            bail!("{} already exists; set overwrite to replace it", dest.display());
+        }
+        let bytes = copy_recursive(&source, &dest)
+            .with_context(|| format!("Failed to copy {} to {}", source.display(), dest.display()))?;
+        Ok(json!({"from": source, "to": dest, "bytes_copied": bytes}))
+    }
+
+    fn rename(from: &str, to: &str, overwrite: bool) -> Result<Value> {
+        let (source, dest) = (expand_home(from), expand_home(to));
+        if !source.exists() {
+            bail!("{} does not exist", source.display());
+        }
+        if dest.exists() && !overwrite {
+            bail!("{} already exists; set overwrite to replace it", dest.display());
+        }
+
+        move_path(&source, &dest)
+            .with_context(|| format!("Failed to move {} to {}", source.display(), dest.display()))?;
+        Ok(json!({"from": source, "to": dest}))
+    }
+
+    fn delete(path: &str, recursive: bool) -> Result<Value> {
+        let target = expand_home(path);
+        remove_path(&target, recursive)
+            .with_context(|| format!("Failed to delete {}", target.display()))?;
+        Ok(json!({"path": target, "deleted": true}))
+    }
+
+    pub(crate) fn stat(path: &Path) -> Result<Value> {
+        let meta = fs::symlink_metadata(path)
+            .with_context(|| format!("Failed to stat {}", path.display()))?;
+        let timestamp = |time: std::io::Result<std::time::SystemTime>| {
+            time.ok()
+                .map(|t| chrono::DateTime::<chrono::Utc>::from(t).to_rfc3339())
+        };
+
+        let mut info = json!({
+            "path": path,
+            "type": file_type(&meta),
+            "size": meta.len(),
+            "readonly": meta.permissions().readonly(),
+            "modified": timestamp(meta.modified()),
+            "accessed": timestamp(meta.accessed()),
+            "created": timestamp(meta.created()),
+        });
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            info["mode"] = json!(format!("{:o}", meta.mode() & 0o7777));
+            info["uid"] = json!(meta.uid());
+            info["gid"] = json!(meta.gid());
+        }
+        Ok(info)
+    }
+
+    fn list(path: &str, include_hidden: bool) -> Result<Value> {
+        let target = expand_home(path);
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(&target)
+            .with_context(|| format!("Failed to list {}", target.display()))?
+        {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !include_hidden && name.starts_with('.') {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            entries.push(json!({
+                "name": name,
+                "path": entry.path(),
+                "type": file_type(&meta),
+                "size": meta.len(),
+            }));
+        }
+        entries.sort_by(|a, b| a["name"].as_str().cmp(&b["name"].as_str()));
+
+        Ok(json!({"path": target, "count": entries.len(), "entries": entries}))
+    }
+
+    fn glob(pattern: &str, limit: Option<usize>) -> Result<Value> {
+        let pattern = expand_home(pattern);
+        let pattern = pattern.to_string_lossy();
+        let mut matches = Vec::new();
+        let mut truncated = false;
+        for entry in glob::glob(&pattern).with_context(|| format!("Invalid glob pattern {}", pattern))? {
+            if limit.is_some_and(|limit| matches.len() >= limit) {
+                truncated = true;
+                break;
+            }
+            // Unreadable directories are skipped rather than failing the whole search
+            if let Ok(path) = entry {
+                matches.push(path);
+            }
+        }
+
+        Ok(json!({
+            "pattern": pattern,
+            "count": matches.len(),
+            "matches": matches,
+            "truncated": truncated,
+        }))
+    }
+}
+
+fn file_type(meta: &fs::Metadata) -> &'static str {
+    let file_type = meta.file_type();
+    if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_file() {
+        "file"
+    } else {
+        "other"
+    }
+}
+
+/// Copy a file, or a directory tree, returning the number of bytes copied
+///
+/// Symbolic links are copied as links instead of being followed, and a
+/// directory cannot be copied into itself.
+pub(crate) fn copy_recursive(source: &Path, dest: &Path) -> Result<u64> {
+    let is_dir = fs::symlink_metadata(source)?.is_dir();
+    if is_dir && resolve_existing(dest).starts_with(resolve_existing(source)) {
+        bail!("Cannot copy {} into itself at {}", source.display(), dest.display());
+    }
+    copy_tree(source, dest)
+}
+
+fn copy_tree(source: &Path, dest: &Path) -> Result<u64> {
+    let meta = fs::symlink_metadata(source)?;
+    if meta.file_type().is_symlink() {
+        copy_link(source, dest)?;
+        return Ok(0);
+    }
+    if !meta.is_dir() {
+        return Ok(fs::copy(source, dest)?);
+    }
+
+    fs::create_dir_all(dest)?;
+    let mut total = 0;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        total += copy_tree(&entry.path(), &dest.join(entry.file_name()))?;
+    }
+    Ok(total)
+}
+
+#[cfg(unix)]
+fn copy_link(source: &Path, dest: &Path) -> Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(source)?, dest)?;
+    Ok(())
+}
+
+#[cfg(windows)]
+fn copy_link(source: &Path, dest: &Path) -> Result<()> {
+    let target = fs::read_link(source)?;
+    if fs::metadata(source).is_ok_and(|meta| meta.is_dir()) {
+        std::os::windows::fs::symlink_dir(target, dest)?;
+    } else {
+        std::os::windows::fs::symlink_file(target, dest)?;
+    }
+    Ok(())
+}
+
+/// Move a file or directory tree, copying it when it has to cross filesystems
+///
+/// As with a plain rename, a file or an empty directory of the same kind at
+/// `dest` is replaced; a non-empty directory is an error rather than being
+/// merged into.
+pub(crate) fn move_path(source: &Path, dest: &Path) -> Result<()> {
+    match fs::rename(source, dest) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            if let Ok(existing) = fs::symlink_metadata(dest) {
+                if existing.is_dir() != fs::symlink_metadata(source)?.is_dir() {
+                    bail!("{} and {} are not both directories", source.display(), dest.display());
+                }
+                remove_path(dest, false)?;
+            }
+            copy_recursive(source, dest)?;
+            remove_path(source, true)
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub(crate) fn remove_path(path: &Path, recursive: bool) -> Result<()> {
+    let meta = fs::symlink_metadata(path)?;
+    if meta.is_dir() {
+        if recursive {
+            fs::remove_dir_all(path)?;
+        } else {
+            fs::remove_dir(path)?;
+        }
+    } else {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
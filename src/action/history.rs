@@ -0,0 +1,221 @@
+use crate::action::engine::ActionResult;
+use crate::action::schema::{redact, Action, FileOperation};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// One executed action
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub duration_ms: u64,
+    /// Who asked for the action: `api`, `task_planner`, ...
+    pub caller: String,
+    pub action: Action,
+    pub result: ActionResult,
+}
+
+impl HistoryEntry {
+    /// The entry as written to the journal, without typed text, file or
+    /// clipboard contents, in the action or in what it read
+    fn redacted(&self) -> HistoryEntry {
+        let mut entry = self.clone();
+        entry.action = self.action.redacted();
+        if matches!(
+            self.action,
+            Action::FileOp(FileOperation::Read { .. }) | Action::ClipboardGet(_)
+        ) {
+            if let Some(result) = entry.result.result.as_object_mut() {
+                for key in ["content", "text", "data"] {
+                    if let Some(Value::String(value)) = result.get_mut(key) {
+                        redact(value);
+                    }
+                }
+            }
+        }
+        entry
+    }
+}
+
+/// Filter and page over the history, newest entries first
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    pub action_type: Option<String>,
+    pub caller: Option<String>,
+    pub success: Option<bool>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+const DEFAULT_PAGE_SIZE: usize = 50;
+const MAX_PAGE_SIZE: usize = 1000;
+
+impl HistoryQuery {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.action_type.as_deref().is_none_or(|t| t == entry.action.name())
+            && self.caller.as_deref().is_none_or(|c| c == entry.caller)
+            && self.success.is_none_or(|s| s == entry.result.success)
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+            && self.until.is_none_or(|until| entry.timestamp <= until)
+    }
+}
+
+struct Inner {
+    entries: VecDeque<HistoryEntry>,
+    /// Lines in the journal file, including ones already evicted from memory
+    journal_lines: usize,
+}
+
+/// Bounded action history, journaled to a JSON-lines file
+///
+/// Entries are appended to the journal as they are recorded; once the file
+/// holds twice as many lines as the history keeps, it is rewritten from the
+/// in-memory entries. The journal gets redacted copies (see
+/// [`Action::redacted`]); only the in-memory entries, which macro recording
+/// replays from, keep the full actions.
+pub struct ActionHistory {
+    inner: Mutex<Inner>,
+    max_entries: usize,
+    path: Option<PathBuf>,
+}
+
+impl ActionHistory {
+    /// History kept in memory only
+    pub fn in_memory(max_entries: usize) -> Self {
+        Self {
+            inner: Mutex::new(Inner {
+                entries: VecDeque::new(),
+                journal_lines: 0,
+            }),
+            max_entries,
+            path: None,
+        }
+    }
+
+    /// History persisted at `path`, loading whatever is already there
+    pub fn open(path: PathBuf, max_entries: usize) -> Result<Self> {
+        let mut entries = VecDeque::new();
+        let mut journal_lines = 0;
+        if path.exists() {
+            let file = fs::File::open(&path)
+                .with_context(|| format!("Failed to open {}", path.display()))?;
+            let mut skipped = 0;
+            for line in BufReader::new(file).lines() {
+                journal_lines += 1;
+                match serde_json::from_str::<HistoryEntry>(&line?) {
+                    Ok(entry) => {
+                        entries.push_back(entry);
+                        if entries.len() > max_entries {
+                            entries.pop_front();
+                        }
+                    }
+                    Err(_) => skipped += 1,
+                }
+            }
+            if skipped > 0 {
+                warn!("Skipped {} unreadable entries in {}", skipped, path.display());
+            }
+        }
+        info!("Loaded {} history entries from {:?}", entries.len(), path);
+
+        Ok(Self {
+            inner: Mutex::new(Inner {
+                entries,
+                journal_lines,
+            }),
+            max_entries,
+            path: Some(path),
+        })
+    }
+
+    pub fn record(&self, entry: HistoryEntry) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        if let Some(path) = &self.path {
+            if let Err(e) = append(path, &entry) {
+                warn!("Failed to persist action history: {:#}", e);
+            } else {
+                inner.journal_lines += 1;
+            }
+        }
+
+        inner.entries.push_back(entry);
+        while inner.entries.len() > self.max_entries {
+            inner.entries.pop_front();
+        }
+
+        if let Some(path) = &self.path {
+            if inner.journal_lines > self.max_entries.max(1) * 2 {
+                match rewrite(path, &inner.entries) {
+                    Ok(()) => inner.journal_lines = inner.entries.len(),
+                    Err(e) => warn!("Failed to compact action history: {:#}", e),
+                }
+            }
+        }
+    }
+
+    /// Entries matching `query`, newest first, with the total match count
+    pub fn query(&self, query: &HistoryQuery) -> (usize, Vec<HistoryEntry>) {
+        let Ok(inner) = self.inner.lock() else {
+            return (0, Vec::new());
+        };
+        let limit = query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+        let matching = inner.entries.iter().rev().filter(|e| query.matches(e));
+        let total = matching.clone().count();
+        let page = matching.skip(query.offset).take(limit).cloned().collect();
+        (total, page)
+    }
+
+    pub fn get(&self, id: &str) -> Option<HistoryEntry> {
+        let inner = self.inner.lock().ok()?;
+        inner.entries.iter().rev().find(|e| e.id == id).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().map(|inner| inner.entries.len()).unwrap_or(0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn append(path: &Path, entry: &HistoryEntry) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut line = serde_json::to_vec(&entry.redacted())?;
+    line.push(b'\n');
+    file.write_all(&line)?;
+    Ok(())
+}
+
+fn rewrite(path: &Path, entries: &VecDeque<HistoryEntry>) -> Result<()> {
+    let tmp = path.with_extension("jsonl.tmp");
+    {
+        let mut file = fs::File::create(&tmp)
+            .with_context(|| format!("Failed to create {}", tmp.display()))?;
+        for entry in entries {
+            let mut line = serde_json::to_vec(&entry.redacted())?;
+            line.push(b'\n');
+            file.write_all(&line)?;
+        }
+    }
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
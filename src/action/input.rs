@@ -0,0 +1,159 @@
+use crate::action::schema::MouseButton;
+use anyhow::{anyhow, bail, Result};
+use enigo::{Button, Coordinate, Direction, Enigo, Key, Keyboard, Mouse, Settings};
+
+/// Mouse and keyboard injection
+///
+/// Backed by enigo, which uses XTest on Linux/X11. Each call opens its own
+/// connection on a blocking thread, so a missing display surfaces as an error
+/// instead of a silent no-op.
+pub struct InputController;
+
+impl InputController {
+    pub async fn move_pointer(x: i32, y: i32) -> Result<()> {
+        blocking(move |enigo| {
+            enigo.move_mouse(x, y, Coordinate::Abs)?;
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn click(x: i32, y: i32, button: MouseButton, double: bool) -> Result<()> {
+        blocking(move |enigo| {
+            enigo.move_mouse(x, y, Coordinate::Abs)?;
+            let button = match button {
+                MouseButton::Left => Button::Left,
+                MouseButton::Right => Button::Right,
+                MouseButton::Middle => Button::Middle,
+            };
+            enigo.button(button, Direction::Click)?;
+            if double {
+                enigo.button(button, Direction::Click)?;
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    pub async fn type_text(text: String) -> Result<()> {
+        blocking(move |enigo| {
+            enigo.text(&text)?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Press a key chord such as `ctrl+shift+t`
+    ///
+    /// Every key but the last is held as a modifier while the last one is
+    /// clicked, then the modifiers are released in reverse order. Modifiers
+    /// that were pressed are released even when a later key fails, so none
+    /// stays held on the display.
+    pub async fn press_chord(chord: &str) -> Result<()> {
+        let keys = parse_chord(chord)?;
+        blocking(move |enigo| {
+            let (last, modifiers) = keys.split_last().expect("parse_chord never returns empty");
+            let mut pressed = Vec::new();
+            let mut result = modifiers
+                .iter()
+                .try_for_each(|key| {
+                    enigo.key(*key, Direction::Press)?;
+                    pressed.push(*key);
+                    Ok(())
+                })
+                .and_then(|()| enigo.key(*last, Direction::Click));
+            for key in pressed.iter().rev() {
+                // Keep releasing the rest; the first error is the one reported
+                if let Err(e) = enigo.key(*key, Direction::Release) {
+                    result = result.and(Err(e));
+                }
+            }
+            Ok(result?)
+        })
+        .await
+    }
+}
+
+async fn blocking<F>(f: F) -> Result<()>
+where
+    F: FnOnce(&mut Enigo) -> Result<()> + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let mut enigo = Enigo::new(&Settings::default())
+            .map_err(|e| anyhow!("No display available for input injection: {}", e))?;
+        f(&mut enigo)
+    })
+    .await?
+}
+
+/// Parse a `+`-separated key chord into enigo keys
+pub fn parse_chord(chord: &str) -> Result<Vec<Key>> {
+    // A trailing "++" (or a lone "+") means the plus key itself
+    let (head, plus) = match chord.strip_suffix("++") {
+        Some(head) => (head, true),
+        None if chord == "+" => ("", true),
+        None => (chord, false),
+    };
+
+    let mut keys = Vec::new();
+    if !head.is_empty() {
+        for part in head.split('+') {
+            keys.push(parse_key(part)?);
+        }
+    }
+    if plus {
+        keys.push(Key::Unicode('+'));
+    }
+    if keys.is_empty() {
+        bail!("Empty key chord");
+    }
+    Ok(keys)
+}
+
+fn parse_key(name: &str) -> Result<Key> {
+    let name = name.trim();
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Unicode(c));
+    }
+
+    let key = match name.to_lowercase().as_str() {
+        "ctrl" | "control" => Key::Control,
+        "shift" => Key::Shift,
+        "alt" => Key::Alt,
+        "super" | "meta" | "win" | "cmd" | "command" => Key::Meta,
+        "enter" | "return" => Key::Return,
+        "tab" => Key::Tab,
+        "esc" | "escape" => Key::Escape,
+        "space" => Key::Space,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        #[cfg(not(target_os = "macos"))]
+        "insert" | "ins" => Key::Insert,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "pageup" | "pgup" => Key::PageUp,
+        "pagedown" | "pgdn" => Key::PageDown,
+        "up" => Key::UpArrow,
+        "down" => Key::DownArrow,
+        "left" => Key::LeftArrow,
+        "right" => Key::RightArrow,
+        "capslock" => Key::CapsLock,
+        "plus" => Key::Unicode('+'),
+        "f1" => Key::F1,
+        "f2" => Key::F2,
+        "f3" => Key::F3,
+        "f4" => Key::F4,
+        "f5" => Key::F5,
+        "f6" => Key::F6,
+        "f7" => Key::F7,
+        "f8" => Key::F8,
+        "f9" => Key::F9,
+        "f10" => Key::F10,
+        "f11" => Key::F11,
+        "f12" => Key::F12,
+        "" => bail!("Empty key in chord"),
+        other => bail!("Unknown key: {}", other),
+    };
+    Ok(key)
+}
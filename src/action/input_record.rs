@@ -0,0 +1,448 @@
+use crate::action::schema::{Action, ClickParams, KeyParams, MouseButton, TypeParams};
+use anyhow::{bail, Result};
+
+/// An action reconstructed from user input, `offset_ms` after recording started
+#[derive(Debug, Clone)]
+pub struct RecordedInput {
+    pub offset_ms: u64,
+    pub action: Action,
+}
+
+/// Input Recorder - Captures real keyboard and mouse input as actions
+///
+/// Uses the X11 RECORD extension, which sees input for every client without
+/// grabbing it. Clicks keep their screen coordinates, consecutive printable
+/// keys become one `type` action, and anything pressed with ctrl/alt/super
+/// (or a non-printable key) becomes a `key` chord. Actions have no way to
+/// hold a button down, so a recording containing a drag is rejected rather
+/// than saved as a click that would do something else on replay.
+pub struct InputRecorder {
+    inner: platform::Recorder,
+}
+
+impl InputRecorder {
+    pub fn start() -> Result<Self> {
+        Ok(Self {
+            inner: platform::Recorder::start()?,
+        })
+    }
+
+    /// Stop capturing and return what was recorded, oldest first
+    pub fn stop(self) -> Result<Vec<RecordedInput>> {
+        self.inner.stop()
+    }
+}
+
+/// Two presses of the same button closer than this (in time and pixels) are a double click
+const DOUBLE_CLICK_MS: u32 = 400;
+const DOUBLE_CLICK_DISTANCE: i32 = 4;
+/// A button released further than this from where it was pressed was dragged
+const DRAG_DISTANCE: i32 = 4;
+
+const SHIFT_MASK: u16 = 0x01;
+const LOCK_MASK: u16 = 0x02;
+const CONTROL_MASK: u16 = 0x04;
+const ALT_MASK: u16 = 0x08;
+const SUPER_MASK: u16 = 0x40;
+
+/// Turns raw key and button presses into actions
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+struct Translator {
+    /// Server time of the first event; offsets are relative to it
+    origin: Option<u32>,
+    inputs: Vec<RecordedInput>,
+    /// Text typed since the last non-text input, with the time it started
+    typed: Option<(u64, String)>,
+    /// Time, position and button of the last click, while it may still become a double click
+    last_click: Option<(u32, i32, i32, MouseButton)>,
+    /// Button held down, with where and when it was pressed
+    held: Option<(MouseButton, i32, i32, u64)>,
+    /// The first drag seen, described for the error
+    drag: Option<String>,
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+impl Translator {
+    fn new() -> Self {
+        Self {
+            origin: None,
+            inputs: Vec::new(),
+            typed: None,
+            last_click: None,
+            held: None,
+            drag: None,
+        }
+    }
+
+    fn offset(&mut self, time: u32) -> u64 {
+        let origin = *self.origin.get_or_insert(time);
+        u64::from(time.wrapping_sub(origin))
+    }
+
+    /// A key press; `base` is the unshifted keysym, `shifted` the one for the current modifiers
+    fn key(&mut self, time: u32, base: u32, shifted: u32, state: u16) {
+        if is_modifier(base) {
+            return;
+        }
+        let offset = self.offset(time);
+        self.last_click = None;
+        let chord_modifiers = state & (CONTROL_MASK | ALT_MASK | SUPER_MASK) != 0;
+
+        if !chord_modifiers {
+            if let Some(c) = keysym_char(shifted) {
+                let c = if state & LOCK_MASK != 0 && c.is_alphabetic() {
+                    swap_case(c)
+                } else {
+                    c
+                };
+                self.typed
+                    .get_or_insert_with(|| (offset, String::new()))
+                    .1
+                    .push(c);
+                return;
+            }
+        }
+
+        let Some(name) = key_name(base) else {
+            return;
+        };
+        let mut chord = String::new();
+        for (mask, modifier) in [
+            (CONTROL_MASK, "ctrl+"),
+            (ALT_MASK, "alt+"),
+            (SUPER_MASK, "super+"),
+            (SHIFT_MASK, "shift+"),
+        ] {
+            if state & mask != 0 {
+                chord.push_str(modifier);
+            }
+        }
+        chord.push_str(&name);
+        self.flush_text();
+        self.push(offset, Action::Key(KeyParams { key: chord }));
+    }
+
+    fn button(&mut self, time: u32, button: u8, x: i32, y: i32) {
+        let Some(button) = mouse_button(button) else {
+            return;
+        };
+        let offset = self.offset(time);
+        self.flush_text();
+        self.held = Some((button, x, y, offset));
+
+        if let Some((last_time, last_x, last_y, last_button)) = self.last_click.take() {
+            let close = (x - last_x).abs() <= DOUBLE_CLICK_DISTANCE
+                && (y - last_y).abs() <= DOUBLE_CLICK_DISTANCE;
+            if last_button == button && close && time.wrapping_sub(last_time) <= DOUBLE_CLICK_MS {
+                if let Some(Action::Click(params)) = self.inputs.last_mut().map(|i| &mut i.action) {
+                    params.double = true;
+                    return;
+                }
+            }
+        }
+        self.last_click = Some((time, x, y, button));
+        self.push(
+            offset,
+            Action::Click(ClickParams {
+                x,
+                y,
+                button,
+                double: false,
+            }),
+        );
+    }
+
+    fn release(&mut self, button: u8, x: i32, y: i32) {
+        let Some(button) = mouse_button(button) else {
+            return;
+        };
+        let Some((held, from_x, from_y, offset)) = self.held.filter(|h| h.0 == button) else {
+            return;
+        };
+        self.held = None;
+        let moved = (x - from_x).abs() > DRAG_DISTANCE || (y - from_y).abs() > DRAG_DISTANCE;
+        if moved && self.drag.is_none() {
+            self.drag = Some(format!(
+                "{:?} button dragged from ({}, {}) to ({}, {}) {} ms in",
+                held, from_x, from_y, x, y, offset
+            ));
+        }
+    }
+
+    fn flush_text(&mut self) {
+        if let Some((offset, text)) = self.typed.take() {
+            self.push(offset, Action::Type(TypeParams { text }));
+        }
+    }
+
+    fn push(&mut self, offset_ms: u64, action: Action) {
+        self.inputs.push(RecordedInput { offset_ms, action });
+    }
+
+    fn finish(mut self) -> Result<Vec<RecordedInput>> {
+        if let Some(drag) = self.drag {
+            bail!(
+                "The recording contains a drag ({}), which would replay as a click; \
+                 record it again without dragging",
+                drag
+            );
+        }
+        self.flush_text();
+        Ok(self.inputs)
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn mouse_button(button: u8) -> Option<MouseButton> {
+    match button {
+        1 => Some(MouseButton::Left),
+        2 => Some(MouseButton::Middle),
+        3 => Some(MouseButton::Right),
+        // 4-7 are scroll wheel steps
+        _ => None,
+    }
+}
+
+/// Shift, control, caps lock, meta, alt, super, hyper and the ISO level shifts
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn is_modifier(keysym: u32) -> bool {
+    matches!(keysym, 0xffe1..=0xffee | 0xfe01..=0xfe13)
+}
+
+/// The character a keysym types, for Latin-1 and Unicode keysyms
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn keysym_char(keysym: u32) -> Option<char> {
+    match keysym {
+        0x20..=0x7e | 0xa0..=0xff => char::from_u32(keysym),
+        0x0100_0000..=0x0110_ffff => char::from_u32(keysym - 0x0100_0000),
+        _ => None,
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn swap_case(c: char) -> char {
+    if c.is_uppercase() {
+        c.to_lowercase().next().unwrap_or(c)
+    } else {
+        c.to_uppercase().next().unwrap_or(c)
+    }
+}
+
+/// Key name as understood by `key` chords
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn key_name(keysym: u32) -> Option<String> {
+    let name = match keysym {
+        0xff0d | 0xff8d => "enter",
+        0xff09 => "tab",
+        0xff1b => "esc",
+        0xff08 => "backspace",
+        0xffff => "delete",
+        0xff63 => "insert",
+        0xff50 => "home",
+        0xff57 => "end",
+        0xff55 => "pageup",
+        0xff56 => "pagedown",
+        0xff51 => "left",
+        0xff52 => "up",
+        0xff53 => "right",
+        0xff54 => "down",
+        0x20 => "space",
+        0x2b => "plus",
+        0xffbe..=0xffc9 => return Some(format!("f{}", keysym - 0xffbe + 1)),
+        _ => return keysym_char(keysym).map(|c| c.to_lowercase().to_string()),
+    };
+    Some(name.to_string())
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{RecordedInput, Translator, SHIFT_MASK};
+    use anyhow::{anyhow, bail, Result};
+    use std::thread::JoinHandle;
+    use x11rb::connection::{Connection, RequestConnection};
+    use x11rb::protocol::record::{self, ConnectionExt as _};
+    use x11rb::protocol::xproto::{self, ConnectionExt as _};
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::wrapper::ConnectionExt as _;
+    use x11rb::x11_utils::TryParse;
+
+    /// Reply categories, see the RECORD protocol specification
+    const FROM_SERVER: u8 = 0;
+
+    /// Keysyms per keycode, as returned by GetKeyboardMapping
+    struct Keymap {
+        min_keycode: u8,
+        per_keycode: usize,
+        keysyms: Vec<u32>,
+    }
+
+    impl Keymap {
+        fn load(conn: &RustConnection) -> Result<Self> {
+            let setup = conn.setup();
+            let (min, max) = (setup.min_keycode, setup.max_keycode);
+            let reply = conn.get_keyboard_mapping(min, max - min + 1)?.reply()?;
+            Ok(Self {
+                min_keycode: min,
+                per_keycode: usize::from(reply.keysyms_per_keycode),
+                keysyms: reply.keysyms,
+            })
+        }
+
+        fn keysym(&self, keycode: u8, column: usize) -> u32 {
+            let Some(index) = keycode.checked_sub(self.min_keycode) else {
+                return 0;
+            };
+            if column >= self.per_keycode {
+                return 0;
+            }
+            self.keysyms
+                .get(usize::from(index) * self.per_keycode + column)
+                .copied()
+                .unwrap_or(0)
+        }
+
+        /// Unshifted and shifted keysyms for a key press
+        fn lookup(&self, keycode: u8, state: u16) -> (u32, u32) {
+            let base = self.keysym(keycode, 0);
+            let shifted = if state & SHIFT_MASK != 0 {
+                match self.keysym(keycode, 1) {
+                    0 => base,
+                    keysym => keysym,
+                }
+            } else {
+                base
+            };
+            (base, shifted)
+        }
+    }
+
+    pub struct Recorder {
+        ctrl: RustConnection,
+        context: record::Context,
+        worker: JoinHandle<Result<Vec<RecordedInput>>>,
+    }
+
+    impl Recorder {
+        pub fn start() -> Result<Self> {
+            // RECORD wants one connection for control requests and another to stream data
+            let (ctrl, _) = x11rb::connect(None)
+                .map_err(|e| anyhow!("No display available for input recording: {}", e))?;
+            let (data, _) = x11rb::connect(None)?;
+            if ctrl
+                .extension_information(record::X11_EXTENSION_NAME)?
+                .is_none()
+            {
+                bail!("The X server does not support the RECORD extension");
+            }
+            let keymap = Keymap::load(&ctrl)?;
+
+            let context = ctrl.generate_id()?;
+            let empty = record::Range8 { first: 0, last: 0 };
+            let empty_ext = record::ExtRange {
+                major: empty,
+                minor: record::Range16 { first: 0, last: 0 },
+            };
+            let range = record::Range {
+                core_requests: empty,
+                core_replies: empty,
+                ext_requests: empty_ext,
+                ext_replies: empty_ext,
+                delivered_events: empty,
+                device_events: record::Range8 {
+                    first: xproto::KEY_PRESS_EVENT,
+                    last: xproto::BUTTON_RELEASE_EVENT,
+                },
+                errors: empty,
+                client_started: false,
+                client_died: false,
+            };
+            ctrl.record_create_context(context, 0, &[record::CS::ALL_CLIENTS.into()], &[range])?
+                .check()?;
+
+            let worker = std::thread::spawn(move || {
+                let mut translator = Translator::new();
+                for reply in data.record_enable_context(context)? {
+                    let reply = reply?;
+                    if reply.category != FROM_SERVER || reply.client_swapped {
+                        continue;
+                    }
+                    let mut remaining = &reply.data[..];
+                    while !remaining.is_empty() {
+                        remaining = translate(remaining, &keymap, &mut translator)?;
+                    }
+                }
+                translator.finish()
+            });
+            Ok(Self {
+                ctrl,
+                context,
+                worker,
+            })
+        }
+
+        pub fn stop(self) -> Result<Vec<RecordedInput>> {
+            // Disabling the context ends the data stream, which ends the worker
+            self.ctrl.record_disable_context(self.context)?;
+            self.ctrl.sync()?;
+            let inputs = self
+                .worker
+                .join()
+                .map_err(|_| anyhow!("Input recording thread panicked"))?;
+            let _ = self.ctrl.record_free_context(self.context);
+            let _ = self.ctrl.flush();
+            inputs
+        }
+    }
+
+    /// Feed one recorded event to the translator and return the data after it
+    fn translate<'a>(
+        data: &'a [u8],
+        keymap: &Keymap,
+        translator: &mut Translator,
+    ) -> Result<&'a [u8]> {
+        match data[0] {
+            xproto::KEY_PRESS_EVENT => {
+                let (event, remaining) = xproto::KeyPressEvent::try_parse(data)?;
+                let state = u16::from(event.state);
+                let (base, shifted) = keymap.lookup(event.detail, state);
+                translator.key(event.time, base, shifted, state);
+                Ok(remaining)
+            }
+            xproto::BUTTON_PRESS_EVENT => {
+                let (event, remaining) = xproto::ButtonPressEvent::try_parse(data)?;
+                translator.button(
+                    event.time,
+                    event.detail,
+                    i32::from(event.root_x),
+                    i32::from(event.root_y),
+                );
+                Ok(remaining)
+            }
+            xproto::BUTTON_RELEASE_EVENT => {
+                let (event, remaining) = xproto::ButtonReleaseEvent::try_parse(data)?;
+                translator.release(event.detail, i32::from(event.root_x), i32::from(event.root_y));
+                Ok(remaining)
+            }
+            // Key releases and anything else we did not ask for are fixed-size events
+            _ => Ok(data.get(32..).unwrap_or_default()),
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::RecordedInput;
+    use anyhow::{bail, Result};
+
+    pub struct Recorder;
+
+    impl Recorder {
+        pub fn start() -> Result<Self> {
+            bail!("Input recording is only supported on X11")
+        }
+
+        pub fn stop(self) -> Result<Vec<RecordedInput>> {
+            Ok(Vec::new())
+        }
+    }
+}
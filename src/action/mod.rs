@@ -1,4 +1,24 @@
+pub mod audit;
 pub mod engine;
+pub mod file_ops;
+pub mod history;
+pub mod input;
+pub mod input_record;
+pub mod policy;
+pub mod process_ops;
+pub mod schema;
+pub mod simulate;
+pub mod system_ops;
+pub mod undo;
+pub mod wait;
 
-pub use engine::{ActionEngine, Action, ActionResult};
-
+pub use audit::{AuditEntry, AuditLog};
+pub use engine::{ActionEngine, ActionResult};
+pub use history::{ActionHistory, HistoryEntry, HistoryQuery};
+pub use input_record::{InputRecorder, RecordedInput};
+pub use policy::{PendingApproval, PolicyEngine, Verdict};
+pub use process_ops::{ExitInfo, ProcessManager};
+pub use schema::Action;
+pub use system_ops::{LifecycleRequest, SystemOperations};
+pub use undo::{UndoLog, UndoRecord};
+pub use wait::{Condition, Waiter};
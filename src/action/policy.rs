@@ -0,0 +1,1012 @@
+use crate::action::schema::{
+    A11yPressParams, A11yQuery, A11ySetTextParams, Action, ClickImageParams, ClipboardGetParams,
+    ClipboardSetParams, FileOperation, ProcessOperation, SystemOperation, WindowOperation,
+};
+use crate::core::config::{PolicyConfig, PolicyRuleKind, PolicyVerdict, SystemConfig, WatchSpec};
+use crate::core::paths::{expand_home, resolve_existing};
+use crate::state::{WindowInfo, WindowSystem};
+use crate::vision::accessibility;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::sync::oneshot;
+use tracing::{info, warn};
+
+/// Name reported when an action is refused because kernel ops are disabled
+pub const KERNEL_OPS_RULE: &str = "kernel_ops";
+
+/// Paths that only kernel-level operations should write to
+const KERNEL_PATHS: &[&str] = &["/proc", "/sys", "/dev", "/boot"];
+const KERNEL_COMMANDS: &[&str] = &[
+    "modprobe", "insmod", "rmmod", "sysctl", "kexec", "mount", "umount", "swapon", "swapoff",
+];
+/// System operations that write to sysfs or stop the service
+const KERNEL_SYSTEM_OPERATIONS: &[&str] = &["set_brightness", "shutdown", "restart"];
+const SHELLS: &[&str] = &["sh", "bash", "dash", "zsh", "ksh", "fish"];
+/// Programs that run their arguments as a command: the options among them
+/// that take a value, and how many operands come before the command
+const WRAPPERS: &[(&str, &[&str], usize)] = &[
+    ("env", &["-u", "--unset", "-C", "--chdir"], 0),
+    (
+        "sudo",
+        &[
+            "-u", "--user", "-g", "--group", "-h", "--host", "-p", "--prompt", "-C",
+            "--close-from", "-D", "--chdir", "-r", "--role", "-t", "--type", "-T",
+            "--command-timeout", "-U", "--other-user",
+        ],
+        0,
+    ),
+    ("doas", &["-u", "-C"], 0),
+    ("nice", &["-n", "--adjustment"], 0),
+    ("ionice", &["-c", "--class", "-n", "--classdata"], 0),
+    ("nohup", &[], 0),
+    ("setsid", &[], 0),
+    ("stdbuf", &["-i", "-o", "-e"], 0),
+    ("time", &["-f", "--format", "-o", "--output"], 0),
+    (
+        "xargs",
+        &[
+            "-a", "--arg-file", "-d", "--delimiter", "-E", "-I", "-L", "--max-lines", "-n",
+            "--max-args", "-P", "--max-procs", "-s", "--max-chars",
+        ],
+        0,
+    ),
+    ("busybox", &[], 0),
+    ("command", &[], 0),
+    ("exec", &["-a"], 0),
+    ("timeout", &["-s", "--signal", "-k", "--kill-after"], 1),
+    ("chrt", &[], 1),
+    ("taskset", &[], 1),
+    ("chroot", &["--userspec", "--groups"], 1),
+];
+/// Wrapper and shell levels unwrapped before giving up
+const MAX_UNWRAP: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    Deny { rule: String, reason: String },
+    Confirm { rule: String, reason: String },
+}
+
+/// An action parked until a human approves or rejects it
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingApproval {
+    pub id: String,
+    pub action: Action,
+    pub caller: String,
+    pub rule: String,
+    pub reason: String,
+    pub requested_at: DateTime<Utc>,
+}
+
+enum Matcher {
+    Paths(Vec<glob::Pattern>),
+    Commands(Vec<glob::Pattern>),
+    WindowClasses(Vec<String>),
+    SystemOperations(Vec<String>),
+    RateLimit {
+        action_type: Option<String>,
+        max: usize,
+        window: Duration,
+    },
+}
+
+struct Rule {
+    name: String,
+    matcher: Matcher,
+    on_match: PolicyVerdict,
+}
+
+/// Policy Engine - Decides whether an action may run before the engine executes it
+///
+/// Kernel-level operations are gated by `enable_kernel_ops` regardless of
+/// `safety_mode`; the declarative rules only apply while `safety_mode` is on.
+pub struct PolicyEngine {
+    safety_mode: bool,
+    enable_kernel_ops: bool,
+    rules: Vec<Rule>,
+    confirm_timeout: Duration,
+    recent: Mutex<HashMap<String, VecDeque<Instant>>>,
+    pending: Mutex<HashMap<String, (PendingApproval, oneshot::Sender<bool>)>>,
+}
+
+impl PolicyEngine {
+    pub fn new(system: &SystemConfig, policy: &PolicyConfig) -> Result<Self> {
+        let compile = |rule: &str, patterns: &[String], expand: bool| {
+            patterns
+                .iter()
+                .map(|p| {
+                    let p = if expand {
+                        expand_home(p).to_string_lossy().to_string()
+                    } else {
+                        p.clone()
+                    };
+                    glob::Pattern::new(&p)
+                        .with_context(|| format!("Invalid pattern {:?} in policy rule {}", p, rule))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+
+        let mut rules = Vec::new();
+        for rule in &policy.rules {
+            let matcher = match &rule.kind {
+                PolicyRuleKind::DenyPaths { patterns } => {
+                    let mut compiled = compile(&rule.name, patterns, true)?;
+                    // Also deny where the pattern's directory really is, if it is a symlink
+                    let resolved = compiled
+                        .iter()
+                        .filter_map(|pattern| resolved_pattern(pattern.as_str()))
+                        .collect::<Vec<_>>();
+                    compiled.extend(compile(&rule.name, &resolved, false)?);
+                    Matcher::Paths(compiled)
+                }
+                PolicyRuleKind::DenyCommands { patterns } => {
+                    Matcher::Commands(compile(&rule.name, patterns, false)?)
+                }
+                PolicyRuleKind::AllowWindowClasses { classes } => {
+                    Matcher::WindowClasses(classes.iter().map(|c| c.to_lowercase()).collect())
+                }
+                PolicyRuleKind::SystemOperations { operations } => {
+                    Matcher::SystemOperations(operations.clone())
+                }
+                PolicyRuleKind::RateLimit { action_type, max, per_secs } => Matcher::RateLimit {
+                    action_type: action_type.clone(),
+                    max: *max,
+                    window: Duration::from_secs(*per_secs),
+                },
+            };
+            rules.push(Rule {
+                name: rule.name.clone(),
+                matcher,
+                on_match: rule.on_match,
+            });
+        }
+        info!(
+            "Policy engine: safety_mode={}, kernel_ops={}, {} rules",
+            system.safety_mode,
+            system.enable_kernel_ops,
+            rules.len()
+        );
+
+        Ok(Self {
+            safety_mode: system.safety_mode,
+            enable_kernel_ops: system.enable_kernel_ops,
+            rules,
+            confirm_timeout: Duration::from_secs(policy.confirm_timeout_secs),
+            recent: Mutex::new(HashMap::new()),
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Decide on `action`, counting it against any rate limits
+    pub async fn evaluate(&self, action: &Action) -> Verdict {
+        self.judge(action, true).await
+    }
+
+    /// Decide on `action` without counting it, for dry runs
+    pub async fn check(&self, action: &Action) -> Verdict {
+        self.judge(action, false).await
+    }
+
+    async fn judge(&self, action: &Action, record: bool) -> Verdict {
+        if !self.enable_kernel_ops {
+            if let Some(reason) = kernel_operation(action) {
+                return Verdict::Deny {
+                    rule: KERNEL_OPS_RULE.to_string(),
+                    reason: format!("{} (enable_kernel_ops is off)", reason),
+                };
+            }
+        }
+        if !self.safety_mode {
+            return Verdict::Allow;
+        }
+
+        for rule in &self.rules {
+            let reason = match &rule.matcher {
+                Matcher::Paths(patterns) => denied_path(action, patterns),
+                Matcher::Commands(patterns) => denied_command(action, patterns),
+                Matcher::WindowClasses(classes) => disallowed_window(action, classes).await,
+                Matcher::SystemOperations(operations) => match action {
+                    Action::SystemOp(operation)
+                        if operations.iter().any(|o| o == operation.name()) =>
+                    {
+                        Some(format!("system operation {}", operation.name()))
+                    }
+                    _ => None,
+                },
+                Matcher::RateLimit { action_type, max, window } => {
+                    let limit = (action_type.as_deref(), *max, *window);
+                    self.rate_limited(&rule.name, action, limit, record)
+                }
+            };
+            if let Some(reason) = reason {
+                let name = rule.name.clone();
+                return match rule.on_match {
+                    PolicyVerdict::Deny => Verdict::Deny { rule: name, reason },
+                    PolicyVerdict::Confirm => Verdict::Confirm { rule: name, reason },
+                };
+            }
+        }
+        Verdict::Allow
+    }
+
+    /// Park `action` until it is approved or rejected through [`resolve`](Self::resolve)
+    ///
+    /// Returns whether it was approved; nobody answering within the confirm
+    /// timeout counts as a rejection.
+    pub async fn confirm(&self, action: &Action, caller: &str, rule: &str, reason: &str) -> bool {
+        let id = uuid::Uuid::new_v4().to_string();
+        let (tx, rx) = oneshot::channel();
+        let approval = PendingApproval {
+            id: id.clone(),
+            action: action.clone(),
+            caller: caller.to_string(),
+            rule: rule.to_string(),
+            reason: reason.to_string(),
+            requested_at: Utc::now(),
+        };
+        match self.pending.lock() {
+            Ok(mut pending) => {
+                pending.insert(id.clone(), (approval, tx));
+            }
+            Err(_) => return false,
+        }
+        info!("Action {} awaits approval as {} (rule {})", action.name(), id, rule);
+
+        let approved = match tokio::time::timeout(self.confirm_timeout, rx).await {
+            Ok(Ok(approved)) => approved,
+            Ok(Err(_)) => false,
+            Err(_) => {
+                warn!("Approval {} timed out", id);
+                false
+            }
+        };
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.remove(&id);
+        }
+        approved
+    }
+
+    /// Actions currently waiting for a human, oldest first
+    pub fn pending(&self) -> Vec<PendingApproval> {
+        let mut pending: Vec<PendingApproval> = self
+            .pending
+            .lock()
+            .map(|p| p.values().map(|(approval, _)| approval.clone()).collect())
+            .unwrap_or_default();
+        pending.sort_by_key(|p| p.requested_at);
+        pending
+    }
+
+    pub fn resolve(&self, id: &str, approve: bool) -> Result<PendingApproval> {
+        let (approval, tx) = self
+            .pending
+            .lock()
+            .map_err(|_| anyhow!("Approval table poisoned"))?
+            .remove(id)
+            .ok_or_else(|| anyhow!("No pending approval {}", id))?;
+        info!("Approval {} {}", id, if approve { "granted" } else { "rejected" });
+        let _ = tx.send(approve);
+        Ok(approval)
+    }
+
+    /// Decide on a click at a point the engine has only just located, such as
+    /// text or an image found on screen
+    ///
+    /// Only window class rules apply. It is too late to park the action for
+    /// approval by then, so the engine refuses a confirm verdict as well.
+    pub async fn check_click(&self, x: i32, y: i32) -> Verdict {
+        if !self.safety_mode {
+            return Verdict::Allow;
+        }
+        for rule in &self.rules {
+            let Matcher::WindowClasses(classes) = &rule.matcher else {
+                continue;
+            };
+            let window = tokio::task::spawn_blocking(move || WindowSystem::at(x, y)).await;
+            let Some(window) = window.ok().and_then(|w| w.ok()).flatten() else {
+                continue;
+            };
+            let Some(reason) = disallowed_class(&window, classes) else {
+                continue;
+            };
+            let name = rule.name.clone();
+            return match rule.on_match {
+                PolicyVerdict::Deny => Verdict::Deny { rule: name, reason },
+                PolicyVerdict::Confirm => Verdict::Confirm { rule: name, reason },
+            };
+        }
+        Verdict::Allow
+    }
+
+    /// Decide whether a directory may be watched for file changes
+    ///
+    /// Only path rules apply, to the directory and, for recursive watches,
+    /// everything below it. Watches have no approval flow, so callers refuse
+    /// a confirm verdict as well.
+    pub fn check_watch(&self, spec: &WatchSpec) -> Verdict {
+        if !self.safety_mode {
+            return Verdict::Allow;
+        }
+        let root = expand_home(&spec.path);
+        for rule in &self.rules {
+            let Matcher::Paths(patterns) = &rule.matcher else {
+                continue;
+            };
+            let Some(reason) = denied_paths([(root.clone(), spec.recursive)], patterns) else {
+                continue;
+            };
+            let name = rule.name.clone();
+            return match rule.on_match {
+                PolicyVerdict::Deny => Verdict::Deny { rule: name, reason },
+                PolicyVerdict::Confirm => Verdict::Confirm { rule: name, reason },
+            };
+        }
+        Verdict::Allow
+    }
+
+    fn rate_limited(
+        &self,
+        rule: &str,
+        action: &Action,
+        (action_type, max, window): (Option<&str>, usize, Duration),
+        record: bool,
+    ) -> Option<String> {
+        if action_type.is_some_and(|t| t != action.name()) {
+            return None;
+        }
+        let mut recent = self.recent.lock().ok()?;
+        let times = recent.entry(rule.to_string()).or_default();
+        let now = Instant::now();
+        while times.front().is_some_and(|t| now.duration_since(*t) > window) {
+            times.pop_front();
+        }
+        if times.len() >= max {
+            return Some(format!("more than {} actions in {:?}", max, window));
+        }
+        if record {
+            times.push_back(now);
+        }
+        None
+    }
+}
+
+/// Absolute, lexically normalized form of a user-supplied path
+///
+/// Symlinks are left alone; [`forms`] adds the path they lead to.
+fn normalize(path: &str) -> PathBuf {
+    let path = expand_home(path);
+    let path = if path.is_absolute() {
+        path
+    } else {
+        std::env::current_dir().unwrap_or_default().join(path)
+    };
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            Component::CurDir => {}
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+/// A normalized path and, when symlinks lead elsewhere, where it really is
+///
+/// Both are checked, so neither a link into a denied directory nor a link
+/// out of one gets past a rule.
+fn forms(path: &Path) -> Vec<PathBuf> {
+    let resolved = resolve_existing(path);
+    if resolved == path {
+        vec![path.to_path_buf()]
+    } else {
+        vec![path.to_path_buf(), resolved]
+    }
+}
+
+/// The fixed part of a glob pattern, before its first wildcard
+fn literal_prefix(pattern: &str) -> &str {
+    let end = pattern.find(['*', '?', '[']).unwrap_or(pattern.len());
+    &pattern[..end]
+}
+
+/// `pattern` with its fixed part resolved through symlinks, if that changes it
+fn resolved_pattern(pattern: &str) -> Option<String> {
+    let prefix = literal_prefix(pattern);
+    // Resolve whole components only; a partial last one stays in the pattern
+    let split = if prefix == pattern { prefix.len() } else { prefix.rfind('/')? + 1 };
+    let (directory, rest) = pattern.split_at(split);
+    let resolved = resolve_existing(Path::new(directory));
+    if resolved == Path::new(directory) {
+        return None;
+    }
+    let directory = glob::Pattern::escape(&resolved.to_string_lossy());
+    if rest.is_empty() {
+        return Some(directory);
+    }
+    Some(format!("{}/{}", directory.trim_end_matches('/'), rest))
+}
+
+/// Paths an action touches; the flag marks paths whose whole subtree is affected
+fn action_paths(action: &Action) -> Vec<(PathBuf, bool)> {
+    let operation = match action {
+        Action::FileOp(operation) => operation,
+        // Clipboard images can be saved to or loaded from a file
+        Action::ClipboardGet(ClipboardGetParams { path: Some(path), .. })
+        | Action::ClipboardSet(ClipboardSetParams::Image { path: Some(path), .. }) => {
+            return vec![(normalize(path), false)];
+        }
+        // The image to look for is read from disk
+        Action::FindImage(params) | Action::ClickImage(ClickImageParams { image: params, .. }) => {
+            return vec![(normalize(&params.path), false)];
+        }
+        Action::ScreenDiff(params) => {
+            return std::iter::once(&params.before)
+                .chain(&params.after)
+                .map(|path| (normalize(path), false))
+                .collect();
+        }
+        // Waiting on a file reveals whether it exists and what it contains
+        Action::WaitForFile(condition) | Action::AssertFile(condition) => {
+            return vec![(normalize(&condition.path), false)];
+        }
+        // Relative paths in the command then land in the working directory
+        Action::ProcessOp(ProcessOperation::Spawn { cwd: Some(cwd), .. }) => {
+            return vec![(normalize(cwd), false)];
+        }
+        _ => return Vec::new(),
+    };
+    match operation {
+        FileOperation::Read { path, .. }
+        | FileOperation::Write { path, .. }
+        | FileOperation::Append { path, .. }
+        | FileOperation::Mkdir { path, .. }
+        | FileOperation::Stat { path }
+        | FileOperation::List { path, .. } => vec![(normalize(path), false)],
+        FileOperation::Delete { path, recursive } => vec![(normalize(path), *recursive)],
+        FileOperation::Copy { from, to, .. } => vec![(normalize(from), true), (normalize(to), false)],
+        FileOperation::Move { from, to, .. } => vec![(normalize(from), true), (normalize(to), false)],
+        FileOperation::Glob { pattern, .. } => vec![(normalize(literal_prefix(pattern)), true)],
+    }
+}
+
+fn denied_path(action: &Action, patterns: &[glob::Pattern]) -> Option<String> {
+    denied_paths(action_paths(action), patterns)
+}
+
+/// Why one of `paths`, each flagged with whether its subtree is involved,
+/// is denied by `patterns`, if it is
+fn denied_paths(
+    paths: impl IntoIterator<Item = (PathBuf, bool)>,
+    patterns: &[glob::Pattern],
+) -> Option<String> {
+    let paths = paths
+        .into_iter()
+        .flat_map(|(path, subtree)| forms(&path).into_iter().map(move |form| (form, subtree)));
+    for (path, subtree) in paths {
+        for pattern in patterns {
+            // A pattern matching a parent directory covers everything below it
+            if path.ancestors().any(|p| pattern.matches_path(p)) {
+                return Some(format!("{} matches {}", path.display(), pattern));
+            }
+            let inner = Path::new(literal_prefix(pattern.as_str()));
+            if subtree && inner != path && inner.starts_with(&path) {
+                return Some(format!("{} contains {}", path.display(), pattern));
+            }
+        }
+    }
+    None
+}
+
+/// Command lines an action would run, with each program reduced to its file name
+///
+/// Wrappers such as `env`, `sudo` or `busybox` and shell `-c` scripts are
+/// unwrapped, and the commands they run are listed as command lines too.
+fn action_commands(action: &Action) -> Vec<String> {
+    let Action::ProcessOp(ProcessOperation::Spawn { command, args, .. }) = action else {
+        return Vec::new();
+    };
+    let argv = std::iter::once(command.clone()).chain(args.iter().cloned()).collect();
+    let mut lines = Vec::new();
+    command_lines(argv, 0, &mut lines);
+    lines
+}
+
+fn command_lines(mut argv: Vec<String>, depth: usize, lines: &mut Vec<String>) {
+    for depth in depth..MAX_UNWRAP {
+        let Some(first) = argv.first() else {
+            return;
+        };
+        let program = Path::new(first)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_else(|| first.clone());
+        argv[0] = program.clone();
+        let line = argv.join(" ");
+        if !lines.contains(&line) {
+            lines.push(line);
+        }
+
+        if SHELLS.contains(&program.as_str()) {
+            let Some(script) = shell_script(&argv[1..]) else {
+                return;
+            };
+            let script = script.trim().to_string();
+            if !lines.contains(&script) {
+                lines.push(script.clone());
+            }
+            // Each command of the script, as far as splitting on operators finds them
+            for command in script.split([';', '&', '|', '\n', '(', ')', '`']) {
+                let words = command
+                    .split_whitespace()
+                    .map(|word| word.trim_matches(['\'', '"']).to_string())
+                    .filter(|word| !word.is_empty())
+                    .collect();
+                command_lines(words, depth + 1, lines);
+            }
+            return;
+        }
+        match unwrap_command(&program, &argv[1..]) {
+            Some(inner) => argv = inner,
+            None => return,
+        }
+    }
+}
+
+/// The script a shell runs with `-c`, as in `sh -c "..."` or `bash -lc "..."`
+fn shell_script(args: &[String]) -> Option<&String> {
+    let mut command = false;
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if arg == "--" || arg == "-" {
+            index += 1;
+            break;
+        }
+        if arg.starts_with("--") {
+            index += if matches!(arg.as_str(), "--rcfile" | "--init-file") { 2 } else { 1 };
+            continue;
+        }
+        let Some(flags) = arg.strip_prefix('-').or_else(|| arg.strip_prefix('+')) else {
+            break;
+        };
+        command |= arg.starts_with('-') && flags.contains('c');
+        // `-o name` and `-O name` set shell options
+        index += if flags.ends_with(['o', 'O']) { 2 } else { 1 };
+    }
+    if command {
+        args.get(index)
+    } else {
+        None
+    }
+}
+
+/// The command a wrapper program runs, if `program` is one
+fn unwrap_command(program: &str, args: &[String]) -> Option<Vec<String>> {
+    let (_, valued, operands) = WRAPPERS.iter().find(|(name, ..)| *name == program)?;
+    let env = program == "env";
+    let mut split = Vec::new();
+    let mut index = 0;
+    while let Some(arg) = args.get(index) {
+        if arg == "--" {
+            index += 1;
+            break;
+        }
+        if !arg.starts_with('-') || arg == "-" {
+            break;
+        }
+        // `env -S "..."` splits its argument into the command
+        let script = match arg.as_str() {
+            "-S" | "--split-string" if env => {
+                index += 1;
+                args.get(index).cloned()
+            }
+            other if env => other
+                .strip_prefix("--split-string=")
+                .or_else(|| other.strip_prefix("-S"))
+                .map(String::from),
+            _ => None,
+        };
+        if let Some(script) = script {
+            split.extend(script.split_whitespace().map(String::from));
+            index += 1;
+            continue;
+        }
+        index += if valued.contains(&arg.as_str()) { 2 } else { 1 };
+    }
+    // `env` sets variables before the command
+    while env && args.get(index).is_some_and(|arg| arg.contains('=')) {
+        index += 1;
+    }
+    let rest = args.get(index + operands..).unwrap_or_default();
+    let inner: Vec<String> = split.into_iter().chain(rest.iter().cloned()).collect();
+    (!inner.is_empty()).then_some(inner)
+}
+
+fn denied_command(action: &Action, patterns: &[glob::Pattern]) -> Option<String> {
+    for line in action_commands(action) {
+        if let Some(pattern) = patterns.iter().find(|p| p.matches(&line)) {
+            return Some(format!("`{}` matches {}", line, pattern));
+        }
+    }
+    None
+}
+
+/// Whether an action reaches a window whose class is not in `classes`
+///
+/// Window operations are checked against their target, clicks against the
+/// window under the point, typing and key presses against the focused window,
+/// and accessibility actions against the windows of the node's application.
+/// Clicks on text, images or marks are only placed once the engine has found
+/// them, so it checks those through [`PolicyEngine::check_click`].
+async fn disallowed_window(action: &Action, classes: &[String]) -> Option<String> {
+    let lookup: Box<dyn FnOnce() -> Result<Option<WindowInfo>> + Send> = match action {
+        Action::WindowOp(operation) => {
+            let selector = match operation {
+                WindowOperation::List => return None,
+                WindowOperation::Focus { window, .. }
+                | WindowOperation::Move { window, .. }
+                | WindowOperation::Resize { window, .. }
+                | WindowOperation::Minimize { window, .. }
+                | WindowOperation::Maximize { window, .. }
+                | WindowOperation::Restore { window, .. }
+                | WindowOperation::Close { window, .. } => window.clone(),
+            };
+            Box::new(move || WindowSystem::find(&selector).map(Some))
+        }
+        Action::Click(params) => {
+            let (x, y) = (params.x, params.y);
+            Box::new(move || WindowSystem::at(x, y))
+        }
+        Action::Type(_) | Action::Key(_) => Box::new(WindowSystem::focused),
+        Action::A11yPress(A11yPressParams { target, .. })
+        | Action::A11ySetText(A11ySetTextParams { target, .. })
+        | Action::A11yFocus(target) => return disallowed_application(target, classes).await,
+        _ => return None,
+    };
+    // A selector that matches nothing fails later in the engine with a better
+    // error; input that reaches no window reaches no application either
+    let window = tokio::task::spawn_blocking(lookup).await.ok()?.ok()??;
+    disallowed_class(&window, classes)
+}
+
+/// Whether the application owning the node `target` has no allowed window
+async fn disallowed_application(target: &A11yQuery, classes: &[String]) -> Option<String> {
+    // A target that matches no single node fails later in the engine
+    let node = accessibility::resolve(target).await.ok()?;
+    let pid = match accessibility::process_id(&node).await {
+        Ok(pid) => pid,
+        Err(e) => {
+            let (role, name) = (&node.role, &node.name);
+            return Some(format!("cannot tell which window {} {:?} is in: {}", role, name, e));
+        }
+    };
+    let windows = tokio::task::spawn_blocking(WindowSystem::list).await.ok()?.ok()?;
+    let owned = windows.iter().filter(|w| w.pid == Some(pid)).collect::<Vec<_>>();
+    if owned.iter().any(|w| disallowed_class(w, classes).is_none()) {
+        return None;
+    }
+    let owned = owned.iter().map(|w| w.class.as_str()).collect::<Vec<_>>();
+    Some(format!(
+        "{} {:?} belongs to process {} whose windows ({:?}) are not allowed",
+        node.role, node.name, pid, owned
+    ))
+}
+
+fn disallowed_class(window: &WindowInfo, classes: &[String]) -> Option<String> {
+    if classes.contains(&window.class.to_lowercase()) {
+        None
+    } else {
+        Some(format!("window class {:?} is not allowed", window.class))
+    }
+}
+
+/// The first of `paths` that lies under a kernel path, directly or through symlinks
+fn kernel_path(paths: impl IntoIterator<Item = (PathBuf, bool)>) -> Option<String> {
+    paths
+        .into_iter()
+        .find_map(|(path, _)| {
+            let modified = forms(&path)
+                .into_iter()
+                .find(|form| KERNEL_PATHS.iter().any(|k| form.starts_with(k)))?;
+            Some((path, modified))
+        })
+        .map(|(path, modified)| {
+            if path == modified {
+                format!("modifies {}", path.display())
+            } else {
+                format!("modifies {} (through {})", modified.display(), path.display())
+            }
+        })
+}
+
+/// Why an action counts as a kernel-level operation, if it does
+fn kernel_operation(action: &Action) -> Option<String> {
+    match action {
+        Action::FileOp(operation) => {
+            let writes = !matches!(
+                operation,
+                FileOperation::Read { .. }
+                    | FileOperation::Stat { .. }
+                    | FileOperation::List { .. }
+                    | FileOperation::Glob { .. }
+            );
+            if !writes {
+                return None;
+            }
+            // Copying only modifies the destination
+            let skip = usize::from(matches!(operation, FileOperation::Copy { .. }));
+            kernel_path(action_paths(action).into_iter().skip(skip))
+        }
+        Action::ClipboardGet(ClipboardGetParams { path: Some(_), .. }) => {
+            kernel_path(action_paths(action))
+        }
+        Action::ProcessOp(ProcessOperation::Signal { pid: Some(1), .. }) => {
+            Some("signals init (pid 1)".to_string())
+        }
+        Action::ProcessOp(ProcessOperation::Spawn { .. }) => action_commands(action)
+            .into_iter()
+            .find(|line| {
+                let program = line.split_whitespace().next().unwrap_or_default();
+                KERNEL_COMMANDS.contains(&program)
+            })
+            .map(|line| format!("runs `{}`", line)),
+        Action::SystemOp(operation) if KERNEL_SYSTEM_OPERATIONS.contains(&operation.name()) => {
+            Some(format!("system operation {}", operation.name()))
+        }
+        Action::SystemOp(SystemOperation::SetEnv { set, unset }) => set
+            .keys()
+            .chain(unset)
+            // These decide which binaries and libraries spawned processes load
+            .find(|name| name.as_str() == "PATH" || name.starts_with("LD_"))
+            .map(|name| format!("changes {} for spawned processes", name)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::PolicyRule;
+    use serde_json::json;
+
+    fn action(value: serde_json::Value) -> Action {
+        serde_json::from_value(value).expect("valid action")
+    }
+
+    fn read(path: &str) -> Action {
+        action(json!({
+            "action_type": "file_operation",
+            "params": {"operation": "read", "path": path},
+        }))
+    }
+
+    fn spawn(command: &str, args: &[&str]) -> Action {
+        action(json!({
+            "action_type": "process_operation",
+            "params": {"operation": "spawn", "command": command, "args": args},
+        }))
+    }
+
+    fn engine(safety_mode: bool, rules: Vec<(&str, PolicyRuleKind)>) -> PolicyEngine {
+        let system = SystemConfig {
+            safety_mode,
+            enable_kernel_ops: false,
+            max_concurrent_tasks: 1,
+        };
+        let policy = PolicyConfig {
+            rules: rules
+                .into_iter()
+                .map(|(name, kind)| PolicyRule {
+                    name: name.to_string(),
+                    kind,
+                    on_match: PolicyVerdict::Deny,
+                })
+                .collect(),
+            confirm_timeout_secs: 1,
+        };
+        PolicyEngine::new(&system, &policy).expect("valid policy")
+    }
+
+    fn deny_paths(patterns: &[&str]) -> PolicyEngine {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect();
+        engine(true, vec![("paths", PolicyRuleKind::DenyPaths { patterns })])
+    }
+
+    fn deny_commands(patterns: &[&str]) -> PolicyEngine {
+        let patterns = patterns.iter().map(|p| p.to_string()).collect();
+        engine(true, vec![("commands", PolicyRuleKind::DenyCommands { patterns })])
+    }
+
+    fn scratch_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("digios-policy-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn denied(verdict: Verdict) -> bool {
+        matches!(verdict, Verdict::Deny { .. })
+    }
+
+    #[test]
+    fn normalize_resolves_dot_components() {
+        assert_eq!(normalize("/etc/./ssh/../shadow"), PathBuf::from("/etc/shadow"));
+        assert_eq!(normalize("/../etc//shadow"), PathBuf::from("/etc/shadow"));
+        let relative = normalize("a/../b");
+        assert!(relative.is_absolute());
+        assert!(relative.ends_with("b"));
+    }
+
+    #[test]
+    fn literal_prefix_stops_at_first_wildcard() {
+        assert_eq!(literal_prefix("/home/*/.ssh"), "/home/");
+        assert_eq!(literal_prefix("/etc/sudoers*"), "/etc/sudoers");
+        assert_eq!(literal_prefix("/etc/shadow"), "/etc/shadow");
+    }
+
+    #[tokio::test]
+    async fn deny_paths_cover_subtrees_and_prefixes() {
+        let policy = deny_paths(&["/etc/sudoers*", "/secret"]);
+        assert!(denied(policy.check(&read("/etc/sudoers.d/local")).await));
+        assert!(denied(policy.check(&read("/secret/key")).await));
+        assert!(denied(policy.check(&read("/tmp/../secret")).await));
+        assert_eq!(policy.check(&read("/secrets")).await, Verdict::Allow);
+
+        // A glob or recursive delete above a denied path reaches into it
+        let glob = action(json!({
+            "action_type": "file_operation",
+            "params": {"operation": "glob", "pattern": "/*"},
+        }));
+        assert!(denied(policy.check(&glob).await));
+        let delete = action(json!({
+            "action_type": "file_operation",
+            "params": {"operation": "delete", "path": "/", "recursive": true},
+        }));
+        assert!(denied(policy.check(&delete).await));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn deny_paths_follow_symlinks() {
+        let dir = scratch_dir();
+        std::fs::create_dir(dir.join("secret")).unwrap();
+        std::os::unix::fs::symlink(dir.join("secret"), dir.join("alias")).unwrap();
+
+        // A link into a denied directory
+        let policy = deny_paths(&[&dir.join("secret").to_string_lossy()]);
+        let through_link = dir.join("alias/key");
+        assert!(denied(policy.check(&read(&through_link.to_string_lossy())).await));
+
+        // A pattern naming the link covers where it leads
+        let policy = deny_paths(&[&format!("{}/**", dir.join("alias").display())]);
+        let real = dir.join("secret/key");
+        assert!(denied(policy.check(&read(&real.to_string_lossy())).await));
+        assert_eq!(policy.check(&read(&dir.join("other").to_string_lossy())).await, Verdict::Allow);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn kernel_paths_follow_symlinks() {
+        let dir = scratch_dir();
+        std::os::unix::fs::symlink("/dev", dir.join("devices")).unwrap();
+        let write = action(json!({
+            "action_type": "file_operation",
+            "params": {
+                "operation": "write",
+                "path": dir.join("devices/null").to_string_lossy(),
+                "content": "",
+            },
+        }));
+        // Kernel paths are gated even with safety mode off
+        let policy = engine(false, Vec::new());
+        match policy.check(&write).await {
+            Verdict::Deny { rule, .. } => assert_eq!(rule, KERNEL_OPS_RULE),
+            other => panic!("expected a kernel ops denial, got {:?}", other),
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn spawn_working_directory_is_checked() {
+        let policy = deny_paths(&["/etc/ssh"]);
+        let spawn_in = |cwd: &str| {
+            action(json!({
+                "action_type": "process_operation",
+                "params": { "operation": "spawn", "command": "ls", "cwd": cwd },
+            }))
+        };
+        assert!(denied(policy.check(&spawn_in("/etc/ssh")).await));
+        assert_eq!(policy.check(&spawn_in("/etc")).await, Verdict::Allow);
+    }
+
+    #[test]
+    fn watches_follow_path_rules() {
+        let policy = deny_paths(&["/etc/ssh"]);
+        let watch = |path: &str, recursive: bool| WatchSpec {
+            path: path.to_string(),
+            recursive,
+            patterns: Vec::new(),
+            debounce_ms: 0,
+        };
+        assert!(denied(policy.check_watch(&watch("/etc/ssh", false))));
+        assert!(denied(policy.check_watch(&watch("/etc", true))));
+        assert_eq!(policy.check_watch(&watch("/etc", false)), Verdict::Allow);
+    }
+
+    #[tokio::test]
+    async fn loader_environment_needs_kernel_ops() {
+        let set_env = |set: serde_json::Value, unset: &[&str]| {
+            action(json!({
+                "action_type": "system_operation",
+                "params": { "operation": "set_env", "set": set, "unset": unset },
+            }))
+        };
+        let policy = engine(false, Vec::new());
+        let preload = set_env(json!({ "LD_PRELOAD": "/tmp/x.so" }), &[]);
+        for denied in [preload, set_env(json!({}), &["PATH"])] {
+            assert!(matches!(policy.check(&denied).await, Verdict::Deny { .. }));
+        }
+        let allowed = set_env(json!({ "LANG": "C" }), &["LDFLAGS"]);
+        assert_eq!(policy.check(&allowed).await, Verdict::Allow);
+    }
+
+    #[test]
+    fn commands_are_unwrapped() {
+        let runs = |command: &str, args: &[&str], line: &str| {
+            action_commands(&spawn(command, args)).contains(&line.to_string())
+        };
+        assert!(runs("/sbin/mkfs.ext4", &["/dev/sda1"], "mkfs.ext4 /dev/sda1"));
+        assert!(runs("env", &["-i", "FOO=1", "/usr/bin/rm", "-rf", "/"], "rm -rf /"));
+        assert!(runs("env", &["-S", "rm -rf /"], "rm -rf /"));
+        assert!(runs("sudo", &["-u", "root", "wipefs", "-a"], "wipefs -a"));
+        assert!(runs("busybox", &["rm", "-rf", "/"], "rm -rf /"));
+        assert!(runs("timeout", &["-s", "KILL", "5", "shred", "x"], "shred x"));
+        assert!(runs("bash", &["-lc", "cd /tmp && /bin/rm -rf /"], "rm -rf /"));
+        assert!(runs("sh", &["-e", "-c", "nice -n 5 'mkfs' /dev/sdb"], "mkfs /dev/sdb"));
+        // Without -c the shell runs a script file, which cannot be inspected
+        let script = spawn("sh", &["script.sh"]);
+        assert_eq!(action_commands(&script), vec!["sh script.sh".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn deny_commands_match_wrapped_commands() {
+        let policy = deny_commands(&["mkfs*", "rm -rf /"]);
+        assert!(denied(policy.check(&spawn("/usr/sbin/mkfs", &["/dev/sdb"])).await));
+        assert!(denied(policy.check(&spawn("env", &["sh", "-c", "rm -rf /"])).await));
+        assert!(denied(policy.check(&spawn("nohup", &["busybox", "mkfs.vfat", "x"])).await));
+        assert_eq!(policy.check(&spawn("ls", &["-la"])).await, Verdict::Allow);
+    }
+
+    #[tokio::test]
+    async fn rate_limits_count_only_evaluated_actions() {
+        let policy = engine(
+            true,
+            vec![(
+                "flood",
+                PolicyRuleKind::RateLimit {
+                    action_type: Some("file_operation".to_string()),
+                    max: 2,
+                    per_secs: 60,
+                },
+            )],
+        );
+        let action = read("/tmp/x");
+        assert_eq!(policy.evaluate(&action).await, Verdict::Allow);
+        // Dry runs are not counted
+        assert_eq!(policy.check(&action).await, Verdict::Allow);
+        assert_eq!(policy.check(&action).await, Verdict::Allow);
+        assert_eq!(policy.evaluate(&action).await, Verdict::Allow);
+        assert!(denied(policy.evaluate(&action).await));
+        // Other action types have their own budget
+        assert_eq!(policy.evaluate(&spawn("ls", &[])).await, Verdict::Allow);
+    }
+}
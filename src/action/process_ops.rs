@@ -0,0 +1,425 @@
+use crate::action::schema::ProcessOperation;
+use crate::core::paths::expand_home;
+use crate::state::describe_process;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use sysinfo::{Pid, Signal, System};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+/// Captured output is capped per stream; older bytes are dropped first
+const MAX_CAPTURE_BYTES: usize = 1024 * 1024;
+/// Finished processes beyond this count are forgotten, oldest first
+const MAX_TRACKED: usize = 256;
+/// How far the kernel's start time of a process may be from when the spawn
+/// returned; the kernel's is only known to the second
+const START_TIME_SLACK_SECS: u64 = 2;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExitInfo {
+    pub code: Option<i32>,
+    pub signal: Option<i32>,
+    pub timed_out: bool,
+    pub finished_at: DateTime<Utc>,
+}
+
+#[derive(Default)]
+struct OutputBuffer {
+    data: Vec<u8>,
+    total_bytes: usize,
+}
+
+impl OutputBuffer {
+    fn push(&mut self, chunk: &[u8]) {
+        self.total_bytes += chunk.len();
+        self.data.extend_from_slice(chunk);
+        if self.data.len() > MAX_CAPTURE_BYTES {
+            let excess = self.data.len() - MAX_CAPTURE_BYTES;
+            self.data.drain(..excess);
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "text": String::from_utf8_lossy(&self.data),
+            "total_bytes": self.total_bytes,
+            "truncated": self.total_bytes > self.data.len(),
+        })
+    }
+}
+
+struct ManagedProcess {
+    pid: Option<u32>,
+    command: String,
+    args: Vec<String>,
+    started_at: DateTime<Utc>,
+    stdout: Arc<Mutex<OutputBuffer>>,
+    stderr: Arc<Mutex<OutputBuffer>>,
+    exit: watch::Receiver<Option<ExitInfo>>,
+}
+
+impl ManagedProcess {
+    fn to_json(&self, handle: &str) -> Value {
+        let exit = self.exit.borrow().clone();
+        json!({
+            "handle": handle,
+            "pid": self.pid,
+            "command": self.command,
+            "args": self.args,
+            "started_at": self.started_at,
+            "running": exit.is_none(),
+            "exit": exit,
+            "stdout": self.stdout.lock().map(|b| b.to_json()).unwrap_or(Value::Null),
+            "stderr": self.stderr.lock().map(|b| b.to_json()).unwrap_or(Value::Null),
+        })
+    }
+}
+
+impl Default for ProcessManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Process Manager - Executes `process_operation` actions and tracks spawned children
+pub struct ProcessManager {
+    children: Mutex<HashMap<String, ManagedProcess>>,
+    /// Environment changes applied to every spawn; `None` removes the variable
+    env: Mutex<BTreeMap<String, Option<String>>>,
+}
+
+impl ProcessManager {
+    pub fn new() -> Self {
+        Self {
+            children: Mutex::new(HashMap::new()),
+            env: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Change the environment future spawns inherit, returning all overrides
+    pub fn set_env(
+        &self,
+        set: HashMap<String, String>,
+        unset: Vec<String>,
+    ) -> Result<BTreeMap<String, Option<String>>> {
+        for name in set.keys().chain(&unset) {
+            if name.is_empty() || name.contains(['=', '\0']) {
+                bail!("Invalid environment variable name: {:?}", name);
+            }
+        }
+        let mut env = self.env.lock().map_err(|_| anyhow!("Environment lock poisoned"))?;
+        for name in unset {
+            env.insert(name, None);
+        }
+        for (name, value) in set {
+            env.insert(name, Some(value));
+        }
+        Ok(env.clone())
+    }
+
+    pub fn env_overrides(&self) -> BTreeMap<String, Option<String>> {
+        self.env.lock().map(|env| env.clone()).unwrap_or_default()
+    }
+
+    pub async fn execute(&self, operation: ProcessOperation) -> Result<Value> {
+        match operation {
+            ProcessOperation::Spawn { command, args, env, cwd, timeout_ms, wait } => {
+                let handle = self.spawn(command, args, env, cwd, timeout_ms)?;
+                if wait {
+                    self.wait(&handle, None).await
+                } else {
+                    self.describe(&handle)
+                }
+            }
+            ProcessOperation::List { name, limit } => Self::list(name, limit).await,
+            ProcessOperation::Output { handle } => self.describe(&handle),
+            ProcessOperation::Wait { handle, timeout_ms } => self.wait(&handle, timeout_ms).await,
+            ProcessOperation::Signal { handle, pid, signal } => {
+                let pid = match (handle, pid) {
+                    (Some(handle), _) => self.pid_of(&handle)?,
+                    (None, Some(pid)) => pid,
+                    (None, None) => bail!("signal requires a handle or a pid"),
+                };
+                Self::signal(pid, &signal)
+            }
+        }
+    }
+
+    /// Spawn a command and return its tracking handle
+    pub fn spawn(
+        &self,
+        command: String,
+        args: Vec<String>,
+        env: HashMap<String, String>,
+        cwd: Option<String>,
+        timeout_ms: Option<u64>,
+    ) -> Result<String> {
+        let mut cmd = tokio::process::Command::new(&command);
+        for (name, value) in self.env_overrides() {
+            match value {
+                Some(value) => cmd.env(name, value),
+                None => cmd.env_remove(name),
+            };
+        }
+        cmd.args(&args)
+            .envs(&env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(false);
+        if let Some(cwd) = &cwd {
+            cmd.current_dir(expand_home(cwd));
+        }
+
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to spawn {}", command))?;
+        let pid = child.id();
+        let handle = uuid::Uuid::new_v4().to_string();
+        info!("Spawned {} (pid {:?}) as {}", command, pid, handle);
+
+        let stdout = Arc::new(Mutex::new(OutputBuffer::default()));
+        let stderr = Arc::new(Mutex::new(OutputBuffer::default()));
+        let mut readers = Vec::new();
+        if let Some(pipe) = child.stdout.take() {
+            readers.push(tokio::spawn(capture(pipe, stdout.clone())));
+        }
+        if let Some(pipe) = child.stderr.take() {
+            readers.push(tokio::spawn(capture(pipe, stderr.clone())));
+        }
+
+        let (exit_tx, exit_rx) = watch::channel(None);
+        tokio::spawn(async move {
+            let timeout = timeout_ms.map(Duration::from_millis);
+            let (status, timed_out) = match timeout {
+                Some(timeout) => match tokio::time::timeout(timeout, child.wait()).await {
+                    Ok(status) => (status, false),
+                    Err(_) => {
+                        warn!("Process {:?} timed out after {:?}; killing", pid, timeout);
+                        let _ = child.kill().await;
+                        (child.wait().await, true)
+                    }
+                },
+                None => (child.wait().await, false),
+            };
+            // Let the readers drain the pipes, unless a grandchild is holding them open
+            let _ = tokio::time::timeout(
+                Duration::from_millis(500),
+                futures::future::join_all(readers),
+            )
+            .await;
+
+            let (code, signal) = match status {
+                Ok(status) => (status.code(), exit_signal(&status)),
+                Err(_) => (None, None),
+            };
+            let _ = exit_tx.send(Some(ExitInfo {
+                code,
+                signal,
+                timed_out,
+                finished_at: Utc::now(),
+            }));
+        });
+
+        let mut children = self.children.lock().map_err(|_| anyhow!("Process table poisoned"))?;
+        prune_finished(&mut children);
+        children.insert(
+            handle.clone(),
+            ManagedProcess {
+                pid,
+                command,
+                args,
+                started_at: Utc::now(),
+                stdout,
+                stderr,
+                exit: exit_rx,
+            },
+        );
+        Ok(handle)
+    }
+
+    /// Exit status of a spawned process by pid and start time, in seconds
+    /// since the epoch: `None` if digiOS didn't spawn it, `Some(None)` while
+    /// its exit is still being collected
+    ///
+    /// The start time tells a child apart from a later process that reused
+    /// its pid.
+    pub fn exit_of(&self, pid: u32, start_time: u64) -> Option<Option<ExitInfo>> {
+        let children = self.children.lock().ok()?;
+        children
+            .values()
+            .filter(|p| p.pid == Some(pid))
+            .filter(|p| {
+                let started = u64::try_from(p.started_at.timestamp()).unwrap_or_default();
+                started.abs_diff(start_time) <= START_TIME_SLACK_SECS
+            })
+            .max_by_key(|p| p.started_at)
+            .map(|p| p.exit.borrow().clone())
+    }
+
+    fn describe(&self, handle: &str) -> Result<Value> {
+        let children = self.children.lock().map_err(|_| anyhow!("Process table poisoned"))?;
+        let process = children
+            .get(handle)
+            .ok_or_else(|| anyhow!("Unknown process handle: {}", handle))?;
+        Ok(process.to_json(handle))
+    }
+
+    /// Whether `handle` refers to a tracked process
+    pub(crate) fn knows(&self, handle: &str) -> bool {
+        self.children
+            .lock()
+            .map(|children| children.contains_key(handle))
+            .unwrap_or(false)
+    }
+
+    pub(crate) fn pid_of(&self, handle: &str) -> Result<u32> {
+        let children = self.children.lock().map_err(|_| anyhow!("Process table poisoned"))?;
+        let process = children
+            .get(handle)
+            .ok_or_else(|| anyhow!("Unknown process handle: {}", handle))?;
+        let exited = process.exit.borrow().is_some();
+        match process.pid {
+            Some(pid) if !exited => Ok(pid),
+            _ => bail!("Process {} has already exited", handle),
+        }
+    }
+
+    /// Wait for a tracked process to exit, or until `timeout_ms` elapses
+    pub async fn wait(&self, handle: &str, timeout_ms: Option<u64>) -> Result<Value> {
+        let mut exit = {
+            let children = self.children.lock().map_err(|_| anyhow!("Process table poisoned"))?;
+            children
+                .get(handle)
+                .ok_or_else(|| anyhow!("Unknown process handle: {}", handle))?
+                .exit
+                .clone()
+        };
+
+        let finished = exit.wait_for(|status| status.is_some());
+        match timeout_ms {
+            Some(ms) => {
+                // Still running after the timeout is reported, not treated as an error
+                let _ = tokio::time::timeout(Duration::from_millis(ms), finished).await;
+            }
+            None => {
+                finished.await?;
+            }
+        }
+        self.describe(handle)
+    }
+
+    async fn list(name: Option<String>, limit: Option<usize>) -> Result<Value> {
+        tokio::task::spawn_blocking(move || {
+            // CPU usage needs two samples at least MINIMUM_CPU_UPDATE_INTERVAL apart
+            let mut system = System::new();
+            system.refresh_processes();
+            std::thread::sleep(sysinfo::MINIMUM_CPU_UPDATE_INTERVAL);
+            system.refresh_processes();
+
+            let filter = name.map(|n| n.to_lowercase());
+            let mut processes: Vec<_> = system
+                .processes()
+                .iter()
+                .filter(|(_, p)| {
+                    filter
+                        .as_ref()
+                        .is_none_or(|f| p.name().to_lowercase().contains(f))
+                })
+                .collect();
+            processes.sort_by(|a, b| b.1.cpu_usage().total_cmp(&a.1.cpu_usage()));
+
+            let total = processes.len();
+            let processes: Vec<Value> = processes
+                .into_iter()
+                .take(limit.unwrap_or(usize::MAX))
+                .map(|(pid, process)| describe_process(*pid, process))
+                .collect();
+            Ok(json!({"total": total, "processes": processes}))
+        })
+        .await?
+    }
+
+    fn signal(pid: u32, name: &str) -> Result<Value> {
+        let signal = parse_signal(name)?;
+        let pid = Pid::from_u32(pid);
+        let mut system = System::new();
+        if !system.refresh_process(pid) {
+            bail!("No process with pid {}", pid);
+        }
+        let process = system
+            .process(pid)
+            .ok_or_else(|| anyhow!("No process with pid {}", pid))?;
+        match process.kill_with(signal) {
+            Some(true) => Ok(json!({"pid": pid.as_u32(), "signal": signal.to_string()})),
+            Some(false) => bail!("Failed to send {} to pid {}", signal, pid),
+            None => bail!("Signal {} is not supported on this platform", signal),
+        }
+    }
+}
+
+async fn capture<R: AsyncRead + Unpin>(mut pipe: R, buffer: Arc<Mutex<OutputBuffer>>) {
+    let mut chunk = [0u8; 8192];
+    loop {
+        match pipe.read(&mut chunk).await {
+            Ok(0) | Err(_) => break,
+            Ok(n) => {
+                if let Ok(mut buffer) = buffer.lock() {
+                    buffer.push(&chunk[..n]);
+                }
+            }
+        }
+    }
+}
+
+fn prune_finished(children: &mut HashMap<String, ManagedProcess>) {
+    if children.len() < MAX_TRACKED {
+        return;
+    }
+    let mut finished: Vec<(String, DateTime<Utc>)> = children
+        .iter()
+        .filter(|(_, p)| p.exit.borrow().is_some())
+        .map(|(handle, p)| (handle.clone(), p.started_at))
+        .collect();
+    finished.sort_by_key(|(_, started)| *started);
+    let excess = children.len() + 1 - MAX_TRACKED;
+    for (handle, _) in finished.into_iter().take(excess) {
+        children.remove(&handle);
+    }
+}
+
+#[cfg(unix)]
+fn exit_signal(status: &std::process::ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.signal()
+}
+
+#[cfg(not(unix))]
+fn exit_signal(_status: &std::process::ExitStatus) -> Option<i32> {
+    None
+}
+
+fn parse_signal(name: &str) -> Result<Signal> {
+    let lower = name.to_lowercase();
+    let name = lower.strip_prefix("sig").unwrap_or(&lower);
+    let signal = match name {
+        "term" | "15" => Signal::Term,
+        "kill" | "9" => Signal::Kill,
+        "int" | "2" => Signal::Interrupt,
+        "hup" | "1" => Signal::Hangup,
+        "quit" | "3" => Signal::Quit,
+        "stop" => Signal::Stop,
+        "cont" | "continue" => Signal::Continue,
+        "usr1" => Signal::User1,
+        "usr2" => Signal::User2,
+        other => bail!("Unknown signal: {}", other),
+    };
+    Ok(signal)
+}
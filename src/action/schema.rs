@@ -0,0 +1,961 @@
+use schemars::{schema_for, JsonSchema};
+use serde::de::{self, MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Declares `Action` from one table of variant, parameter type and wire
+/// name, so that the serde tag, `name()` and `catalog()` cannot drift apart
+macro_rules! actions {
+    ($($variant:ident($params:ty) => $name:literal,)*) => {
+        /// Typed action request
+        ///
+        /// Serializes to the `{"action_type": "...", "params": {...}}` shape the API
+        /// has always accepted, so existing clients keep working. Unknown fields,
+        /// including any next to `action_type` and `params`, are rejected.
+        #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+        #[serde(tag = "action_type", content = "params", deny_unknown_fields)]
+        pub enum Action {
+            $(
+                #[serde(rename = $name)]
+                $variant($params),
+            )*
+        }
+
+        impl Action {
+            /// Wire name of this action, as used in `action_type`
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $(Action::$variant(_) => $name,)*
+                }
+            }
+
+            /// Every action type with the JSON Schema of its parameters
+            pub fn catalog() -> Vec<Value> {
+                vec![$(
+                    serde_json::json!({
+                        "action_type": $name,
+                        "params": schema_for!($params),
+                    }),
+                )*]
+            }
+        }
+    };
+}
+
+actions! {
+    MouseMove(MouseMoveParams) => "mouse_move",
+    Click(ClickParams) => "click",
+    Type(TypeParams) => "type",
+    Key(KeyParams) => "key",
+    Screenshot(ScreenshotParams) => "screenshot",
+    WindowOp(WindowOperation) => "window_operation",
+    FileOp(FileOperation) => "file_operation",
+    ProcessOp(ProcessOperation) => "process_operation",
+    SystemOp(SystemOperation) => "system_operation",
+    Undo(UndoOperation) => "undo",
+    ClipboardGet(ClipboardGetParams) => "clipboard_get",
+    ClipboardSet(ClipboardSetParams) => "clipboard_set",
+    WaitForWindow(WindowCondition) => "wait_for_window",
+    WaitForProcess(ProcessCondition) => "wait_for_process",
+    WaitForFile(FileCondition) => "wait_for_file",
+    WaitForPixelChange(PixelChangeCondition) => "wait_for_pixel_change",
+    WaitForText(TextCondition) => "wait_for_text",
+    AssertWindow(WindowCondition) => "assert_window",
+    AssertProcess(ProcessCondition) => "assert_process",
+    AssertFile(FileCondition) => "assert_file",
+    AssertPixelChange(PixelChangeCondition) => "assert_pixel_change",
+    AssertText(TextCondition) => "assert_text",
+    ReadText(ReadTextParams) => "read_text",
+    ClickText(ClickTextParams) => "click_text",
+    FindImage(FindImageParams) => "find_image",
+    ClickImage(ClickImageParams) => "click_image",
+    ScreenDiff(ScreenDiffParams) => "screen_diff",
+    AnnotateScreen(AnnotateScreenParams) => "annotate_screen",
+    ClickMark(ClickMarkParams) => "click_mark",
+    A11yTree(A11yTreeParams) => "a11y_tree",
+    A11yFind(A11yFindParams) => "a11y_find",
+    A11yPress(A11yPressParams) => "a11y_press",
+    A11ySetText(A11ySetTextParams) => "a11y_set_text",
+    A11yFocus(A11yQuery) => "a11y_focus",
+}
+
+/// Move the pointer to absolute screen coordinates
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct MouseMoveParams {
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Click at absolute screen coordinates
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ClickParams {
+    pub x: i32,
+    pub y: i32,
+    #[serde(default)]
+    pub button: MouseButton,
+    #[serde(default)]
+    pub double: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MouseButton {
+    #[default]
+    Left,
+    Right,
+    Middle,
+}
+
+/// Type a string of (Unicode) text
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct TypeParams {
+    pub text: String,
+}
+
+/// Press a key or key chord such as `ctrl+shift+t`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct KeyParams {
+    pub key: String,
+}
+
+/// Capture the screen, a single monitor, or a region of either
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenshotParams {
+    /// Draw a reticle over the pointer position
+    #[serde(default = "default_true")]
+    pub show_cursor: bool,
+    /// Index into the monitor list; the whole screen when omitted
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    /// Rectangle to capture, relative to the selected monitor (or the screen)
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+}
+
+impl Default for ScreenshotParams {
+    fn default() -> Self {
+        Self {
+            show_cursor: true,
+            monitor: None,
+            region: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenRegion {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Window operation, selected by the `operation` field
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "operation", rename_all = "snake_case", deny_unknown_fields)]
+pub enum WindowOperation {
+    List,
+    Focus {
+        #[serde(flatten)]
+        window: WindowSelector,
+        #[serde(flatten)]
+        #[schemars(skip)]
+        extra: NoExtraFields,
+    },
+    /// Move the window's top-left corner to (`x`, `y`)
+    Move {
+        #[serde(flatten)]
+        window: WindowSelector,
+        x: i32,
+        y: i32,
+        #[serde(flatten)]
+        #[schemars(skip)]
+        extra: NoExtraFields,
+    },
+    Resize {
+        #[serde(flatten)]
+        window: WindowSelector,
+        width: u32,
+        height: u32,
+        #[serde(flatten)]
+        #[schemars(skip)]
+        extra: NoExtraFields,
+    },
+    Minimize {
+        #[serde(flatten)]
+        window: WindowSelector,
+        #[serde(flatten)]
+        #[schemars(skip)]
+        extra: NoExtraFields,
+    },
+    Maximize {
+        #[serde(flatten)]
+        window: WindowSelector,
+        #[serde(flatten)]
+        #[schemars(skip)]
+        extra: NoExtraFields,
+    },
+    /// Undo minimize/maximize and raise the window
+    Restore {
+        #[serde(flatten)]
+        window: WindowSelector,
+        #[serde(flatten)]
+        #[schemars(skip)]
+        extra: NoExtraFields,
+    },
+    Close {
+        #[serde(flatten)]
+        window: WindowSelector,
+        #[serde(flatten)]
+        #[schemars(skip)]
+        extra: NoExtraFields,
+    },
+}
+
+/// Picks a window by id, or by the first window whose title/class contains the given text
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WindowSelector {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub class: Option<String>,
+}
+
+/// File operation, selected by the `operation` field
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "operation", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FileOperation {
+    /// Read a file, optionally only `length` bytes starting at `offset`
+    Read {
+        path: String,
+        #[serde(default)]
+        encoding: FileEncoding,
+        #[serde(default)]
+        offset: Option<u64>,
+        #[serde(default)]
+        length: Option<u64>,
+    },
+    /// Write a file, replacing any existing content
+    Write {
+        path: String,
+        content: String,
+        #[serde(default)]
+        encoding: FileEncoding,
+        #[serde(default)]
+        create_dirs: bool,
+    },
+    /// Append to a file, creating it if needed
+    Append {
+        path: String,
+        content: String,
+        #[serde(default)]
+        encoding: FileEncoding,
+    },
+    Mkdir {
+        path: String,
+        #[serde(default = "default_true")]
+        parents: bool,
+    },
+    /// Copy a file or directory tree
+    Copy {
+        from: String,
+        to: String,
+        #[serde(default)]
+        overwrite: bool,
+    },
+    Move {
+        from: String,
+        to: String,
+        #[serde(default)]
+        overwrite: bool,
+    },
+    Delete {
+        path: String,
+        #[serde(default)]
+        recursive: bool,
+    },
+    Stat {
+        path: String,
+    },
+    List {
+        path: String,
+        #[serde(default)]
+        include_hidden: bool,
+    },
+    /// Find paths matching a glob pattern such as `~/Downloads/**/*.pdf`
+    Glob {
+        pattern: String,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+}
+
+/// How file content is carried in `content` / returned from `read`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum FileEncoding {
+    #[default]
+    Text,
+    Base64,
+}
+
+/// Process operation, selected by the `operation` field
+///
+/// Processes started with `spawn` are tracked by the returned `handle`, which
+/// later `output`, `wait` and `signal` operations can refer to.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "operation", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ProcessOperation {
+    /// Start a command; with `wait` the call blocks until it exits
+    Spawn {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        #[serde(default)]
+        env: HashMap<String, String>,
+        #[serde(default)]
+        cwd: Option<String>,
+        /// Kill the process if it is still running after this many milliseconds
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+        #[serde(default)]
+        wait: bool,
+    },
+    /// List running processes, optionally filtered by name substring
+    List {
+        #[serde(default)]
+        name: Option<String>,
+        #[serde(default)]
+        limit: Option<usize>,
+    },
+    /// Captured stdout/stderr of a spawned process so far
+    Output {
+        handle: String,
+    },
+    /// Wait for a spawned process to exit
+    Wait {
+        handle: String,
+        #[serde(default)]
+        timeout_ms: Option<u64>,
+    },
+    /// Send a signal (`term`, `kill`, `int`, `hup`, `stop`, `cont`, ...) by handle or pid
+    Signal {
+        #[serde(default)]
+        handle: Option<String>,
+        #[serde(default)]
+        pid: Option<u32>,
+        #[serde(default = "default_signal")]
+        signal: String,
+    },
+}
+
+/// Undo earlier actions, selected by the `operation` field
+///
+/// Only actions whose result was flagged `reversible` can be undone.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "operation", rename_all = "snake_case", deny_unknown_fields)]
+pub enum UndoOperation {
+    /// Undo the `count` most recent actions not undone yet, newest first
+    Last {
+        #[serde(default = "default_one")]
+        count: usize,
+    },
+    /// Undo one action by the `action_id` its result carried
+    ///
+    /// When a newer action that has not been undone touched the same paths,
+    /// window or clipboard, this is refused unless `force` is set.
+    Action {
+        id: String,
+        #[serde(default)]
+        force: bool,
+    },
+}
+
+/// Read the clipboard as text or as a PNG image
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ClipboardGetParams {
+    #[serde(default)]
+    pub format: ClipboardFormat,
+    /// Images only: write the PNG here instead of returning it base64-encoded
+    #[serde(default)]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ClipboardFormat {
+    #[default]
+    Text,
+    Image,
+}
+
+/// Replace the clipboard contents, selected by the `format` field
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "format", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ClipboardSetParams {
+    Text { text: String },
+    /// An image from `path` or base64 `data`; non-PNG images are converted
+    Image {
+        #[serde(default)]
+        path: Option<String>,
+        #[serde(default)]
+        data: Option<String>,
+    },
+}
+
+/// How long a `wait_for_*` or `assert_*` action keeps polling its condition
+///
+/// `timeout_ms` defaults to 10 s for waits and to a single check for
+/// assertions.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct WaitTiming {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout_ms: Option<u64>,
+    #[serde(default = "default_poll_interval")]
+    pub interval_ms: u64,
+}
+
+/// A window matching `window` exists (is focused, or with `absent` is gone)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct WindowCondition {
+    #[serde(flatten)]
+    pub window: WindowSelector,
+    #[serde(default)]
+    pub focused: bool,
+    #[serde(default)]
+    pub absent: bool,
+    #[serde(flatten)]
+    pub timing: WaitTiming,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: NoExtraFields,
+}
+
+/// A process whose name contains `name`, or with the given `pid`, is running
+/// (or with `absent` has exited)
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ProcessCondition {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    #[serde(default)]
+    pub absent: bool,
+    #[serde(flatten)]
+    pub timing: WaitTiming,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: NoExtraFields,
+}
+
+/// A file exists (or with `absent` does not), optionally with a minimum size
+/// or containing some text
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct FileCondition {
+    pub path: String,
+    #[serde(default)]
+    pub absent: bool,
+    #[serde(default)]
+    pub min_size: Option<u64>,
+    #[serde(default)]
+    pub contains: Option<String>,
+    #[serde(flatten)]
+    pub timing: WaitTiming,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: NoExtraFields,
+}
+
+/// Part of the screen changes compared to when the action started
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct PixelChangeCondition {
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+    /// Fraction of pixels (0-1) that must differ
+    #[serde(default = "default_change_threshold")]
+    pub threshold: f64,
+    #[serde(flatten)]
+    pub timing: WaitTiming,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: NoExtraFields,
+}
+
+/// Text is visible on screen (or with `absent` is not), read through OCR
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct TextCondition {
+    pub text: String,
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    #[serde(default)]
+    pub absent: bool,
+    #[serde(flatten)]
+    pub timing: WaitTiming,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: NoExtraFields,
+}
+
+/// Recognize the text on screen, with word and line boxes in screen coordinates
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ReadTextParams {
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+    /// Words recognized with less confidence (0-100) are dropped
+    #[serde(default)]
+    pub min_confidence: f32,
+}
+
+/// Find text on screen through OCR and click its center
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ClickTextParams {
+    /// A word or phrase; a phrase must appear on one line
+    pub text: String,
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+    #[serde(default)]
+    pub case_sensitive: bool,
+    /// Which match to click, in reading order
+    #[serde(default)]
+    pub occurrence: usize,
+    #[serde(default)]
+    pub min_confidence: f32,
+    #[serde(default)]
+    pub button: MouseButton,
+    #[serde(default)]
+    pub double: bool,
+}
+
+/// Find every place an image (e.g. a cropped screenshot of a button) appears
+/// on screen
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct FindImageParams {
+    /// Image file to look for
+    pub path: String,
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+    /// Minimum match score, 0-1
+    #[serde(default = "default_match_threshold")]
+    pub threshold: f32,
+    /// Sizes to try, relative to the image; 1.0 is its own size
+    #[serde(default = "default_match_scales")]
+    pub scales: Vec<f32>,
+}
+
+/// Find an image on screen and click the center of the best match
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct ClickImageParams {
+    #[serde(flatten)]
+    pub image: FindImageParams,
+    #[serde(default)]
+    pub button: MouseButton,
+    #[serde(default)]
+    pub double: bool,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: NoExtraFields,
+}
+
+/// Compare a saved screenshot with another one, or with the screen as it is
+/// now, reporting how much changed and where
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ScreenDiffParams {
+    /// Screenshot taken earlier, e.g. by the `screenshot` action
+    pub before: String,
+    /// Screenshot to compare with; the live screen when omitted
+    #[serde(default)]
+    pub after: Option<String>,
+    /// What to capture when comparing with the live screen
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+}
+
+/// What numbered marks are drawn over in an annotated screenshot
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum MarkSource {
+    /// Lines of text found by OCR
+    Text,
+    /// The visible part of each top-level window
+    Windows,
+    /// Accessibility nodes that can be pressed or edited
+    Accessibility,
+}
+
+/// Take a screenshot annotated for a vision model: numbered marks over UI
+/// elements and/or a labelled coordinate grid, with a table mapping each
+/// label to screen coordinates for `click_mark`
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct AnnotateScreenParams {
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+    /// What to mark; empty for a grid only
+    #[serde(default = "default_mark_sources")]
+    pub marks: Vec<MarkSource>,
+    /// Side of the grid cells in pixels; no grid when omitted
+    #[serde(default)]
+    pub grid: Option<u32>,
+    /// OCR words recognized with less confidence (0-100) are not marked
+    #[serde(default)]
+    pub min_confidence: f32,
+}
+
+/// A mark number such as `17`, or a grid cell such as `C7`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum MarkLabel {
+    Number(u32),
+    Label(String),
+}
+
+impl std::fmt::Display for MarkLabel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarkLabel::Number(number) => write!(f, "{}", number),
+            MarkLabel::Label(label) => f.write_str(label),
+        }
+    }
+}
+
+/// Click the center of a mark or grid cell from an annotated screenshot;
+/// window marks focus their window first, and only focus it when other
+/// windows have since covered the mark
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ClickMarkParams {
+    pub mark: MarkLabel,
+    /// Id of the annotation the label comes from; the latest when omitted
+    #[serde(default)]
+    pub annotation: Option<String>,
+    #[serde(default)]
+    pub button: MouseButton,
+    #[serde(default)]
+    pub double: bool,
+}
+
+/// Which accessibility nodes to act on; every field that is set must match
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct A11yQuery {
+    /// Node id from `a11y_tree` or `a11y_find`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Role name such as `push button` or `text`, case-insensitive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    /// Substring of the accessible name, case-insensitive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Substring of the application name, case-insensitive
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app: Option<String>,
+    /// States the node must have, e.g. `["showing", "enabled"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub states: Vec<String>,
+}
+
+/// Read the accessibility tree of running applications
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct A11yTreeParams {
+    /// Substring of the application name; every application when omitted
+    #[serde(default)]
+    pub app: Option<String>,
+    /// Levels below each application to include
+    #[serde(default = "default_a11y_depth")]
+    pub max_depth: usize,
+}
+
+/// Search the accessibility tree
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct A11yFindParams {
+    #[serde(flatten)]
+    pub query: A11yQuery,
+    #[serde(default = "default_a11y_limit")]
+    pub limit: usize,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: NoExtraFields,
+}
+
+/// Invoke an action (press, click, activate, ...) on the one node matching
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct A11yPressParams {
+    #[serde(flatten)]
+    pub target: A11yQuery,
+    /// Action name as the node reports it; its first action when omitted
+    #[serde(default)]
+    pub action: Option<String>,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: NoExtraFields,
+}
+
+/// Replace the text of the one editable node matching
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct A11ySetTextParams {
+    #[serde(flatten)]
+    pub target: A11yQuery,
+    pub text: String,
+    #[serde(flatten)]
+    #[schemars(skip)]
+    pub extra: NoExtraFields,
+}
+
+/// System operation, selected by the `operation` field
+///
+/// Every system operation is written to the audit log. Queries always run;
+/// changes are subject to the policy, and `set_brightness`, `shutdown`,
+/// `restart` and `set_env` of `PATH` or `LD_*` also need `enable_kernel_ops`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "operation", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SystemOperation {
+    /// Hostname, OS and kernel version, uptime, load average and memory
+    Info,
+    /// Mounted filesystems with their disk usage
+    Mounts,
+    /// Network interfaces with their addresses and traffic counters
+    NetworkInterfaces,
+    /// Environment applied to every process spawned from now on; `unset`
+    /// removes variables, and the result lists all current overrides
+    SetEnv {
+        #[serde(default)]
+        set: HashMap<String, String>,
+        #[serde(default)]
+        unset: Vec<String>,
+    },
+    /// Output volume of the default audio sink, in percent, and/or mute
+    SetVolume {
+        #[serde(default)]
+        percent: Option<u8>,
+        #[serde(default)]
+        muted: Option<bool>,
+    },
+    /// Backlight brightness in percent, of `device` or the first backlight
+    SetBrightness {
+        percent: u8,
+        #[serde(default)]
+        device: Option<String>,
+    },
+    /// Stop digiOS itself, shortly after replying
+    Shutdown,
+    /// Restart digiOS itself, shortly after replying
+    Restart,
+}
+
+impl FileOperation {
+    /// A copy without the file contents, for logs and persisted records
+    pub fn redacted(&self) -> Self {
+        let mut operation = self.clone();
+        if let FileOperation::Write { content, .. } | FileOperation::Append { content, .. } =
+            &mut operation
+        {
+            redact(content);
+        }
+        operation
+    }
+}
+
+impl ProcessOperation {
+    /// A copy without environment values, which often carry credentials
+    pub fn redacted(&self) -> Self {
+        let mut operation = self.clone();
+        if let ProcessOperation::Spawn { env, .. } = &mut operation {
+            env.values_mut().for_each(redact);
+        }
+        operation
+    }
+}
+
+impl SystemOperation {
+    /// Wire name of the operation, as used in the `operation` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            SystemOperation::Info => "info",
+            SystemOperation::Mounts => "mounts",
+            SystemOperation::NetworkInterfaces => "network_interfaces",
+            SystemOperation::SetEnv { .. } => "set_env",
+            SystemOperation::SetVolume { .. } => "set_volume",
+            SystemOperation::SetBrightness { .. } => "set_brightness",
+            SystemOperation::Shutdown => "shutdown",
+            SystemOperation::Restart => "restart",
+        }
+    }
+
+    /// A copy without the values of `set_env`
+    pub fn redacted(&self) -> Self {
+        let mut operation = self.clone();
+        if let SystemOperation::SetEnv { set, .. } = &mut operation {
+            set.values_mut().for_each(redact);
+        }
+        operation
+    }
+
+    /// Whether the operation only queries the system
+    pub fn is_query(&self) -> bool {
+        matches!(
+            self,
+            SystemOperation::Info | SystemOperation::Mounts | SystemOperation::NetworkInterfaces
+        )
+    }
+}
+
+/// Rejects every field left over once the other fields of a struct have
+/// taken theirs
+///
+/// serde's `deny_unknown_fields` does not work on structs with
+/// `#[serde(flatten)]` fields, so those end with a flattened `NoExtraFields`
+/// instead. Flattened structs claim their own fields first, and whatever
+/// reaches this one was misspelled or unknown.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NoExtraFields;
+
+impl<'de> Deserialize<'de> for NoExtraFields {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Empty;
+
+        impl<'de> Visitor<'de> for Empty {
+            type Value = NoExtraFields;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("no further fields")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<NoExtraFields, A::Error> {
+                match map.next_key::<String>()? {
+                    Some(field) => Err(de::Error::custom(format!("unknown field `{}`", field))),
+                    None => Ok(NoExtraFields),
+                }
+            }
+        }
+
+        deserializer.deserialize_map(Empty)
+    }
+}
+
+impl Serialize for NoExtraFields {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_map(Some(0))?.end()
+    }
+}
+
+/// Replace a secret-bearing string with a note of its length
+pub fn redact(value: &mut String) {
+    *value = format!("[redacted: {} chars]", value.chars().count());
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_one() -> usize {
+    1
+}
+
+fn default_signal() -> String {
+    "term".to_string()
+}
+
+fn default_poll_interval() -> u64 {
+    250
+}
+
+fn default_change_threshold() -> f64 {
+    0.01
+}
+
+fn default_match_threshold() -> f32 {
+    0.8
+}
+
+fn default_a11y_depth() -> usize {
+    8
+}
+
+fn default_a11y_limit() -> usize {
+    50
+}
+
+fn default_mark_sources() -> Vec<MarkSource> {
+    vec![MarkSource::Windows, MarkSource::Text]
+}
+
+fn default_match_scales() -> Vec<f32> {
+    vec![1.0, 0.75, 1.25, 0.5, 1.5, 2.0]
+}
+
+impl Action {
+    /// Whether this is an `assert_*` action, whose failure aborts a plan
+    pub fn is_assertion(&self) -> bool {
+        matches!(
+            self,
+            Action::AssertWindow(_)
+                | Action::AssertProcess(_)
+                | Action::AssertFile(_)
+                | Action::AssertPixelChange(_)
+                | Action::AssertText(_)
+        )
+    }
+
+    /// A copy safe to persist or log: typed text, file and clipboard contents
+    /// and environment values are replaced by a note of their length
+    pub fn redacted(&self) -> Action {
+        match self {
+            Action::FileOp(operation) => Action::FileOp(operation.redacted()),
+            Action::ProcessOp(operation) => Action::ProcessOp(operation.redacted()),
+            Action::SystemOp(operation) => Action::SystemOp(operation.redacted()),
+            _ => {
+                let mut action = self.clone();
+                match &mut action {
+                    Action::Type(TypeParams { text })
+                    | Action::A11ySetText(A11ySetTextParams { text, .. })
+                    | Action::ClipboardSet(ClipboardSetParams::Text { text })
+                    | Action::ClipboardSet(ClipboardSetParams::Image { data: Some(text), .. }) => {
+                        redact(text)
+                    }
+                    _ => {}
+                }
+                action
+            }
+        }
+    }
+
+    /// JSON Schema for the full `Action` type
+    pub fn json_schema() -> Value {
+        serde_json::to_value(schema_for!(Action)).unwrap_or(Value::Null)
+    }
+}
@@ -0,0 +1,721 @@
+use crate::action::input::parse_chord;
+use crate::action::process_ops::ProcessManager;
+use crate::action::schema::{
+    A11yQuery, Action, AnnotateScreenParams, ClipboardFormat, ClipboardGetParams, ClipboardSetParams, FileEncoding,
+    FileOperation, ProcessOperation, ScreenshotParams, WindowOperation,
+};
+use crate::action::wait::Condition;
+use crate::core::paths::expand_home;
+use crate::state::{WindowInfo, WindowSystem};
+use crate::vision::{accessibility, capture};
+use crate::vision::marks::MIN_GRID_CELL;
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use sysinfo::{Pid, System};
+
+/// One side effect an action is predicted to have
+#[derive(Debug, Clone, Serialize)]
+pub struct Effect {
+    pub kind: &'static str,
+    pub target: String,
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub detail: Value,
+}
+
+/// What an action would do, and what would make it fail
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Prediction {
+    pub effects: Vec<Effect>,
+    /// Conditions under which the real run would fail
+    pub problems: Vec<String>,
+    /// Things worth a second look that would not stop the action
+    pub warnings: Vec<String>,
+}
+
+impl Prediction {
+    fn effect(&mut self, kind: &'static str, target: impl ToString, detail: Value) {
+        self.effects.push(Effect {
+            kind,
+            target: target.to_string(),
+            detail,
+        });
+    }
+}
+
+/// Simulator - Predicts the effects of actions without performing them
+///
+/// Filesystem predictions are made against an overlay of paths earlier
+/// simulated actions created or removed, so a plan that creates a directory
+/// and then writes into it is not reported as failing.
+#[derive(Default)]
+pub struct Simulator {
+    created: HashSet<PathBuf>,
+    removed: HashSet<PathBuf>,
+}
+
+impl Simulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn predict(
+        &mut self,
+        action: &Action,
+        processes: &ProcessManager,
+        screenshot_dir: &Path,
+    ) -> Prediction {
+        let mut prediction = Prediction::default();
+        match action {
+            Action::MouseMove(params) => {
+                check_on_screen(params.x, params.y, &mut prediction).await;
+                prediction.effect(
+                    "pointer_move",
+                    format!("({}, {})", params.x, params.y),
+                    Value::Null,
+                );
+            }
+            Action::Click(params) => {
+                check_on_screen(params.x, params.y, &mut prediction).await;
+                let windows = windows_at(params.x, params.y).await;
+                prediction.effect(
+                    "click",
+                    format!("({}, {})", params.x, params.y),
+                    json!({"button": params.button, "double": params.double, "windows_at_point": windows}),
+                );
+            }
+            Action::ClickText(params) => {
+                // Where the text is can only be known by reading the screen
+                prediction.effect(
+                    "click",
+                    format!("text {:?}", params.text),
+                    json!({"button": params.button, "double": params.double, "occurrence": params.occurrence}),
+                );
+            }
+            Action::FindImage(params) => {
+                self.check_input(&params.path, &mut prediction);
+                prediction.effect(
+                    "template_match",
+                    "screen",
+                    json!({"image": params.path, "monitor": params.monitor, "region": params.region}),
+                );
+            }
+            Action::ClickImage(params) => {
+                // Like click_text, the target is only known once the screen is searched
+                self.check_input(&params.image.path, &mut prediction);
+                prediction.effect(
+                    "click",
+                    format!("image {}", params.image.path),
+                    json!({"button": params.button, "double": params.double, "threshold": params.image.threshold}),
+                );
+            }
+            Action::ScreenDiff(params) => {
+                for path in std::iter::once(&params.before).chain(&params.after) {
+                    self.check_input(path, &mut prediction);
+                }
+                let after = params.after.as_deref().unwrap_or("screen");
+                prediction.effect("screen_diff", format!("{} vs {}", params.before, after), Value::Null);
+            }
+            Action::AnnotateScreen(params) => {
+                predict_annotation(params, screenshot_dir, &mut prediction).await
+            }
+            Action::ClickMark(params) => {
+                // Marks only resolve against an annotation taken for real
+                prediction.effect(
+                    "click",
+                    format!("mark {}", params.mark),
+                    json!({"button": params.button, "double": params.double, "annotation": params.annotation}),
+                );
+            }
+            Action::A11yTree(params) => {
+                let app = params.app.as_deref().unwrap_or("all applications");
+                prediction.effect("accessibility_query", app, json!({"max_depth": params.max_depth}));
+            }
+            Action::A11yFind(params) => {
+                prediction.effect("accessibility_query", "nodes", json!({"query": params.query, "limit": params.limit}));
+            }
+            Action::A11yPress(params) => {
+                let target = check_accessible(&params.target, &mut prediction).await;
+                prediction.effect("accessibility_action", target, json!({"action": params.action}));
+            }
+            Action::A11ySetText(params) => {
+                let target = check_accessible(&params.target, &mut prediction).await;
+                prediction.effect(
+                    "accessibility_set_text",
+                    target,
+                    json!({"characters": params.text.chars().count()}),
+                );
+            }
+            Action::A11yFocus(query) => {
+                let target = check_accessible(query, &mut prediction).await;
+                prediction.effect("accessibility_focus", target, Value::Null);
+            }
+            Action::ReadText(params) => {
+                prediction.effect("ocr", "screen", json!({"monitor": params.monitor, "region": params.region}));
+            }
+            Action::Type(params) => {
+                let target = focused_window().await;
+                prediction.effect(
+                    "keyboard_input",
+                    describe_window(target.as_ref()),
+                    json!({"characters": params.text.chars().count()}),
+                );
+            }
+            Action::Key(params) => {
+                if let Err(e) = parse_chord(&params.key) {
+                    prediction.problems.push(format!("{:#}", e));
+                }
+                let target = focused_window().await;
+                prediction.effect(
+                    "keyboard_input",
+                    describe_window(target.as_ref()),
+                    json!({"chord": params.key}),
+                );
+            }
+            Action::Screenshot(params) => {
+                predict_screenshot(params, screenshot_dir, &mut prediction).await
+            }
+            Action::WindowOp(operation) => predict_window(operation, &mut prediction).await,
+            Action::FileOp(operation) => self.predict_file(operation, &mut prediction),
+            Action::ProcessOp(operation) => predict_process(operation, processes, &mut prediction),
+            Action::SystemOp(operation) => {
+                let kind = if operation.is_query() { "system_query" } else { "system_change" };
+                prediction.effect(kind, operation.name(), json!(operation));
+            }
+            Action::Undo(operation) => {
+                prediction.effect("undo", format!("{:?}", operation), Value::Null);
+            }
+            Action::ClipboardGet(params) => self.predict_clipboard_get(params, &mut prediction),
+            Action::ClipboardSet(params) => self.predict_clipboard_set(params, &mut prediction),
+            Action::WaitForWindow(_)
+            | Action::WaitForProcess(_)
+            | Action::WaitForFile(_)
+            | Action::WaitForPixelChange(_)
+            | Action::WaitForText(_)
+            | Action::AssertWindow(_)
+            | Action::AssertProcess(_)
+            | Action::AssertFile(_)
+            | Action::AssertPixelChange(_)
+            | Action::AssertText(_) => {
+                if let Some(condition) = Condition::of(action) {
+                    self.predict_wait(&condition, action.is_assertion(), &mut prediction);
+                }
+            }
+        }
+        prediction
+    }
+
+    fn predict_wait(&self, condition: &Condition, assertion: bool, prediction: &mut Prediction) {
+        let kind = if assertion { "assert" } else { "wait" };
+        prediction.effect(kind, condition.describe(), json!(condition.timing()));
+
+        // A file assertion without a timeout checks once, so the simulated
+        // filesystem already tells whether it would pass
+        if let Condition::File(c) = condition {
+            if assertion && c.timing.timeout_ms.unwrap_or(0) == 0 {
+                let exists = self.exists(&expand_home(&c.path));
+                if exists == c.absent {
+                    prediction
+                        .problems
+                        .push(format!("Assertion would fail: expected {}", condition.describe()));
+                }
+            }
+        }
+    }
+
+    fn predict_clipboard_get(&mut self, params: &ClipboardGetParams, prediction: &mut Prediction) {
+        prediction.effect("clipboard_read", format!("{:?}", params.format).to_lowercase(), Value::Null);
+        let Some(path) = &params.path else {
+            return;
+        };
+        if params.format != ClipboardFormat::Image {
+            prediction.warnings.push("path is ignored when reading text".to_string());
+            return;
+        }
+        let target = expand_home(path);
+        let kind = if self.exists(&target) {
+            "overwrite_file"
+        } else {
+            "create_file"
+        };
+        prediction.effect(kind, target.display(), Value::Null);
+        self.create(&target);
+    }
+
+    fn predict_clipboard_set(&self, params: &ClipboardSetParams, prediction: &mut Prediction) {
+        match params {
+            ClipboardSetParams::Text { text } => {
+                prediction.effect("clipboard_write", "text", json!({"characters": text.chars().count()}));
+            }
+            ClipboardSetParams::Image { path, data } => {
+                match (path, data) {
+                    (Some(path), None) => {
+                        let source = expand_home(path);
+                        if !self.exists(&source) {
+                            prediction
+                                .problems
+                                .push(format!("{} does not exist", source.display()));
+                        }
+                    }
+                    (None, Some(_)) => {}
+                    _ => prediction
+                        .problems
+                        .push("Image clipboard_set needs exactly one of path or data".to_string()),
+                }
+                prediction.effect("clipboard_write", "image", Value::Null);
+            }
+        }
+    }
+
+    fn check_input(&self, path: &str, prediction: &mut Prediction) {
+        let image = expand_home(path);
+        if !self.exists(&image) {
+            prediction
+                .problems
+                .push(format!("{} does not exist", image.display()));
+        }
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        if self.removed.iter().any(|r| path.starts_with(r)) {
+            return self.created.contains(path);
+        }
+        self.created.contains(path) || path.exists()
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        self.exists(path)
+            && (path.is_dir()
+                || self
+                    .created
+                    .iter()
+                    .any(|c| c != path && c.starts_with(path)))
+    }
+
+    fn create(&mut self, path: &Path) {
+        self.removed.remove(path);
+        self.created.insert(path.to_path_buf());
+    }
+
+    fn remove(&mut self, path: &Path) {
+        self.created.retain(|c| !c.starts_with(path));
+        self.removed.insert(path.to_path_buf());
+    }
+
+    fn predict_file(&mut self, operation: &FileOperation, prediction: &mut Prediction) {
+        // Only an action predicted to succeed changes the overlay
+        let problems = prediction.problems.len();
+        let mut created = Vec::new();
+        let mut removed = Vec::new();
+        match operation {
+            FileOperation::Read { path, .. }
+            | FileOperation::Stat { path }
+            | FileOperation::List { path, .. } => {
+                let target = expand_home(path);
+                if !self.exists(&target) {
+                    prediction
+                        .problems
+                        .push(format!("{} does not exist", target.display()));
+                }
+            }
+            FileOperation::Glob { .. } => {}
+            FileOperation::Write {
+                path,
+                create_dirs,
+                content,
+                encoding,
+            } => {
+                let target = expand_home(path);
+                let bytes = match content_length(content, *encoding) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        prediction.problems.push(format!("{:#}", e));
+                        return;
+                    }
+                };
+                self.check_parent(&target, *create_dirs, prediction);
+                let kind = if self.exists(&target) {
+                    "overwrite_file"
+                } else {
+                    "create_file"
+                };
+                prediction.effect(kind, target.display(), json!({"bytes": bytes}));
+                created.push(target);
+            }
+            FileOperation::Append { path, content, encoding } => {
+                let target = expand_home(path);
+                let bytes = match content_length(content, *encoding) {
+                    Ok(bytes) => bytes,
+                    Err(e) => {
+                        prediction.problems.push(format!("{:#}", e));
+                        return;
+                    }
+                };
+                self.check_parent(&target, false, prediction);
+                let kind = if self.exists(&target) {
+                    "append_file"
+                } else {
+                    "create_file"
+                };
+                prediction.effect(kind, target.display(), json!({"bytes": bytes}));
+                created.push(target);
+            }
+            FileOperation::Mkdir { path, parents } => {
+                let target = expand_home(path);
+                if self.exists(&target) {
+                    if !*parents {
+                        prediction
+                            .problems
+                            .push(format!("{} already exists", target.display()));
+                    }
+                    return;
+                }
+                self.check_parent(&target, *parents, prediction);
+                prediction.effect("create_directory", target.display(), Value::Null);
+                created.push(target);
+            }
+            FileOperation::Copy {
+                from,
+                to,
+                overwrite,
+            }
+            | FileOperation::Move {
+                from,
+                to,
+                overwrite,
+            } => {
+                let (source, dest) = (expand_home(from), expand_home(to));
+                if !self.exists(&source) {
+                    prediction
+                        .problems
+                        .push(format!("{} does not exist", source.display()));
+                }
+                if self.exists(&dest) {
+                    if *overwrite {
+                        prediction.effect("overwrite_file", dest.display(), Value::Null);
+                    } else {
+                        prediction.problems.push(format!(
+                            "{} already exists; set overwrite to replace it",
+                            dest.display()
+                        ));
+                    }
+                }
+                let moving = matches!(operation, FileOperation::Move { .. });
+                let detail = tree_summary(&source);
+                prediction.effect(
+                    if moving { "move" } else { "copy" },
+                    format!("{} -> {}", source.display(), dest.display()),
+                    detail,
+                );
+                if moving {
+                    removed.push(source);
+                }
+                created.push(dest);
+            }
+            FileOperation::Delete { path, recursive } => {
+                let target = expand_home(path);
+                if !self.exists(&target) {
+                    prediction
+                        .problems
+                        .push(format!("{} does not exist", target.display()));
+                    return;
+                }
+                if self.is_dir(&target)
+                    && !*recursive
+                    && fs::read_dir(&target).is_ok_and(|mut d| d.next().is_some())
+                {
+                    prediction.problems.push(format!(
+                        "{} is a non-empty directory; set recursive to delete it",
+                        target.display()
+                    ));
+                }
+                prediction.effect("delete", target.display(), tree_summary(&target));
+                removed.push(target);
+            }
+        }
+        if prediction.problems.len() == problems {
+            for path in removed {
+                self.remove(&path);
+            }
+            for path in created {
+                self.create(&path);
+            }
+        }
+    }
+
+    fn check_parent(&self, target: &Path, create_dirs: bool, prediction: &mut Prediction) {
+        let Some(parent) = target.parent().filter(|p| !p.as_os_str().is_empty()) else {
+            return;
+        };
+        if self.exists(parent) {
+            return;
+        }
+        if create_dirs {
+            prediction.effect("create_directory", parent.display(), Value::Null);
+        } else {
+            prediction
+                .problems
+                .push(format!("{} does not exist", parent.display()));
+        }
+    }
+}
+
+/// File count and total size below `path`, as far as it exists on disk
+/// Bytes `content` writes once decoded
+fn content_length(content: &str, encoding: FileEncoding) -> Result<usize> {
+    match encoding {
+        FileEncoding::Text => Ok(content.len()),
+        FileEncoding::Base64 => Ok(BASE64.decode(content).context("Invalid base64 content")?.len()),
+    }
+}
+
+fn tree_summary(path: &Path) -> Value {
+    fn walk(path: &Path, files: &mut u64, bytes: &mut u64) {
+        let Ok(meta) = fs::symlink_metadata(path) else {
+            return;
+        };
+        if meta.is_dir() {
+            for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+                walk(&entry.path(), files, bytes);
+            }
+        } else {
+            *files += 1;
+            *bytes += meta.len();
+        }
+    }
+    let (mut files, mut bytes) = (0, 0);
+    walk(path, &mut files, &mut bytes);
+    if files == 0 {
+        return Value::Null;
+    }
+    json!({"files": files, "bytes": bytes})
+}
+
+async fn predict_screenshot(params: &ScreenshotParams, dir: &Path, prediction: &mut Prediction) {
+    check_monitor(params.monitor, prediction).await;
+    prediction.effect(
+        "create_file",
+        dir.join("screenshot_<timestamp>.png").display(),
+        Value::Null,
+    );
+}
+
+async fn predict_annotation(params: &AnnotateScreenParams, dir: &Path, prediction: &mut Prediction) {
+    check_monitor(params.monitor, prediction).await;
+    if let Some(cell_size) = params.grid.filter(|&size| size < MIN_GRID_CELL) {
+        prediction.problems.push(format!(
+            "Grid cells must be at least {} pixels, got {}",
+            MIN_GRID_CELL, cell_size
+        ));
+    }
+    prediction.effect(
+        "create_file",
+        dir.join("annotated_<timestamp>.png").display(),
+        json!({"marks": params.marks, "grid": params.grid}),
+    );
+}
+
+/// Look the target node up without touching it; it must match exactly one
+async fn check_accessible(target: &A11yQuery, prediction: &mut Prediction) -> String {
+    let query = serde_json::to_string(target).unwrap_or_default();
+    let problem = match accessibility::find(target, 2).await {
+        Ok(nodes) => match nodes.as_slice() {
+            [node] => return format!("{} {:?} ({})", node.role, node.name, node.id),
+            [] => format!("No accessible node matches {}", query),
+            _ => format!("More than one accessible node matches {}", query),
+        },
+        Err(e) => format!("{:#}", e),
+    };
+    prediction.problems.push(problem);
+    query
+}
+
+async fn check_monitor(monitor: Option<usize>, prediction: &mut Prediction) {
+    if let Some(index) = monitor {
+        let monitors = tokio::task::spawn_blocking(capture::monitors)
+            .await
+            .ok()
+            .and_then(|m| m.ok())
+            .unwrap_or_default();
+        if index >= monitors.len() {
+            prediction
+                .problems
+                .push(format!("No monitor {} (found {})", index, monitors.len()));
+        }
+    }
+}
+
+async fn windows() -> Vec<WindowInfo> {
+    tokio::task::spawn_blocking(WindowSystem::list)
+        .await
+        .ok()
+        .and_then(|w| w.ok())
+        .unwrap_or_default()
+}
+
+async fn focused_window() -> Option<WindowInfo> {
+    windows().await.into_iter().find(|w| w.focused)
+}
+
+async fn windows_at(x: i32, y: i32) -> Vec<WindowInfo> {
+    windows()
+        .await
+        .into_iter()
+        .filter(|w| {
+            !w.minimized
+                && x >= w.x
+                && y >= w.y
+                && x < w.x + w.width as i32
+                && y < w.y + w.height as i32
+        })
+        .collect()
+}
+
+fn describe_window(window: Option<&WindowInfo>) -> String {
+    match window {
+        Some(w) => format!("window {} ({}: {})", w.id, w.class, w.title),
+        None => "focused window".to_string(),
+    }
+}
+
+async fn check_on_screen(x: i32, y: i32, prediction: &mut Prediction) {
+    let Ok(Ok(monitors)) = tokio::task::spawn_blocking(capture::monitors).await else {
+        return;
+    };
+    let inside = monitors
+        .iter()
+        .any(|m| x >= m.x && y >= m.y && x < m.x + m.width as i32 && y < m.y + m.height as i32);
+    if !inside && !monitors.is_empty() {
+        prediction
+            .warnings
+            .push(format!("({}, {}) is outside every monitor", x, y));
+    }
+}
+
+async fn predict_window(operation: &WindowOperation, prediction: &mut Prediction) {
+    let (kind, selector) = match operation {
+        WindowOperation::List => return,
+        WindowOperation::Focus { window, .. } => ("focus_window", window),
+        WindowOperation::Move { window, .. } => ("move_window", window),
+        WindowOperation::Resize { window, .. } => ("resize_window", window),
+        WindowOperation::Minimize { window, .. } => ("minimize_window", window),
+        WindowOperation::Maximize { window, .. } => ("maximize_window", window),
+        WindowOperation::Restore { window, .. } => ("restore_window", window),
+        WindowOperation::Close { window, .. } => ("close_window", window),
+    };
+    let selector = selector.clone();
+    match tokio::task::spawn_blocking(move || WindowSystem::find(&selector)).await {
+        Ok(Ok(window)) => {
+            let detail = match operation {
+                WindowOperation::Move { x, y, .. } => {
+                    json!({"from": [window.x, window.y], "to": [x, y]})
+                }
+                WindowOperation::Resize { width, height, .. } => {
+                    json!({"from": [window.width, window.height], "to": [width, height]})
+                }
+                _ => Value::Null,
+            };
+            prediction.effect(kind, describe_window(Some(&window)), detail);
+        }
+        Ok(Err(e)) => prediction.problems.push(format!("{:#}", e)),
+        Err(e) => prediction.problems.push(e.to_string()),
+    }
+}
+
+fn predict_process(
+    operation: &ProcessOperation,
+    processes: &ProcessManager,
+    prediction: &mut Prediction,
+) {
+    match operation {
+        ProcessOperation::Spawn {
+            command,
+            args,
+            cwd,
+            timeout_ms,
+            ..
+        } => {
+            match resolve_command(command) {
+                Some(path) => prediction.effect(
+                    "spawn_process",
+                    path.display(),
+                    json!({"args": args, "timeout_ms": timeout_ms}),
+                ),
+                None => prediction
+                    .problems
+                    .push(format!("{} not found on PATH", command)),
+            }
+            if let Some(cwd) = cwd {
+                let dir = expand_home(cwd);
+                if !dir.is_dir() {
+                    prediction
+                        .problems
+                        .push(format!("{} is not a directory", dir.display()));
+                }
+            }
+        }
+        ProcessOperation::List { .. } => {}
+        ProcessOperation::Output { handle } | ProcessOperation::Wait { handle, .. } => {
+            if !processes.knows(handle) {
+                prediction
+                    .problems
+                    .push(format!("Unknown process handle: {}", handle));
+            }
+        }
+        ProcessOperation::Signal {
+            handle,
+            pid,
+            signal,
+        } => {
+            let pid = match (handle, pid) {
+                (Some(handle), _) => match processes.pid_of(handle) {
+                    Ok(pid) => pid,
+                    Err(e) => {
+                        prediction.problems.push(format!("{:#}", e));
+                        return;
+                    }
+                },
+                (None, Some(pid)) => *pid,
+                (None, None) => {
+                    prediction
+                        .problems
+                        .push("signal requires a handle or a pid".to_string());
+                    return;
+                }
+            };
+            let mut system = System::new();
+            if system.refresh_process(Pid::from_u32(pid)) {
+                let name = system
+                    .process(Pid::from_u32(pid))
+                    .map(|p| p.name().to_string())
+                    .unwrap_or_default();
+                prediction.effect(
+                    "signal_process",
+                    format!("{} ({})", pid, name),
+                    json!({"signal": signal}),
+                );
+            } else {
+                prediction
+                    .problems
+                    .push(format!("No process with pid {}", pid));
+            }
+        }
+    }
+}
+
+/// Locate a command the way `spawn` will, through `PATH` unless it has a separator
+pub(crate) fn resolve_command(command: &str) -> Option<PathBuf> {
+    let path = expand_home(command);
+    if command.contains(std::path::MAIN_SEPARATOR) || command.contains('/') {
+        return path.is_file().then_some(path);
+    }
+    std::env::var_os("PATH").and_then(|paths| {
+        std::env::split_paths(&paths)
+            .map(|dir| dir.join(command))
+            .find(|candidate| candidate.is_file())
+    })
+}
@@ -0,0 +1,335 @@
+use crate::action::process_ops::ProcessManager;
+use crate::action::schema::SystemOperation;
+use crate::action::simulate::resolve_command;
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::time::Duration;
+use sysinfo::{Disks, Networks, System};
+use tokio::sync::watch;
+use tracing::info;
+
+/// Time between answering a shutdown/restart and acting on it, so the reply
+/// still reaches the caller
+const LIFECYCLE_GRACE: Duration = Duration::from_secs(1);
+const BACKLIGHT_DIR: &str = "/sys/class/backlight";
+
+/// What `shutdown` and `restart` ask the process owning digiOS to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LifecycleRequest {
+    Shutdown,
+    Restart,
+}
+
+/// System Operations - Executes `system_operation` actions
+///
+/// Shutdown and restart are only requested here; whoever owns the process
+/// watches [`lifecycle`](Self::lifecycle) and acts on them.
+pub struct SystemOperations {
+    lifecycle: Arc<watch::Sender<Option<LifecycleRequest>>>,
+}
+
+impl Default for SystemOperations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SystemOperations {
+    pub fn new() -> Self {
+        let (lifecycle, _) = watch::channel(None);
+        Self {
+            lifecycle: Arc::new(lifecycle),
+        }
+    }
+
+    /// Shutdown/restart requests made through `system_operation`
+    pub fn lifecycle(&self) -> watch::Receiver<Option<LifecycleRequest>> {
+        self.lifecycle.subscribe()
+    }
+
+    pub async fn execute(&self, operation: SystemOperation, processes: &ProcessManager) -> Result<Value> {
+        match operation {
+            SystemOperation::SetEnv { set, unset } => {
+                let overrides = processes.set_env(set, unset)?;
+                Ok(json!({"env": overrides}))
+            }
+            SystemOperation::Shutdown => Ok(self.request(LifecycleRequest::Shutdown)),
+            SystemOperation::Restart => Ok(self.request(LifecycleRequest::Restart)),
+            operation => tokio::task::spawn_blocking(move || Self::run(operation)).await?,
+        }
+    }
+
+    fn run(operation: SystemOperation) -> Result<Value> {
+        match operation {
+            SystemOperation::Info => Ok(Self::info()),
+            SystemOperation::Mounts => Ok(Self::mounts()),
+            SystemOperation::NetworkInterfaces => Self::network_interfaces(),
+            SystemOperation::SetVolume { percent, muted } => Self::set_volume(percent, muted),
+            SystemOperation::SetBrightness { percent, device } => Self::set_brightness(percent, device),
+            SystemOperation::SetEnv { .. } | SystemOperation::Shutdown | SystemOperation::Restart => {
+                bail!("{} is not a blocking operation", operation.name())
+            }
+        }
+    }
+
+    fn request(&self, request: LifecycleRequest) -> Value {
+        info!("digiOS {:?} requested", request);
+        let lifecycle = self.lifecycle.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(LIFECYCLE_GRACE).await;
+            lifecycle.send_replace(Some(request));
+        });
+        json!({"requested": request, "in_ms": LIFECYCLE_GRACE.as_millis() as u64})
+    }
+
+    fn info() -> Value {
+        let mut system = System::new();
+        system.refresh_memory();
+        system.refresh_cpu();
+        let load = System::load_average();
+        json!({
+            "hostname": System::host_name(),
+            "os": System::name(),
+            "os_version": System::os_version(),
+            "kernel_version": System::kernel_version(),
+            "arch": std::env::consts::ARCH,
+            "uptime_secs": System::uptime(),
+            "boot_time": System::boot_time(),
+            "load_average": {"one": load.one, "five": load.five, "fifteen": load.fifteen},
+            "cpus": system.cpus().len(),
+            "memory": {
+                "total_bytes": system.total_memory(),
+                "used_bytes": system.used_memory(),
+                "available_bytes": system.available_memory(),
+            },
+            "swap": {
+                "total_bytes": system.total_swap(),
+                "used_bytes": system.used_swap(),
+            },
+        })
+    }
+
+    fn mounts() -> Value {
+        let disks = Disks::new_with_refreshed_list();
+        let usage = |mount_point: &Path| {
+            disks
+                .list()
+                .iter()
+                .find(|disk| disk.mount_point() == mount_point)
+                .map(|disk| {
+                    json!({
+                        "total_bytes": disk.total_space(),
+                        "available_bytes": disk.available_space(),
+                        "used_bytes": disk.total_space().saturating_sub(disk.available_space()),
+                    })
+                })
+        };
+
+        let mounts: Vec<Value> = match fs::read_to_string("/proc/self/mounts") {
+            Ok(table) => table
+                .lines()
+                .filter_map(parse_mount)
+                .map(|(device, mount_point, fs_type, options)| {
+                    json!({
+                        "device": device,
+                        "mount_point": mount_point,
+                        "fs_type": fs_type,
+                        "options": options,
+                        "usage": usage(&mount_point),
+                    })
+                })
+                .collect(),
+            // No mount table outside Linux; report the disks sysinfo knows about
+            Err(_) => disks
+                .list()
+                .iter()
+                .map(|disk| {
+                    json!({
+                        "device": disk.name().to_string_lossy(),
+                        "mount_point": disk.mount_point(),
+                        "fs_type": disk.file_system().to_string_lossy(),
+                        "options": [],
+                        "usage": usage(disk.mount_point()),
+                    })
+                })
+                .collect(),
+        };
+        json!({"count": mounts.len(), "mounts": mounts})
+    }
+
+    fn network_interfaces() -> Result<Value> {
+        let networks = Networks::new_with_refreshed_list();
+        let addresses = if_addrs::get_if_addrs().context("Failed to list interface addresses")?;
+
+        let mut names: BTreeSet<&str> = networks.list().keys().map(String::as_str).collect();
+        names.extend(addresses.iter().map(|a| a.name.as_str()));
+
+        let interfaces: Vec<Value> = names
+            .into_iter()
+            .map(|name| {
+                let data = networks.list().get(name);
+                let addrs: Vec<Value> = addresses
+                    .iter()
+                    .filter(|a| a.name == name)
+                    .map(|a| match &a.addr {
+                        if_addrs::IfAddr::V4(v4) => {
+                            json!({"family": "ipv4", "ip": v4.ip, "netmask": v4.netmask})
+                        }
+                        if_addrs::IfAddr::V6(v6) => {
+                            json!({"family": "ipv6", "ip": v6.ip, "netmask": v6.netmask})
+                        }
+                    })
+                    .collect();
+                json!({
+                    "name": name,
+                    "loopback": addresses.iter().any(|a| a.name == name && a.is_loopback()),
+                    "mac_address": data.map(|d| d.mac_address().to_string()),
+                    "addresses": addrs,
+                    "received_bytes": data.map(|d| d.total_received()),
+                    "transmitted_bytes": data.map(|d| d.total_transmitted()),
+                })
+            })
+            .collect();
+        Ok(json!({"count": interfaces.len(), "interfaces": interfaces}))
+    }
+
+    /// Volume through PulseAudio/PipeWire (`pactl`), falling back to ALSA (`amixer`)
+    fn set_volume(percent: Option<u8>, muted: Option<bool>) -> Result<Value> {
+        if percent.is_none() && muted.is_none() {
+            bail!("set_volume needs percent and/or muted");
+        }
+        if percent.is_some_and(|p| p > 100) {
+            bail!("Volume must be between 0 and 100 percent");
+        }
+
+        let backend = if resolve_command("pactl").is_some() {
+            if let Some(percent) = percent {
+                run("pactl", &["set-sink-volume", "@DEFAULT_SINK@", &format!("{}%", percent)])?;
+            }
+            if let Some(muted) = muted {
+                run("pactl", &["set-sink-mute", "@DEFAULT_SINK@", if muted { "1" } else { "0" }])?;
+            }
+            "pactl"
+        } else if resolve_command("amixer").is_some() {
+            if let Some(percent) = percent {
+                run("amixer", &["-q", "set", "Master", &format!("{}%", percent)])?;
+            }
+            if let Some(muted) = muted {
+                run("amixer", &["-q", "set", "Master", if muted { "mute" } else { "unmute" }])?;
+            }
+            "amixer"
+        } else {
+            bail!("No volume control available (install pactl or amixer)");
+        };
+        Ok(json!({"percent": percent, "muted": muted, "backend": backend}))
+    }
+
+    /// Brightness through sysfs, falling back to `brightnessctl` when the
+    /// sysfs file isn't writable by us
+    fn set_brightness(percent: u8, device: Option<String>) -> Result<Value> {
+        if percent > 100 {
+            bail!("Brightness must be between 0 and 100 percent");
+        }
+        let device = match device {
+            Some(device) if device.contains('/') || device.starts_with('.') => {
+                bail!("Invalid backlight device: {:?}", device)
+            }
+            Some(device) => device,
+            None => first_backlight()?,
+        };
+        let dir = Path::new(BACKLIGHT_DIR).join(&device);
+        let max: u64 = fs::read_to_string(dir.join("max_brightness"))
+            .with_context(|| format!("No backlight device {}", device))?
+            .trim()
+            .parse()
+            .context("Unreadable max_brightness")?;
+        let value = max * u64::from(percent) / 100;
+
+        let backend = match fs::write(dir.join("brightness"), value.to_string()) {
+            Ok(()) => "sysfs",
+            Err(e) if e.kind() == std::io::ErrorKind::PermissionDenied
+                && resolve_command("brightnessctl").is_some() =>
+            {
+                run("brightnessctl", &["--device", &device, "set", &format!("{}%", percent)])?;
+                "brightnessctl"
+            }
+            Err(e) => bail!("Failed to set brightness of {}: {}", device, e),
+        };
+        Ok(json!({
+            "device": device,
+            "percent": percent,
+            "brightness": value,
+            "max_brightness": max,
+            "backend": backend,
+        }))
+    }
+}
+
+fn first_backlight() -> Result<String> {
+    let mut devices: Vec<String> = fs::read_dir(BACKLIGHT_DIR)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok())
+                .map(|e| e.file_name().to_string_lossy().into_owned())
+                .collect()
+        })
+        .unwrap_or_default();
+    devices.sort();
+    devices
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No backlight device found"))
+}
+
+/// Fields of a `/proc/self/mounts` line, with octal escapes such as `\040` decoded
+fn parse_mount(line: &str) -> Option<(String, PathBuf, String, Vec<String>)> {
+    let mut fields = line.split_whitespace().map(unescape_mount_field);
+    let device = fields.next()?;
+    let mount_point = PathBuf::from(fields.next()?);
+    let fs_type = fields.next()?;
+    let options = fields.next()?.split(',').map(str::to_string).collect();
+    Some((device, mount_point, fs_type, options))
+}
+
+fn unescape_mount_field(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut rest = field;
+    while let Some(pos) = rest.find('\\') {
+        out.push_str(&rest[..pos]);
+        let code = rest.get(pos + 1..pos + 4).and_then(|o| u8::from_str_radix(o, 8).ok());
+        match code {
+            Some(byte) => {
+                out.push(char::from(byte));
+                rest = &rest[pos + 4..];
+            }
+            None => {
+                out.push('\\');
+                rest = &rest[pos + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let output = Command::new(program)
+        .args(args)
+        .output()
+        .with_context(|| format!("Failed to run {}", program))?;
+    if !output.status.success() {
+        bail!(
+            "{} failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
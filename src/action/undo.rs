@@ -0,0 +1,580 @@
+use crate::action::file_ops::{copy_recursive, move_path, remove_path};
+use crate::action::schema::{
+    Action, ClipboardFormat, ClipboardGetParams, FileOperation, NoExtraFields, ProcessOperation,
+    UndoOperation, WindowOperation, WindowSelector,
+};
+use crate::core::paths::expand_home;
+use crate::state::{ClipboardSystem, WindowSystem};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{info, warn};
+
+/// Name of temporary undo directories, followed by the owning pid
+const TEMPORARY_PREFIX: &str = "digios-undo-";
+/// Undo records beyond this count are forgotten, oldest first, with their backups
+const MAX_RECORDS: usize = 200;
+/// Files or trees larger than this are not backed up; the action is flagged irreversible
+const MAX_BACKUP_BYTES: u64 = 256 * 1024 * 1024;
+
+/// One step that reverses part of an action's effect
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Compensation {
+    /// Replace whatever is at `path` with the backed-up copy
+    RestorePath { path: PathBuf, backup: PathBuf },
+    /// Remove something the action created
+    RemovePath { path: PathBuf },
+    /// Move `from` back to `to`
+    MovePath { from: PathBuf, to: PathBuf },
+    /// Reapply previous window state (geometry, focus, ...)
+    Window(WindowOperation),
+    /// Put the previous clipboard contents back; an image wins over text
+    Clipboard {
+        text: Option<String>,
+        image: Option<PathBuf>,
+    },
+}
+
+impl Compensation {
+    /// Whether both steps change the same thing: paths one inside the other,
+    /// the same window, or the clipboard
+    fn overlaps(&self, other: &Compensation) -> bool {
+        match (self, other) {
+            (Compensation::Window(a), Compensation::Window(b)) => {
+                window_id(a).is_some_and(|id| window_id(b) == Some(id))
+            }
+            (Compensation::Clipboard { .. }, Compensation::Clipboard { .. }) => true,
+            _ => self.paths().iter().any(|a| {
+                other
+                    .paths()
+                    .iter()
+                    .any(|b| a.starts_with(b) || b.starts_with(a))
+            }),
+        }
+    }
+
+    fn paths(&self) -> Vec<&Path> {
+        match self {
+            Compensation::RestorePath { path, .. } | Compensation::RemovePath { path } => {
+                vec![path]
+            }
+            Compensation::MovePath { from, to } => vec![from, to],
+            Compensation::Window(_) | Compensation::Clipboard { .. } => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UndoRecord {
+    pub action_id: String,
+    pub action_type: String,
+    pub recorded_at: DateTime<Utc>,
+    /// Applied in order to undo the action
+    pub steps: Vec<Compensation>,
+    pub undone: bool,
+}
+
+/// What running an action would mean for undo
+pub enum Prepared {
+    /// The action changes nothing that needs undoing
+    ReadOnly,
+    Irreversible(String),
+    Reversible(UndoRecord),
+}
+
+/// Undo Log - Compensating steps for reversible actions
+///
+/// Before a reversible action runs, [`prepare`](UndoLog::prepare) captures
+/// what is needed to reverse it (file backups, window geometry, ...). The
+/// record is kept only if the action then succeeds. Backups live under the
+/// log directory next to `log.json`.
+pub struct UndoLog {
+    dir: PathBuf,
+    records: Mutex<VecDeque<UndoRecord>>,
+    /// Remove `dir` when the log is dropped
+    temporary: bool,
+}
+
+impl UndoLog {
+    /// A log whose backups only last for this session, in the temp directory
+    ///
+    /// Directories left behind by sessions that are no longer running, and
+    /// this pid's own after a restart in place, are removed first.
+    pub fn temporary() -> Result<Self> {
+        let temp = std::env::temp_dir();
+        let own = std::process::id().to_string();
+        for entry in fs::read_dir(&temp).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let Some(pid) = name.strip_prefix(TEMPORARY_PREFIX) else {
+                continue;
+            };
+            if pid == own || !process_running(pid) {
+                let _ = fs::remove_dir_all(entry.path());
+            }
+        }
+        let mut log = Self::open(temp.join(format!("{}{}", TEMPORARY_PREFIX, own)))?;
+        log.temporary = true;
+        Ok(log)
+    }
+
+    pub fn open(dir: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let log = dir.join("log.json");
+        let records: VecDeque<UndoRecord> = match fs::read_to_string(&log) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable undo log {}: {}", log.display(), e);
+                VecDeque::new()
+            }),
+            Err(_) => VecDeque::new(),
+        };
+        info!("Loaded {} undo records from {:?}", records.len(), dir);
+        Ok(Self {
+            dir,
+            records: Mutex::new(records),
+            temporary: false,
+        })
+    }
+
+    /// Capture what is needed to reverse `action` before it runs
+    pub async fn prepare(&self, action_id: &str, action: &Action) -> Prepared {
+        let backups = self.dir.join(action_id);
+        let action = action.clone();
+        let action_id = action_id.to_string();
+        let prepared = tokio::task::spawn_blocking(move || {
+            let steps = match compensations(&action, &backups) {
+                Ok(Some(steps)) => steps,
+                Ok(None) => return Prepared::ReadOnly,
+                Err(e) => {
+                    let _ = fs::remove_dir_all(&backups);
+                    return Prepared::Irreversible(format!("{:#}", e));
+                }
+            };
+            Prepared::Reversible(UndoRecord {
+                action_id,
+                action_type: action.name().to_string(),
+                recorded_at: Utc::now(),
+                steps,
+                undone: false,
+            })
+        })
+        .await;
+        prepared.unwrap_or_else(|e| Prepared::Irreversible(e.to_string()))
+    }
+
+    /// Keep the record of an action that succeeded
+    pub fn commit(&self, record: UndoRecord) {
+        let Ok(mut records) = self.records.lock() else {
+            return;
+        };
+        records.push_back(record);
+        while records.len() > MAX_RECORDS {
+            if let Some(old) = records.pop_front() {
+                let _ = fs::remove_dir_all(self.dir.join(&old.action_id));
+            }
+        }
+        self.save(&records);
+    }
+
+    /// Drop the backups of an action that failed
+    pub fn discard(&self, record: UndoRecord) {
+        let _ = fs::remove_dir_all(self.dir.join(&record.action_id));
+    }
+
+    /// Undo records, newest first
+    pub fn records(&self) -> Vec<UndoRecord> {
+        self.records
+            .lock()
+            .map(|r| r.iter().rev().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub async fn undo(&self, operation: &UndoOperation) -> Result<Value> {
+        let targets: Vec<UndoRecord> = {
+            let records = self.records.lock().map_err(|_| anyhow!("Undo log poisoned"))?;
+            match operation {
+                UndoOperation::Last { count } => records
+                    .iter()
+                    .rev()
+                    .filter(|r| !r.undone)
+                    .take(*count)
+                    .cloned()
+                    .collect(),
+                UndoOperation::Action { id, force } => {
+                    let index = records.iter().position(|r| &r.action_id == id).ok_or_else(|| {
+                        anyhow!("Action {} has no undo record; it was read-only, irreversible or failed", id)
+                    })?;
+                    let record = &records[index];
+                    if record.undone {
+                        bail!("Action {} was already undone", id);
+                    }
+                    // Reverting under a newer change would lose it or restore a mix of both
+                    let newer = records
+                        .iter()
+                        .skip(index + 1)
+                        .filter(|r| !r.undone && r.overlaps(record))
+                        .map(|r| format!("{} ({})", r.action_id, r.action_type))
+                        .collect::<Vec<_>>();
+                    if !newer.is_empty() && !force {
+                        bail!(
+                            "Newer actions changed what {} did: {}; undo them first or set force",
+                            id,
+                            newer.join(", ")
+                        );
+                    }
+                    vec![record.clone()]
+                }
+            }
+        };
+        if targets.is_empty() {
+            bail!("Nothing to undo");
+        }
+
+        // Records are undone newest first; stop at the first one that fails
+        let mut undone = Vec::new();
+        for record in targets {
+            let steps = record.steps.clone();
+            let result = tokio::task::spawn_blocking(move || apply(&steps)).await?;
+            if let Err(e) = result {
+                return Err(e.context(format!(
+                    "Failed to undo {} ({}); undid {:?} before it",
+                    record.action_id, record.action_type, undone
+                )));
+            }
+            info!("Undid {} ({})", record.action_id, record.action_type);
+            self.mark_undone(&record.action_id);
+            undone.push(record.action_id);
+        }
+        Ok(json!({"undone": undone}))
+    }
+
+    fn mark_undone(&self, action_id: &str) {
+        let Ok(mut records) = self.records.lock() else {
+            return;
+        };
+        if let Some(record) = records.iter_mut().find(|r| r.action_id == action_id) {
+            record.undone = true;
+        }
+        let _ = fs::remove_dir_all(self.dir.join(action_id));
+        self.save(&records);
+    }
+
+    fn save(&self, records: &VecDeque<UndoRecord>) {
+        let log = self.dir.join("log.json");
+        let result = serde_json::to_vec(records)
+            .map_err(anyhow::Error::from)
+            .and_then(|bytes| fs::write(&log, bytes).map_err(anyhow::Error::from));
+        if let Err(e) = result {
+            warn!("Failed to save undo log {}: {:#}", log.display(), e);
+        }
+    }
+}
+
+impl UndoRecord {
+    fn overlaps(&self, other: &UndoRecord) -> bool {
+        self.steps
+            .iter()
+            .any(|a| other.steps.iter().any(|b| a.overlaps(b)))
+    }
+}
+
+impl Drop for UndoLog {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// Steps reversing `action`, or `None` when it changes nothing worth undoing
+fn compensations(action: &Action, backups: &Path) -> Result<Option<Vec<Compensation>>> {
+    match action {
+        Action::Screenshot(_)
+        | Action::ReadText(_)
+        | Action::FindImage(_)
+        | Action::ScreenDiff(_)
+        | Action::AnnotateScreen(_)
+        | Action::A11yTree(_)
+        | Action::A11yFind(_) => Ok(None),
+        Action::MouseMove(_)
+        | Action::Click(_)
+        | Action::ClickText(_)
+        | Action::ClickImage(_)
+        | Action::ClickMark(_)
+        | Action::Type(_)
+        | Action::Key(_) => {
+            bail!("input events cannot be undone")
+        }
+        Action::A11yPress(_) | Action::A11ySetText(_) | Action::A11yFocus(_) => {
+            bail!("accessibility actions cannot be undone")
+        }
+        Action::ProcessOp(ProcessOperation::Spawn { .. } | ProcessOperation::Signal { .. }) => {
+            bail!("process operations cannot be undone")
+        }
+        Action::ProcessOp(_) => Ok(None),
+        Action::SystemOp(operation) if operation.is_query() => Ok(None),
+        Action::SystemOp(_) => bail!("system operations cannot be undone"),
+        Action::Undo(_) => bail!("undo cannot itself be undone"),
+        Action::FileOp(operation) => file_compensations(operation, backups),
+        Action::WindowOp(operation) => window_compensations(operation),
+        Action::ClipboardGet(ClipboardGetParams { format: ClipboardFormat::Image, path: Some(path) }) => {
+            let write = FileOperation::Write {
+                path: path.clone(),
+                content: String::new(),
+                encoding: Default::default(),
+                create_dirs: true,
+            };
+            file_compensations(&write, backups)
+        }
+        Action::ClipboardGet(_) => Ok(None),
+        Action::WaitForWindow(_)
+        | Action::WaitForProcess(_)
+        | Action::WaitForFile(_)
+        | Action::WaitForPixelChange(_)
+        | Action::WaitForText(_)
+        | Action::AssertWindow(_)
+        | Action::AssertProcess(_)
+        | Action::AssertFile(_)
+        | Action::AssertPixelChange(_)
+        | Action::AssertText(_) => Ok(None),
+        Action::ClipboardSet(_) => {
+            // Images can't be read everywhere text can; an unreadable image
+            // is treated as no image, and only losing both is irreversible
+            let text = ClipboardSystem::text();
+            let png = ClipboardSystem::png().ok().flatten();
+            let text = match (text, &png) {
+                (Ok(text), _) => text,
+                (Err(_), Some(_)) => None,
+                (Err(e), None) => return Err(e),
+            };
+            let image = match png {
+                Some(png) => {
+                    fs::create_dir_all(backups)?;
+                    let copy = backups.join("clipboard.png");
+                    fs::write(&copy, png)?;
+                    Some(copy)
+                }
+                None => None,
+            };
+            Ok(Some(vec![Compensation::Clipboard { text, image }]))
+        }
+    }
+}
+
+fn file_compensations(operation: &FileOperation, backups: &Path) -> Result<Option<Vec<Compensation>>> {
+    let mut backup_index = 0;
+    let mut backup = |path: &Path| -> Result<PathBuf> {
+        let size = tree_size(path);
+        if size > MAX_BACKUP_BYTES {
+            bail!("{} is too large to back up ({} bytes)", path.display(), size);
+        }
+        fs::create_dir_all(backups)?;
+        let copy = backups.join(backup_index.to_string());
+        backup_index += 1;
+        copy_recursive(path, &copy)
+            .with_context(|| format!("Failed to back up {}", path.display()))?;
+        Ok(copy)
+    };
+
+    let steps = match operation {
+        FileOperation::Read { .. }
+        | FileOperation::Stat { .. }
+        | FileOperation::List { .. }
+        | FileOperation::Glob { .. } => return Ok(None),
+        FileOperation::Write { path, .. } | FileOperation::Append { path, .. } => {
+            let target = expand_home(path);
+            if target.exists() {
+                vec![Compensation::RestorePath { backup: backup(&target)?, path: target }]
+            } else {
+                vec![Compensation::RemovePath { path: topmost_missing(&target) }]
+            }
+        }
+        FileOperation::Mkdir { path, .. } => {
+            let target = expand_home(path);
+            if target.exists() {
+                return Ok(None);
+            }
+            vec![Compensation::RemovePath { path: topmost_missing(&target) }]
+        }
+        FileOperation::Copy { to, .. } => {
+            let dest = expand_home(to);
+            if dest.exists() {
+                vec![Compensation::RestorePath { backup: backup(&dest)?, path: dest }]
+            } else {
+                vec![Compensation::RemovePath { path: dest }]
+            }
+        }
+        FileOperation::Move { from, to, .. } => {
+            let (source, dest) = (expand_home(from), expand_home(to));
+            let mut steps = vec![Compensation::MovePath { from: dest.clone(), to: source }];
+            if fs::symlink_metadata(&dest).is_ok() {
+                steps.push(Compensation::RestorePath { backup: backup(&dest)?, path: dest });
+            }
+            steps
+        }
+        FileOperation::Delete { path, .. } => {
+            let target = expand_home(path);
+            if fs::symlink_metadata(&target).is_err() {
+                return Ok(None);
+            }
+            vec![Compensation::RestorePath { backup: backup(&target)?, path: target }]
+        }
+    };
+    Ok(Some(steps))
+}
+
+fn window_compensations(operation: &WindowOperation) -> Result<Option<Vec<Compensation>>> {
+    let selector = match operation {
+        WindowOperation::List => return Ok(None),
+        WindowOperation::Close { .. } => bail!("closed windows cannot be reopened"),
+        WindowOperation::Focus { window, .. }
+        | WindowOperation::Move { window, .. }
+        | WindowOperation::Resize { window, .. }
+        | WindowOperation::Minimize { window, .. }
+        | WindowOperation::Maximize { window, .. }
+        | WindowOperation::Restore { window, .. } => window,
+    };
+    let window = WindowSystem::find(selector)?;
+    let by_id = |id: u32| WindowSelector {
+        id: Some(id),
+        ..Default::default()
+    };
+
+    let step = match operation {
+        WindowOperation::Move { .. } => WindowOperation::Move {
+            window: by_id(window.id),
+            x: window.x,
+            y: window.y,
+            extra: NoExtraFields,
+        },
+        WindowOperation::Resize { .. } => WindowOperation::Resize {
+            window: by_id(window.id),
+            width: window.width,
+            height: window.height,
+            extra: NoExtraFields,
+        },
+        WindowOperation::Minimize { .. } | WindowOperation::Maximize { .. } => {
+            WindowOperation::Restore {
+                window: by_id(window.id),
+                extra: NoExtraFields,
+            }
+        }
+        WindowOperation::Restore { .. } if window.maximized => {
+            WindowOperation::Maximize {
+                window: by_id(window.id),
+                extra: NoExtraFields,
+            }
+        }
+        WindowOperation::Restore { .. } if window.minimized => {
+            WindowOperation::Minimize {
+                window: by_id(window.id),
+                extra: NoExtraFields,
+            }
+        }
+        WindowOperation::Restore { .. } => return Ok(None),
+        WindowOperation::Focus { .. } => {
+            let focused = WindowSystem::list()?.into_iter().find(|w| w.focused);
+            match focused {
+                Some(previous) if previous.id != window.id => {
+                    WindowOperation::Focus {
+                        window: by_id(previous.id),
+                        extra: NoExtraFields,
+                    }
+                }
+                Some(_) => return Ok(None),
+                None => bail!("no window had focus before"),
+            }
+        }
+        WindowOperation::List | WindowOperation::Close { .. } => unreachable!(),
+    };
+    Ok(Some(vec![Compensation::Window(step)]))
+}
+
+fn apply(steps: &[Compensation]) -> Result<()> {
+    for step in steps {
+        match step {
+            Compensation::RestorePath { path, backup } => {
+                if fs::symlink_metadata(path).is_ok() {
+                    remove_path(path, true)
+                        .with_context(|| format!("Failed to clear {}", path.display()))?;
+                }
+                move_path(backup, path)
+                    .with_context(|| format!("Failed to restore {}", path.display()))?;
+            }
+            Compensation::RemovePath { path } => {
+                if fs::symlink_metadata(path).is_ok() {
+                    remove_path(path, true)
+                        .with_context(|| format!("Failed to remove {}", path.display()))?;
+                }
+            }
+            Compensation::MovePath { from, to } => {
+                // Moving back would replace or merge with whatever is there now
+                if fs::symlink_metadata(to).is_ok() {
+                    bail!("{} exists again; not moving {} back over it", to.display(), from.display());
+                }
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                move_path(from, to).with_context(|| {
+                    format!("Failed to move {} back to {}", from.display(), to.display())
+                })?;
+            }
+            Compensation::Window(operation) => {
+                WindowSystem::run(operation.clone())?;
+            }
+            Compensation::Clipboard { text, image } => match image {
+                Some(backup) => ClipboardSystem::set_png(fs::read(backup)?)?,
+                None => ClipboardSystem::set_text(text.clone().unwrap_or_default())?,
+            },
+        }
+    }
+    Ok(())
+}
+
+fn window_id(operation: &WindowOperation) -> Option<u32> {
+    match operation {
+        WindowOperation::List => None,
+        WindowOperation::Focus { window, .. }
+        | WindowOperation::Move { window, .. }
+        | WindowOperation::Resize { window, .. }
+        | WindowOperation::Minimize { window, .. }
+        | WindowOperation::Maximize { window, .. }
+        | WindowOperation::Restore { window, .. }
+        | WindowOperation::Close { window, .. } => window.id,
+    }
+}
+
+/// Whether the process a temporary undo directory belongs to still runs
+fn process_running(pid: &str) -> bool {
+    // Without /proc there is no cheap check, so anything unclear is kept
+    !cfg!(target_os = "linux") || pid.parse::<u32>().is_err() || Path::new("/proc").join(pid).exists()
+}
+
+/// The highest ancestor of `path` (or `path` itself) that does not exist yet
+fn topmost_missing(path: &Path) -> PathBuf {
+    path.ancestors()
+        .take_while(|p| !p.as_os_str().is_empty() && !p.exists())
+        .last()
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+fn tree_size(path: &Path) -> u64 {
+    let Ok(meta) = fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !meta.is_dir() {
+        return meta.len();
+    }
+    fs::read_dir(path)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| tree_size(&entry.path()))
+        .sum()
+}
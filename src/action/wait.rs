@@ -0,0 +1,345 @@
+use crate::action::schema::{
+    Action, FileCondition, PixelChangeCondition, ProcessCondition, TextCondition, WaitTiming,
+    WindowCondition,
+};
+use crate::core::paths::expand_home;
+use crate::state::{describe_process, WindowSystem};
+use crate::vision::{capture, diff, OcrEngine, OcrResult, VisionSystem};
+use anyhow::{bail, Result};
+use image::RgbaImage;
+use serde_json::{json, Value};
+use std::fs;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use sysinfo::System;
+
+/// How long a `wait_for_*` action polls when no `timeout_ms` is given
+const DEFAULT_WAIT_TIMEOUT_MS: u64 = 10_000;
+/// Lower bound on the poll interval, so a zero interval doesn't spin
+const MIN_INTERVAL_MS: u64 = 10;
+
+/// What a `wait_for_*` / `assert_*` action is waiting on
+#[derive(Debug, Clone)]
+pub enum Condition {
+    Window(WindowCondition),
+    Process(ProcessCondition),
+    File(FileCondition),
+    PixelChange(PixelChangeCondition),
+    Text(TextCondition),
+}
+
+/// Outcome of a single poll
+enum Check {
+    /// The condition holds; carries what was observed
+    Met(Value),
+    /// Not yet; carries why, for the timeout error
+    Pending(String),
+}
+
+impl Condition {
+    /// The condition a `wait_for_*` or `assert_*` action polls
+    pub fn of(action: &Action) -> Option<Self> {
+        let condition = match action {
+            Action::WaitForWindow(c) | Action::AssertWindow(c) => Condition::Window(c.clone()),
+            Action::WaitForProcess(c) | Action::AssertProcess(c) => Condition::Process(c.clone()),
+            Action::WaitForFile(c) | Action::AssertFile(c) => Condition::File(c.clone()),
+            Action::WaitForPixelChange(c) | Action::AssertPixelChange(c) => {
+                Condition::PixelChange(c.clone())
+            }
+            Action::WaitForText(c) | Action::AssertText(c) => Condition::Text(c.clone()),
+            _ => return None,
+        };
+        Some(condition)
+    }
+
+    pub fn timing(&self) -> &WaitTiming {
+        match self {
+            Condition::Window(c) => &c.timing,
+            Condition::Process(c) => &c.timing,
+            Condition::File(c) => &c.timing,
+            Condition::PixelChange(c) => &c.timing,
+            Condition::Text(c) => &c.timing,
+        }
+    }
+
+    /// Human-readable expectation, used in logs and failure messages
+    pub fn describe(&self) -> String {
+        match self {
+            Condition::Window(c) => {
+                let mut parts = vec![];
+                if let Some(id) = c.window.id {
+                    parts.push(format!("id {}", id));
+                }
+                if let Some(title) = &c.window.title {
+                    parts.push(format!("title {:?}", title));
+                }
+                if let Some(class) = &c.window.class {
+                    parts.push(format!("class {:?}", class));
+                }
+                let state = if c.absent {
+                    "closed"
+                } else if c.focused {
+                    "focused"
+                } else {
+                    "open"
+                };
+                format!("window with {} to be {}", parts.join(", "), state)
+            }
+            Condition::Process(c) => {
+                let target = match (&c.name, c.pid) {
+                    (Some(name), Some(pid)) => format!("process {:?} (pid {})", name, pid),
+                    (Some(name), None) => format!("process {:?}", name),
+                    (None, Some(pid)) => format!("process {}", pid),
+                    (None, None) => "process".to_string(),
+                };
+                let state = if c.absent { "exit" } else { "run" };
+                format!("{} to {}", target, state)
+            }
+            Condition::File(c) => {
+                if c.absent {
+                    return format!("{} to not exist", c.path);
+                }
+                let mut expectation = format!("{} to exist", c.path);
+                if let Some(size) = c.min_size {
+                    expectation.push_str(&format!(" with at least {} bytes", size));
+                }
+                if let Some(text) = &c.contains {
+                    expectation.push_str(&format!(" containing {:?}", text));
+                }
+                expectation
+            }
+            Condition::PixelChange(c) => {
+                let area = match c.region {
+                    Some(r) => format!("region {}x{}+{}+{}", r.width, r.height, r.x, r.y),
+                    None => "screen".to_string(),
+                };
+                format!("{:.1}% of the {} to change", c.threshold * 100.0, area)
+            }
+            Condition::Text(c) => {
+                let state = if c.absent { "disappear" } else { "appear" };
+                format!("text {:?} to {} on screen", c.text, state)
+            }
+        }
+    }
+}
+
+/// Polls conditions for the `wait_for_*` and `assert_*` actions
+pub struct Waiter;
+
+impl Waiter {
+    /// Poll `condition` until it holds or its timeout runs out
+    ///
+    /// Assertions default to a single check instead of the wait timeout.
+    /// Errors that polling can't fix, such as no display, fail immediately.
+    pub async fn wait(condition: &Condition, assertion: bool, vision: &VisionSystem) -> Result<Value> {
+        let timing = condition.timing();
+        let default_timeout = if assertion { 0 } else { DEFAULT_WAIT_TIMEOUT_MS };
+        let timeout = Duration::from_millis(timing.timeout_ms.unwrap_or(default_timeout));
+        let interval = Duration::from_millis(timing.interval_ms.max(MIN_INTERVAL_MS));
+        let started = Instant::now();
+
+        // Pixel changes are measured against the screen as it was when we started
+        let baseline = match condition {
+            Condition::PixelChange(c) => {
+                let (monitor, region) = (c.monitor, c.region);
+                let frame =
+                    tokio::task::spawn_blocking(move || capture::grab(monitor, region)).await??;
+                tokio::time::sleep(interval).await;
+                Some(Arc::new(frame.image))
+            }
+            _ => None,
+        };
+        let ocr = match condition {
+            Condition::Text(_) => Some(vision.ocr_engine()?),
+            _ => None,
+        };
+
+        let mut checks = 0u32;
+        loop {
+            let polled = condition.clone();
+            let baseline = baseline.clone();
+            let ocr = ocr.clone();
+            checks += 1;
+            let check = tokio::task::spawn_blocking(move || {
+                check(&polled, baseline.as_deref(), ocr.as_deref())
+            })
+            .await??;
+            let reason = match check {
+                Check::Met(observed) => {
+                    return Ok(json!({
+                        "condition": condition.describe(),
+                        "waited_ms": started.elapsed().as_millis() as u64,
+                        "checks": checks,
+                        "observed": observed,
+                    }));
+                }
+                Check::Pending(reason) => reason,
+            };
+
+            let elapsed = started.elapsed();
+            if elapsed >= timeout {
+                if timeout.is_zero() {
+                    bail!("Expected {}: {}", condition.describe(), reason);
+                }
+                bail!(
+                    "Timed out after {} ms waiting for {}: {}",
+                    elapsed.as_millis(),
+                    condition.describe(),
+                    reason
+                );
+            }
+            tokio::time::sleep(interval.min(timeout - elapsed)).await;
+        }
+    }
+}
+
+fn check(
+    condition: &Condition,
+    baseline: Option<&RgbaImage>,
+    ocr: Option<&dyn OcrEngine>,
+) -> Result<Check> {
+    match condition {
+        Condition::Window(c) => check_window(c),
+        Condition::Process(c) => check_process(c),
+        Condition::File(c) => check_file(c),
+        Condition::PixelChange(c) => match baseline {
+            Some(baseline) => check_pixels(c, baseline),
+            None => bail!("Pixel change check has no baseline frame"),
+        },
+        Condition::Text(c) => match ocr {
+            Some(ocr) => check_text(c, ocr),
+            None => bail!("Text check has no OCR engine"),
+        },
+    }
+}
+
+fn check_window(condition: &WindowCondition) -> Result<Check> {
+    let selector = &condition.window;
+    if selector.id.is_none() && selector.title.is_none() && selector.class.is_none() {
+        bail!("Window condition needs an id, title or class");
+    }
+    let matching: Vec<_> = WindowSystem::list()?
+        .into_iter()
+        .filter(|w| selector.matches(w))
+        .collect();
+
+    if condition.absent {
+        return Ok(match matching.first() {
+            None => Check::Met(Value::Null),
+            Some(window) => Check::Pending(format!("window {} is still open", window.id)),
+        });
+    }
+    if condition.focused {
+        return Ok(match matching.iter().find(|w| w.focused) {
+            Some(window) => Check::Met(json!(window)),
+            None if matching.is_empty() => Check::Pending("no matching window".to_string()),
+            None => Check::Pending(format!("{} matching window(s), none focused", matching.len())),
+        });
+    }
+    Ok(match matching.into_iter().next() {
+        Some(window) => Check::Met(json!(window)),
+        None => Check::Pending("no matching window".to_string()),
+    })
+}
+
+fn check_process(condition: &ProcessCondition) -> Result<Check> {
+    if condition.name.is_none() && condition.pid.is_none() {
+        bail!("Process condition needs a name or pid");
+    }
+    let mut system = System::new();
+    system.refresh_processes();
+
+    let name = condition.name.as_ref().map(|n| n.to_lowercase());
+    let matching: Vec<Value> = system
+        .processes()
+        .iter()
+        .filter(|(pid, process)| {
+            condition.pid.is_none_or(|p| p == pid.as_u32())
+                && name
+                    .as_ref()
+                    .is_none_or(|n| process.name().to_lowercase().contains(n))
+        })
+        .map(|(pid, process)| describe_process(*pid, process))
+        .collect();
+
+    Ok(match (condition.absent, matching.is_empty()) {
+        (false, false) => Check::Met(json!(matching)),
+        (false, true) => Check::Pending("no matching process".to_string()),
+        (true, true) => Check::Met(Value::Null),
+        (true, false) => Check::Pending(format!("{} matching process(es) running", matching.len())),
+    })
+}
+
+fn check_file(condition: &FileCondition) -> Result<Check> {
+    let path = expand_home(&condition.path);
+    let metadata = match fs::metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(if condition.absent {
+                Check::Met(json!({"path": path}))
+            } else {
+                Check::Pending("file does not exist".to_string())
+            });
+        }
+        Err(e) => bail!("Failed to stat {}: {}", path.display(), e),
+    };
+    if condition.absent {
+        return Ok(Check::Pending("file still exists".to_string()));
+    }
+
+    if let Some(min_size) = condition.min_size {
+        if metadata.len() < min_size {
+            return Ok(Check::Pending(format!("file is {} bytes", metadata.len())));
+        }
+    }
+    if let Some(text) = &condition.contains {
+        // A file still being written may not be readable yet
+        let found = fs::read(&path)
+            .map(|bytes| String::from_utf8_lossy(&bytes).contains(text.as_str()))
+            .unwrap_or(false);
+        if !found {
+            return Ok(Check::Pending(format!("file does not contain {:?}", text)));
+        }
+    }
+    Ok(Check::Met(json!({"path": path, "size": metadata.len()})))
+}
+
+fn check_pixels(condition: &PixelChangeCondition, baseline: &RgbaImage) -> Result<Check> {
+    let frame = capture::grab(condition.monitor, condition.region)?;
+    let changes = diff::diff(baseline, &frame.image).offset(frame.origin.0, frame.origin.1);
+    Ok(if changes.changed_fraction >= condition.threshold {
+        Check::Met(json!({
+            "changed_fraction": changes.changed_fraction,
+            "perceptual_difference": changes.perceptual_difference,
+            "regions": changes.regions,
+        }))
+    } else {
+        Check::Pending(format!(
+            "{:.2}% of pixels changed",
+            changes.changed_fraction * 100.0
+        ))
+    })
+}
+
+fn check_text(condition: &TextCondition, ocr: &dyn OcrEngine) -> Result<Check> {
+    let frame = capture::grab(condition.monitor, condition.region)?;
+    let recognized = OcrResult::new(ocr.name(), ocr.recognize(&frame.image)?).text;
+
+    // OCR wraps lines wherever the layout does, so compare with whitespace collapsed
+    let normalize = |text: &str| {
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        if condition.case_sensitive {
+            text
+        } else {
+            text.to_lowercase()
+        }
+    };
+    let found = normalize(&recognized).contains(&normalize(&condition.text));
+
+    Ok(match (condition.absent, found) {
+        (false, true) => Check::Met(json!({"text": condition.text})),
+        (false, false) => Check::Pending("text not recognized on screen".to_string()),
+        (true, false) => Check::Met(Value::Null),
+        (true, true) => Check::Pending("text is still on screen".to_string()),
+    })
+}
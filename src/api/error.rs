@@ -0,0 +1,63 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+
+/// Structured error body returned by the API
+///
+/// Serializes as `{"success": false, "error": {"kind": ..., "message": ...}}`.
+#[derive(Debug)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub kind: &'static str,
+    pub message: String,
+}
+
+impl ApiError {
+    /// The request body did not describe a valid action
+    pub fn validation(message: impl std::fmt::Display) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            kind: "validation",
+            message: message.to_string(),
+        }
+    }
+
+    pub fn not_found(message: impl std::fmt::Display) -> Self {
+        Self {
+            status: StatusCode::NOT_FOUND,
+            kind: "not_found",
+            message: message.to_string(),
+        }
+    }
+
+    /// The policy refused the request
+    pub fn denied(rule: &str, reason: &str) -> Self {
+        Self {
+            status: StatusCode::FORBIDDEN,
+            kind: "denied",
+            message: format!("Denied by policy rule {}: {}", rule, reason),
+        }
+    }
+
+    pub fn internal(error: impl std::fmt::Display) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            kind: "internal",
+            message: format!("{:#}", error),
+        }
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({
+            "success": false,
+            "error": {
+                "kind": self.kind,
+                "message": self.message,
+            }
+        });
+        (self.status, Json(body)).into_response()
+    }
+}
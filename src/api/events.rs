@@ -0,0 +1,143 @@
+use crate::api::{ApiError, AppState};
+use crate::event::{EventFilter, Subscription};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::{
+        sse::{Event as SseEvent, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use tracing::debug;
+
+/// Query string for `GET /api/events`; every parameter narrows the stream
+#[derive(Debug, Default, Deserialize)]
+pub struct EventStreamQuery {
+    /// Comma-separated event types, e.g. `process_started,task_state_changed`
+    pub types: Option<String>,
+    pub pid: Option<u32>,
+    pub path: Option<PathBuf>,
+    pub action_type: Option<String>,
+    pub caller: Option<String>,
+}
+
+impl EventStreamQuery {
+    fn filter(self) -> Result<EventFilter, ApiError> {
+        let types = self
+            .types
+            .map(|types| {
+                types
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default();
+        let filter = EventFilter {
+            types,
+            pid: self.pid,
+            path: self.path,
+            action_type: self.action_type,
+            caller: self.caller,
+        };
+        filter.validate().map_err(ApiError::validation)?;
+        Ok(filter)
+    }
+}
+
+/// Stream events as they are published
+///
+/// A WebSocket upgrade gets a socket with one JSON event per text message;
+/// sending it an `EventFilter` as JSON replaces the filter. Any other request
+/// gets server-sent events named after the event type. Both report events
+/// dropped for a slow client as a `lagged` message with the count.
+pub async fn handle_events(
+    State(state): State<AppState>,
+    ws: Option<WebSocketUpgrade>,
+    query: Result<Query<EventStreamQuery>, axum::extract::rejection::QueryRejection>,
+) -> Result<Response, ApiError> {
+    let Query(query) = query.map_err(|e| ApiError::validation(e.body_text()))?;
+    let subscription = state.events.subscribe(query.filter()?);
+    Ok(match ws {
+        Some(ws) => ws
+            .on_upgrade(move |socket| stream_socket(socket, subscription))
+            .into_response(),
+        None => stream_sse(subscription).into_response(),
+    })
+}
+
+async fn stream_socket(mut socket: WebSocket, mut subscription: Subscription) {
+    let mut missed = 0;
+    loop {
+        tokio::select! {
+            event = subscription.recv() => {
+                let Some(event) = event else {
+                    break;
+                };
+                if subscription.missed() > missed {
+                    let lagged = serde_json::json!({
+                        "type": "lagged",
+                        "missed": subscription.missed() - missed,
+                    });
+                    missed = subscription.missed();
+                    if socket.send(Message::Text(lagged.to_string())).await.is_err() {
+                        break;
+                    }
+                }
+                let Ok(text) = serde_json::to_string(&event) else {
+                    continue;
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => {
+                let text = match message {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue,
+                };
+                let reply = match serde_json::from_str::<EventFilter>(&text)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|filter| filter.validate().map(|_| filter))
+                {
+                    Ok(filter) => {
+                        let reply = serde_json::json!({"type": "subscribed", "filter": filter});
+                        subscription.set_filter(filter);
+                        reply
+                    }
+                    Err(e) => serde_json::json!({"type": "error", "message": format!("{:#}", e)}),
+                };
+                if socket.send(Message::Text(reply.to_string())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+    debug!("Event WebSocket closed");
+}
+
+fn stream_sse(
+    subscription: Subscription,
+) -> Sse<impl futures::Stream<Item = Result<SseEvent, Infallible>>> {
+    let events = futures::stream::unfold((subscription, 0), |(mut subscription, missed)| async move {
+        let event = subscription.recv().await?;
+        let mut messages = Vec::with_capacity(2);
+        if subscription.missed() > missed {
+            let lagged = serde_json::json!({"missed": subscription.missed() - missed});
+            messages.push(Ok(SseEvent::default().event("lagged").data(lagged.to_string())));
+        }
+        if let Ok(data) = serde_json::to_string(&event) {
+            messages.push(Ok(SseEvent::default().event(event.kind()).data(data)));
+        }
+        let missed = subscription.missed();
+        Some((futures::stream::iter(messages), (subscription, missed)))
+    });
+    Sse::new(futures::StreamExt::flatten(events)).keep_alive(KeepAlive::default())
+}
@@ -1,4 +1,6 @@
+pub mod error;
+pub mod events;
 pub mod server;
 
+pub use error::ApiError;
 pub use server::AppState;
-
@@ -1,14 +1,22 @@
-use crate::action::ActionEngine;
+use crate::action::schema::{ScreenshotParams, UndoOperation, WindowOperation};
+use crate::action::{Action, ActionEngine, ActionResult, HistoryQuery, Verdict};
+use crate::api::ApiError;
+use crate::core::config::{ScreenMonitorSpec, WatchSpec};
+use crate::event::{EventSystem, WatchInfo};
 use crate::memory::MemorySystem;
-use crate::state::StateManager;
-use crate::task::TaskPlanner;
+use crate::state::{StateManager, WindowSystem};
+use crate::task::{
+    Macro, MacroStep, MacroSystem, RecordRequest, ReplayOptions, ReplayStatus, TaskPlanner,
+    Trigger, TriggerSpec, TriggerSystem,
+};
 use crate::vision::VisionSystem;
 use anyhow::Result;
 use axum::{
-    extract::State,
-    http::StatusCode,
-    response::Json,
+    extract::{rejection::JsonRejection, Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Json, Response},
 };
+use serde::Deserialize;
 use serde_json::Value;
 use std::sync::Arc;
 use tracing::info;
@@ -19,7 +27,10 @@ pub struct AppState {
     pub vision: Arc<VisionSystem>,
     pub state_manager: Arc<StateManager>,
     pub task_planner: Arc<TaskPlanner>,
+    pub macros: Arc<MacroSystem>,
     pub memory: Arc<MemorySystem>,
+    pub events: Arc<EventSystem>,
+    pub triggers: Arc<TriggerSystem>,
 }
 
 pub async fn handle_status() -> Json<Value> {
@@ -36,18 +47,571 @@ pub async fn handle_capabilities(State(state): State<AppState>) -> Json<Value> {
     }))
 }
 
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExecuteQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Execute one action, or a batch of them
+///
+/// A single action is the usual `{"action_type": ..., "params": ...}` object.
+/// A batch is either a bare array of actions or
+/// `{"actions": [...], "stop_on_failure": true}`; every action is validated
+/// before any of them runs. Executed actions that fail answer 422 with the
+/// `ActionResult` body so callers can tell them apart from bad requests (400).
+///
+/// `"dry_run": true` next to `action_type` (or next to `actions` for a batch),
+/// or `?dry_run=true` for any body, returns predicted effects instead of
+/// running anything. Dry runs apply to a whole batch, so a `dry_run` on an
+/// item inside one is rejected rather than ignored.
 pub async fn handle_execute_action(
     State(state): State<AppState>,
-    Json(action): Json<Value>,
-) -> Result<Json<Value>, StatusCode> {
-    // TODO: Parse and execute action
-    Ok(Json(serde_json::json!({"success": true})))
+    headers: HeaderMap,
+    query: Result<Query<ExecuteQuery>, axum::extract::rejection::QueryRejection>,
+    body: Result<Json<Value>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Query(query) = query.map_err(|e| ApiError::validation(e.body_text()))?;
+    let Json(body) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    let caller = api_caller(&headers);
+
+    let (actions, stop_on_failure, dry_run) = match body {
+        Value::Array(items) => (items, true, query.dry_run),
+        Value::Object(mut map) if map.contains_key("actions") => {
+            let stop_on_failure = take_flag(&mut map, "stop_on_failure", true)?;
+            let dry_run = take_flag(&mut map, "dry_run", false)? || query.dry_run;
+            let items = match map.remove("actions") {
+                Some(Value::Array(items)) => items,
+                _ => return Err(ApiError::validation("actions must be an array")),
+            };
+            if let Some(key) = map.keys().next() {
+                return Err(ApiError::validation(format!("Unknown batch field {:?}", key)));
+            }
+            (items, stop_on_failure, dry_run)
+        }
+        Value::Object(mut map) => {
+            let dry_run = take_flag(&mut map, "dry_run", false)? || query.dry_run;
+            let action: Action = serde_json::from_value(Value::Object(map))
+                .map_err(|e| ApiError::validation(format!("Invalid action: {}", e)))?;
+            let result = if dry_run {
+                state.action_engine.dry_run(std::slice::from_ref(&action)).await.remove(0)
+            } else {
+                state
+                    .action_engine
+                    .execute_as(action, &caller)
+                    .await
+                    .map_err(ApiError::internal)?
+            };
+            return Ok((result_status(&result), Json(result)).into_response());
+        }
+        _ => return Err(ApiError::validation("Expected an action object or an array of actions")),
+    };
+
+    let actions = actions
+        .into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            if item.get("dry_run").is_some() {
+                return Err(ApiError::validation(format!(
+                    "Invalid action at index {}: dry_run applies to the whole batch; \
+                     set it next to actions or pass ?dry_run=true",
+                    index
+                )));
+            }
+            serde_json::from_value::<Action>(item)
+                .map_err(|e| ApiError::validation(format!("Invalid action at index {}: {}", index, e)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let total = actions.len();
+
+    if dry_run {
+        let results = state.action_engine.dry_run(&actions).await;
+        let success = results.iter().all(|r| r.success);
+        let body = serde_json::json!({
+            "success": success,
+            "dry_run": true,
+            "total": total,
+            "results": results,
+        });
+        return Ok((status_for(success), Json(body)).into_response());
+    }
+
+    let mut results: Vec<ActionResult> = Vec::with_capacity(total);
+    let mut stopped_at = None;
+    for (index, action) in actions.into_iter().enumerate() {
+        let result = state
+            .action_engine
+            .execute_as(action, &caller)
+            .await
+            .map_err(ApiError::internal)?;
+        let failed = !result.success;
+        results.push(result);
+        if failed && stop_on_failure {
+            stopped_at = Some(index);
+            break;
+        }
+    }
+
+    let success = results.iter().all(|r| r.success);
+    let body = serde_json::json!({
+        "success": success,
+        "total": total,
+        "executed": results.len(),
+        "stopped_at": stopped_at,
+        "results": results,
+    });
+    Ok((status_for(success), Json(body)).into_response())
+}
+
+/// Remove an optional boolean field from a request object
+fn take_flag(map: &mut serde_json::Map<String, Value>, key: &str, default: bool) -> Result<bool, ApiError> {
+    match map.remove(key) {
+        None => Ok(default),
+        Some(Value::Bool(flag)) => Ok(flag),
+        Some(_) => Err(ApiError::validation(format!("{} must be a boolean", key))),
+    }
 }
 
-pub async fn handle_screenshot(State(state): State<AppState>) -> Result<Json<Value>, StatusCode> {
-    match state.vision.capture_screen(true).await {
-        Ok(path) => Ok(Json(serde_json::json!({"screenshot": path}))),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+fn status_for(success: bool) -> StatusCode {
+    if success {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
     }
 }
 
+/// A plan for [`TaskPlanner::execute_plan`]
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlanRequest {
+    pub actions: Vec<Action>,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Run actions as one task: `{"actions": [...], "dry_run": false}`
+///
+/// Unlike a batch, the plan publishes `task_state_changed` events and stops
+/// at the first failed assertion. With `dry_run` the effects are only
+/// predicted.
+pub async fn handle_execute_plan(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Json<PlanRequest>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Json(request) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    let result = state
+        .task_planner
+        .execute_plan(request.actions, &api_caller(&headers), request.dry_run)
+        .await
+        .map_err(ApiError::internal)?;
+    let success = result["results"]
+        .as_array()
+        .is_some_and(|results| results.iter().all(|r| r["success"] == true));
+    Ok((status_for(success), Json(result)).into_response())
+}
+
+/// History caller for API requests: `api`, or `api:<name>` with an `X-Caller` header
+fn api_caller(headers: &HeaderMap) -> String {
+    match headers.get("x-caller").and_then(|v| v.to_str().ok()) {
+        Some(name) if !name.is_empty() => format!("api:{}", name),
+        _ => "api".to_string(),
+    }
+}
+
+fn result_status(result: &ActionResult) -> StatusCode {
+    status_for(result.success)
+}
+
+/// Page through executed actions, newest first
+///
+/// Filters: `action_type`, `caller`, `success`, `since`/`until` (RFC 3339);
+/// paging: `offset`, `limit` (default 50).
+pub async fn handle_history(
+    State(state): State<AppState>,
+    query: Result<Query<HistoryQuery>, axum::extract::rejection::QueryRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Query(query) = query.map_err(|e| ApiError::validation(e.body_text()))?;
+    let (total, entries) = state.action_engine.history().query(&query);
+    Ok(Json(serde_json::json!({
+        "total": total,
+        "offset": query.offset,
+        "count": entries.len(),
+        "entries": entries,
+    })))
+}
+
+/// Actions parked by a `confirm` policy rule, oldest first
+pub async fn handle_list_approvals(State(state): State<AppState>) -> Json<Value> {
+    let pending = state.action_engine.policy().pending();
+    Json(serde_json::json!({"count": pending.len(), "pending": pending}))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ApprovalDecision {
+    pub approve: bool,
+}
+
+/// Approve or reject a parked action; the original request then completes
+pub async fn handle_resolve_approval(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Result<Json<ApprovalDecision>, JsonRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Json(decision) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    let approval = state
+        .action_engine
+        .policy()
+        .resolve(&id, decision.approve)
+        .map_err(ApiError::not_found)?;
+    Ok(Json(serde_json::json!({
+        "success": true,
+        "approved": decision.approve,
+        "approval": approval,
+    })))
+}
+
+/// Recorded reversible actions, newest first; `undone` marks ones already reverted
+pub async fn handle_list_undo(State(state): State<AppState>) -> Json<Value> {
+    let records = state.action_engine.undo_log().records();
+    Json(serde_json::json!({"count": records.len(), "records": records}))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    #[serde(default = "default_audit_limit")]
+    pub limit: usize,
+}
+
+fn default_audit_limit() -> usize {
+    100
+}
+
+/// Most recent audited system operations, newest first
+pub async fn handle_audit(
+    State(state): State<AppState>,
+    query: Result<Query<AuditQuery>, axum::extract::rejection::QueryRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Query(query) = query.map_err(|e| ApiError::validation(e.body_text()))?;
+    let entries = state
+        .action_engine
+        .audit_log()
+        .recent(query.limit)
+        .map_err(ApiError::internal)?;
+    Ok(Json(serde_json::json!({"count": entries.len(), "entries": entries})))
+}
+
+/// Run an `undo` action, e.g. `{"operation": "last", "count": 2}`
+pub async fn handle_undo(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Json<UndoOperation>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Json(operation) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    let result = state
+        .action_engine
+        .execute_as(Action::Undo(operation), &api_caller(&headers))
+        .await
+        .map_err(ApiError::internal)?;
+    Ok((result_status(&result), Json(result)).into_response())
+}
+
+pub async fn handle_list_macros(State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    let macros = state.macros.list().map_err(ApiError::internal)?;
+    Ok(Json(serde_json::json!({
+        "count": macros.len(),
+        "macros": macros,
+        "recording": state.macros.recording(),
+    })))
+}
+
+pub async fn handle_get_macro(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Macro>, ApiError> {
+    state.macros.get(&name).map(Json).map_err(ApiError::not_found)
+}
+
+/// Body of `PUT /api/macros/:name`, for hand-written macros
+#[derive(Debug, Deserialize)]
+pub struct SaveMacro {
+    #[serde(default)]
+    pub description: String,
+    pub steps: Vec<MacroStep>,
+}
+
+pub async fn handle_save_macro(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    body: Result<Json<SaveMacro>, JsonRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Json(body) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    let stored = Macro {
+        name,
+        description: body.description,
+        created_at: chrono::Utc::now(),
+        steps: body.steps,
+    };
+    state
+        .macros
+        .save(&stored)
+        .map_err(|e| ApiError::validation(format!("{:#}", e)))?;
+    Ok(Json(serde_json::json!({"success": true, "macro": stored.summary()})))
+}
+
+pub async fn handle_delete_macro(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    state.macros.delete(&name).map_err(ApiError::not_found)?;
+    Ok(Json(serde_json::json!({"success": true, "deleted": name})))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RunMacroQuery {
+    #[serde(default)]
+    pub wait: bool,
+}
+
+/// Replay a macro in the background; the body is optional:
+/// `{"speed": 2.0, "variables": {"filename": "a.txt"}, "stop_on_failure": true}`
+///
+/// Answers 202 with the replay's status, which `GET /api/replays/:id`
+/// follows and `DELETE /api/replays/:id` cancels. With `?wait=true` the
+/// request waits for the outcome instead; cancelling still works meanwhile.
+pub async fn handle_run_macro(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    query: Result<Query<RunMacroQuery>, axum::extract::rejection::QueryRejection>,
+    body: axum::body::Bytes,
+) -> Result<Response, ApiError> {
+    let Query(query) = query.map_err(|e| ApiError::validation(e.body_text()))?;
+    let options: ReplayOptions = if body.is_empty() {
+        ReplayOptions::default()
+    } else {
+        serde_json::from_slice(&body)
+            .map_err(|e| ApiError::validation(format!("Invalid replay options: {}", e)))?
+    };
+    let stored = state.macros.get(&name).map_err(ApiError::not_found)?;
+    let actions = stored
+        .resolve(&options.variables)
+        .map_err(|e| ApiError::validation(format!("{:#}", e)))?;
+    let (status, handle) = state
+        .macros
+        .start_replay(stored, actions, options)
+        .map_err(ApiError::validation)?;
+    if !query.wait {
+        let body = serde_json::json!({"replay": status});
+        return Ok((StatusCode::ACCEPTED, Json(body)).into_response());
+    }
+    let result = handle
+        .await
+        .map_err(ApiError::internal)?
+        .map_err(ApiError::internal)?;
+    let success = result["success"].as_bool().unwrap_or(false);
+    Ok((status_for(success), Json(result)).into_response())
+}
+
+/// Background macro replays, newest first
+pub async fn handle_list_replays(State(state): State<AppState>) -> Json<Value> {
+    let replays = state.macros.replays();
+    Json(serde_json::json!({"count": replays.len(), "replays": replays}))
+}
+
+pub async fn handle_get_replay(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<ReplayStatus>, ApiError> {
+    state.macros.replay_status(&id).map(Json).map_err(ApiError::not_found)
+}
+
+/// Cancel a running replay; it stops before its next step
+pub async fn handle_cancel_replay(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    state.macros.replay_status(&id).map_err(ApiError::not_found)?;
+    let replay = state.macros.cancel_replay(&id).map_err(ApiError::validation)?;
+    Ok(Json(serde_json::json!({"success": true, "replay": replay})))
+}
+
+pub async fn handle_recording_status(State(state): State<AppState>) -> Json<Value> {
+    Json(serde_json::json!({"recording": state.macros.recording()}))
+}
+
+/// Start recording: `{"name": "rename-files", "source": "actions" | "input"}`
+pub async fn handle_start_recording(
+    State(state): State<AppState>,
+    body: Result<Json<RecordRequest>, JsonRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Json(request) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    let status = state.macros.start_recording(request).map_err(ApiError::validation)?;
+    Ok(Json(serde_json::json!({"success": true, "status": status})))
+}
+
+/// Stop recording and save the macro
+pub async fn handle_stop_recording(State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    let recorded = state.macros.stop_recording().await.map_err(ApiError::validation)?;
+    Ok(Json(serde_json::json!({"success": true, "macro": recorded.summary()})))
+}
+
+pub async fn handle_list_watches(State(state): State<AppState>) -> Json<Value> {
+    let watches = state.events.watches();
+    Json(serde_json::json!({"count": watches.len(), "watches": watches}))
+}
+
+/// Watch a directory, e.g. `{"path": "~/Downloads", "patterns": ["*.pdf"]}`
+pub async fn handle_add_watch(
+    State(state): State<AppState>,
+    body: Result<Json<WatchSpec>, JsonRejection>,
+) -> Result<Json<WatchInfo>, ApiError> {
+    let Json(spec) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    match state.action_engine.policy().check_watch(&spec) {
+        Verdict::Allow => {}
+        Verdict::Deny { rule, reason } | Verdict::Confirm { rule, reason } => {
+            return Err(ApiError::denied(&rule, &reason));
+        }
+    }
+    let watch = state
+        .events
+        .watch(spec)
+        .map_err(|e| ApiError::validation(format!("{:#}", e)))?;
+    Ok(Json(watch))
+}
+
+pub async fn handle_remove_watch(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let watch = state.events.unwatch(&id).map_err(ApiError::not_found)?;
+    Ok(Json(serde_json::json!({"success": true, "removed": watch})))
+}
+
+pub async fn handle_list_triggers(State(state): State<AppState>) -> Json<Value> {
+    let triggers = state.triggers.list();
+    Json(serde_json::json!({"count": triggers.len(), "triggers": triggers}))
+}
+
+pub async fn handle_get_trigger(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Trigger>, ApiError> {
+    state.triggers.get(&name).map(Json).map_err(ApiError::not_found)
+}
+
+/// Create or replace a trigger, e.g. `{"event": {"types": ["file_changed"]}, "conditions": [{"field": "path", "op": "matches", "value": "*.pdf"}], "actions": [...]}`
+pub async fn handle_save_trigger(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    body: Result<Json<TriggerSpec>, JsonRejection>,
+) -> Result<Json<Trigger>, ApiError> {
+    let Json(spec) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    let trigger = state
+        .triggers
+        .save(&name, spec)
+        .map_err(|e| ApiError::validation(format!("{:#}", e)))?;
+    info!("Saved trigger {}", name);
+    Ok(Json(trigger))
+}
+
+pub async fn handle_delete_trigger(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    state.triggers.delete(&name).map_err(ApiError::not_found)?;
+    Ok(Json(serde_json::json!({"success": true, "deleted": name})))
+}
+
+pub async fn handle_enable_trigger(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let trigger = state.triggers.set_enabled(&name, true).map_err(ApiError::not_found)?;
+    Ok(Json(trigger.summary()))
+}
+
+pub async fn handle_disable_trigger(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let trigger = state.triggers.set_enabled(&name, false).map_err(ApiError::not_found)?;
+    Ok(Json(trigger.summary()))
+}
+
+pub async fn handle_monitor_status(State(state): State<AppState>) -> Json<Value> {
+    Json(serde_json::json!({"monitoring": state.vision.monitoring()}))
+}
+
+/// Start publishing `screen_changed` events, e.g. `{"interval_ms": 500, "threshold": 0.02}`
+pub async fn handle_start_monitor(
+    State(state): State<AppState>,
+    body: Result<Json<ScreenMonitorSpec>, JsonRejection>,
+) -> Result<Json<Value>, ApiError> {
+    let Json(spec) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    let status = state
+        .vision
+        .start_monitoring(spec)
+        .map_err(|e| ApiError::validation(format!("{:#}", e)))?;
+    Ok(Json(serde_json::json!({"success": true, "status": status})))
+}
+
+pub async fn handle_stop_monitor(State(state): State<AppState>) -> Result<Json<Value>, ApiError> {
+    let spec = state.vision.stop_monitoring().map_err(ApiError::validation)?;
+    Ok(Json(serde_json::json!({"success": true, "stopped": spec})))
+}
+
+/// Query string for `GET /api/vision/screenshot`; regions go through the `screenshot` action
+#[derive(Debug, Deserialize)]
+pub struct ScreenshotQuery {
+    pub show_cursor: Option<bool>,
+    pub monitor: Option<usize>,
+}
+
+pub async fn handle_screenshot(
+    State(state): State<AppState>,
+    Query(query): Query<ScreenshotQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let params = ScreenshotParams {
+        show_cursor: query.show_cursor.unwrap_or(true),
+        monitor: query.monitor,
+        ..Default::default()
+    };
+    let screenshot = state
+        .vision
+        .capture_screen(&params)
+        .await
+        .map_err(ApiError::internal)?;
+    Ok(Json(serde_json::json!({"screenshot": screenshot})))
+}
+
+/// Windows, processes and clipboard as of the last state update
+pub async fn handle_state(State(state): State<AppState>) -> Json<Value> {
+    Json(state.state_manager.snapshot().await)
+}
+
+pub async fn handle_list_windows() -> Result<Json<Value>, ApiError> {
+    // Window enumeration makes blocking X11 round trips
+    let windows = tokio::task::spawn_blocking(WindowSystem::list)
+        .await
+        .map_err(ApiError::internal)?
+        .map_err(ApiError::internal)?;
+    Ok(Json(serde_json::json!({"count": windows.len(), "windows": windows})))
+}
+
+/// Run a `window_operation` through the action engine
+pub async fn handle_window_operation(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Result<Json<WindowOperation>, JsonRejection>,
+) -> Result<Response, ApiError> {
+    let Json(operation) = body.map_err(|e| ApiError::validation(e.body_text()))?;
+    let result = state
+        .action_engine
+        .execute_as(Action::WindowOp(operation), &api_caller(&headers))
+        .await
+        .map_err(ApiError::internal)?;
+    Ok((result_status(&result), Json(result)).into_response())
+}
@@ -1,10 +1,13 @@
+use crate::action::LifecycleRequest;
 use crate::core::aios::aiOS;
 use crate::core::paths;
+use crate::event::Event;
 use crate::model::ModelManager;
 use crate::self_improve::SelfImprovementEngine;
 use crate::interaction::{InteractionManager, ToolManager};
 use anyhow::Result;
 use std::sync::Arc;
+use tokio::sync::watch;
 use tracing::{info, error};
 
 /// Init System - First process that runs on boot
@@ -97,7 +100,8 @@ impl InitSystem {
         
         // Check if human interface should be enabled
         if std::env::var("DIGIOS_HUMAN_INTERFACE").is_ok() {
-            interaction.start_terminal().await?;
+            let macros = self.aios.as_ref().map(|aios| aios.macros());
+            interaction.start_terminal(macros).await?;
         }
         
         self.interaction = Some(interaction);
@@ -121,6 +125,12 @@ impl InitSystem {
         // Load model
         model_manager.load_model().await?;
         info!("Model loaded and ready");
+        if let Some(aios) = &self.aios {
+            aios.events().publish(Event::ModelLoaded {
+                timestamp: chrono::Utc::now(),
+                model: model_manager.get_config().name.clone(),
+            });
+        }
         
         self.model_manager = Some(Arc::new(model_manager));
         Ok(())
@@ -163,6 +173,11 @@ impl InitSystem {
         Ok(())
     }
 
+    /// Shutdown/restart requests from the running aiOS, once booted
+    pub fn lifecycle_requests(&self) -> Option<watch::Receiver<Option<LifecycleRequest>>> {
+        self.aios.as_ref().map(|aios| aios.lifecycle_requests())
+    }
+
     /// Check if this is first boot (setup needed)
     pub async fn is_first_boot(&self) -> bool {
         // Check for setup flag or config file
@@ -1,15 +1,15 @@
-use crate::action::ActionEngine;
+use crate::action::{ActionEngine, ActionHistory, AuditLog, LifecycleRequest, PolicyEngine, UndoLog, Verdict};
 use crate::api::server::AppState;
-use crate::event::EventSystem;
+use crate::event::{EventFilter, EventSystem};
 use crate::memory::MemorySystem;
 use crate::state::StateManager;
-use crate::task::TaskPlanner;
+use crate::task::{MacroSystem, TaskPlanner, TriggerSystem};
 use crate::vision::VisionSystem;
 use crate::core::config::Config;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use tracing::{info, error};
+use tracing::{debug, info, error, warn};
 
 
 #[derive(Clone)]
@@ -19,6 +19,8 @@ pub struct aiOS {
     vision: Arc<VisionSystem>,
     state_manager: Arc<StateManager>,
     task_planner: Arc<TaskPlanner>,
+    macros: Arc<MacroSystem>,
+    triggers: Arc<TriggerSystem>,
     event_system: Arc<EventSystem>,
     memory: Arc<MemorySystem>,
     running: Arc<RwLock<bool>>,
@@ -31,18 +33,57 @@ impl aiOS {
         info!("Initializing aiOS with config: {:?}", config);
 
         // Initialize components
-        let action_engine = Arc::new(ActionEngine::new().await?);
-        let vision = Arc::new(VisionSystem::new().await?);
-        let state_manager = Arc::new(StateManager::new().await?);
+        let event_system = Arc::new(EventSystem::new().await?);
+        let vision = Arc::new(
+            VisionSystem::new(&config.vision, config.features.ocr, event_system.clone()).await?,
+        );
         let memory = Arc::new(MemorySystem::new(&config.memory.path).await?);
+
+        let (history, undo) = if config.features.memory_persistence {
+            (
+                ActionHistory::open(memory.path().join("action_history.jsonl"), config.memory.max_history)?,
+                UndoLog::open(memory.path().join("undo"))?,
+            )
+        } else {
+            (
+                ActionHistory::in_memory(config.memory.max_history),
+                // Backups still need somewhere to live; they just don't outlive the session
+                UndoLog::temporary()?,
+            )
+        };
+        // System operations are audited whether or not memory persistence is on
+        let audit = AuditLog::open(memory.path().join("system_audit.jsonl"))?;
+        let policy = PolicyEngine::new(&config.system, &config.policy)?;
+        let action_engine = Arc::new(ActionEngine::new(vision.clone(), history, policy, undo, audit, event_system.clone()).await?);
+        for spec in &config.watches {
+            let result = match action_engine.policy().check_watch(spec) {
+                Verdict::Allow => event_system.watch(spec.clone()).map(drop),
+                Verdict::Deny { rule, reason } | Verdict::Confirm { rule, reason } => {
+                    Err(anyhow!("denied by policy rule {}: {}", rule, reason))
+                }
+            };
+            if let Err(e) = result {
+                warn!("Skipping watch of {}: {:#}", spec.path, e);
+            }
+        }
+        let state_manager = Arc::new(StateManager::new(
+            event_system.clone(),
+            action_engine.processes(),
+            config.process_monitor.clone(),
+        ).await?);
         
         let task_planner = Arc::new(TaskPlanner::new(
             action_engine.clone(),
             vision.clone(),
             state_manager.clone(),
+            event_system.clone(),
         ));
-
-        let event_system = Arc::new(EventSystem::new().await?);
+        let macros = Arc::new(MacroSystem::new(memory.path().join("macros"), action_engine.clone())?);
+        let triggers = Arc::new(TriggerSystem::new(
+            memory.path().join("triggers"),
+            task_planner.clone(),
+            event_system.clone(),
+        )?);
 
         Ok(Self {
             config,
@@ -50,6 +91,8 @@ impl aiOS {
             vision,
             state_manager,
             task_planner,
+            macros,
+            triggers,
             event_system,
             memory,
             running: Arc::new(RwLock::new(false)),
@@ -68,7 +111,10 @@ impl aiOS {
             vision: self.vision.clone(),
             state_manager: self.state_manager.clone(),
             task_planner: self.task_planner.clone(),
+            macros: self.macros.clone(),
             memory: self.memory.clone(),
+            events: self.event_system.clone(),
+            triggers: self.triggers.clone(),
         };
 
         // Build router
@@ -76,7 +122,41 @@ impl aiOS {
             .route("/api/status", axum::routing::get(crate::api::server::handle_status))
             .route("/api/capabilities", axum::routing::get(crate::api::server::handle_capabilities))
             .route("/api/action", axum::routing::post(crate::api::server::handle_execute_action))
+            .route("/api/plan", axum::routing::post(crate::api::server::handle_execute_plan))
             .route("/api/vision/screenshot", axum::routing::get(crate::api::server::handle_screenshot))
+            .route("/api/vision/monitor", axum::routing::get(crate::api::server::handle_monitor_status)
+                .post(crate::api::server::handle_start_monitor)
+                .delete(crate::api::server::handle_stop_monitor))
+            .route("/api/state", axum::routing::get(crate::api::server::handle_state))
+            .route("/api/windows", axum::routing::get(crate::api::server::handle_list_windows)
+                .post(crate::api::server::handle_window_operation))
+            .route("/api/history", axum::routing::get(crate::api::server::handle_history))
+            .route("/api/approvals", axum::routing::get(crate::api::server::handle_list_approvals))
+            .route("/api/approvals/:id", axum::routing::post(crate::api::server::handle_resolve_approval))
+            .route("/api/audit", axum::routing::get(crate::api::server::handle_audit))
+            .route("/api/undo", axum::routing::get(crate::api::server::handle_list_undo)
+                .post(crate::api::server::handle_undo))
+            .route("/api/macros", axum::routing::get(crate::api::server::handle_list_macros))
+            .route("/api/macros/:name", axum::routing::get(crate::api::server::handle_get_macro)
+                .put(crate::api::server::handle_save_macro)
+                .delete(crate::api::server::handle_delete_macro))
+            .route("/api/macros/:name/run", axum::routing::post(crate::api::server::handle_run_macro))
+            .route("/api/replays", axum::routing::get(crate::api::server::handle_list_replays))
+            .route("/api/replays/:id", axum::routing::get(crate::api::server::handle_get_replay)
+                .delete(crate::api::server::handle_cancel_replay))
+            .route("/api/recording", axum::routing::get(crate::api::server::handle_recording_status)
+                .post(crate::api::server::handle_start_recording)
+                .delete(crate::api::server::handle_stop_recording))
+            .route("/api/watches", axum::routing::get(crate::api::server::handle_list_watches)
+                .post(crate::api::server::handle_add_watch))
+            .route("/api/watches/:id", axum::routing::delete(crate::api::server::handle_remove_watch))
+            .route("/api/events", axum::routing::get(crate::api::events::handle_events))
+            .route("/api/triggers", axum::routing::get(crate::api::server::handle_list_triggers))
+            .route("/api/triggers/:name", axum::routing::get(crate::api::server::handle_get_trigger)
+                .put(crate::api::server::handle_save_trigger)
+                .delete(crate::api::server::handle_delete_trigger))
+            .route("/api/triggers/:name/enable", axum::routing::post(crate::api::server::handle_enable_trigger))
+            .route("/api/triggers/:name/disable", axum::routing::post(crate::api::server::handle_disable_trigger))
             .with_state(app_state.clone());
 
         // Start server in background
@@ -106,14 +186,13 @@ impl aiOS {
             }
         });
 
-        // Start event processing
-        let event_system = self.event_system.clone();
+        self.triggers.start();
+
+        // Trace every event as it is published
+        let mut events = self.event_system.subscribe(EventFilter::default());
         tokio::spawn(async move {
-            loop {
-                if let Err(e) = event_system.process_events().await {
-                    error!("Event processing error: {}", e);
-                }
-                tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+            while let Some(event) = events.recv().await {
+                debug!("Event {}: {}", event.kind(), serde_json::to_string(&event).unwrap_or_default());
             }
         });
 
@@ -127,6 +206,23 @@ impl aiOS {
         Ok(())
     }
 
+    /// Shutdown/restart requests made through `system_operation`
+    pub fn lifecycle_requests(&self) -> tokio::sync::watch::Receiver<Option<LifecycleRequest>> {
+        self.action_engine.system().lifecycle()
+    }
+
+    pub fn events(&self) -> Arc<EventSystem> {
+        self.event_system.clone()
+    }
+
+    pub fn macros(&self) -> Arc<MacroSystem> {
+        self.macros.clone()
+    }
+
+    pub fn triggers(&self) -> Arc<TriggerSystem> {
+        self.triggers.clone()
+    }
+
     pub fn get_capabilities(&self) -> serde_json::Value {
         serde_json::json!({
             "actions": self.action_engine.get_available_actions(),
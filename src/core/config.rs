@@ -1,3 +1,4 @@
+use crate::action::schema::ScreenRegion;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -6,6 +7,15 @@ pub struct Config {
     pub memory: MemoryConfig,
     pub system: SystemConfig,
     pub features: FeaturesConfig,
+    #[serde(default)]
+    pub vision: VisionConfig,
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    /// Directories watched for file changes from startup
+    #[serde(default)]
+    pub watches: Vec<WatchSpec>,
+    #[serde(default)]
+    pub process_monitor: ProcessMonitorConfig,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -36,6 +46,247 @@ pub struct FeaturesConfig {
     pub memory_persistence: bool,
 }
 
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VisionConfig {
+    /// Where screenshots are written; empty means `<data dir>/screenshots`
+    #[serde(default)]
+    pub screenshot_dir: String,
+    /// Text recognition backend, used when `features.ocr` is on
+    #[serde(default)]
+    pub ocr: OcrConfig,
+    /// Screen change monitoring started with the service; also started and
+    /// stopped through `/api/vision/monitor`
+    #[serde(default)]
+    pub monitor: Option<ScreenMonitorSpec>,
+}
+
+/// Part of the screen compared against itself every `interval_ms`,
+/// publishing `screen_changed` when enough of it changed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScreenMonitorSpec {
+    #[serde(default)]
+    pub monitor: Option<usize>,
+    #[serde(default)]
+    pub region: Option<ScreenRegion>,
+    #[serde(default = "default_monitor_interval")]
+    pub interval_ms: u64,
+    /// Fraction of pixels (0-1) that must differ from the previous capture
+    #[serde(default = "default_monitor_threshold")]
+    pub threshold: f64,
+}
+
+fn default_monitor_interval() -> u64 {
+    1000
+}
+
+fn default_monitor_threshold() -> f64 {
+    0.01
+}
+
+/// OCR engine, selected by the `engine` field
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "engine", rename_all = "snake_case")]
+pub enum OcrConfig {
+    /// The `tesseract` command-line tool
+    Tesseract {
+        #[serde(default = "default_tesseract_command")]
+        command: String,
+        /// Language pack(s), e.g. `eng` or `eng+deu`
+        #[serde(default = "default_ocr_language")]
+        language: String,
+        /// Page segmentation mode (`--psm`); tesseract's default when omitted
+        #[serde(default)]
+        psm: Option<u8>,
+    },
+}
+
+impl Default for OcrConfig {
+    fn default() -> Self {
+        OcrConfig::Tesseract {
+            command: default_tesseract_command(),
+            language: default_ocr_language(),
+            psm: None,
+        }
+    }
+}
+
+fn default_tesseract_command() -> String {
+    "tesseract".to_string()
+}
+
+fn default_ocr_language() -> String {
+    "eng".to_string()
+}
+
+/// Safety policy applied in front of the action engine when `safety_mode` is on
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyConfig {
+    #[serde(default = "default_policy_rules")]
+    pub rules: Vec<PolicyRule>,
+    /// How long an action waiting for confirmation stays parked before it is denied
+    #[serde(default = "default_confirm_timeout")]
+    pub confirm_timeout_secs: u64,
+}
+
+impl Default for PolicyConfig {
+    fn default() -> Self {
+        Self {
+            rules: default_policy_rules(),
+            confirm_timeout_secs: default_confirm_timeout(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyRule {
+    pub name: String,
+    #[serde(flatten)]
+    pub kind: PolicyRuleKind,
+    /// What happens when the rule matches
+    #[serde(default)]
+    pub on_match: PolicyVerdict,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PolicyRuleKind {
+    /// Glob patterns for paths file operations may not touch, nor spawned
+    /// processes run in; a pattern also covers everything below a matching
+    /// directory
+    DenyPaths { patterns: Vec<String> },
+    /// Glob patterns matched against spawned command lines, e.g. `mkfs*`;
+    /// commands run through wrappers such as `env`, `sudo` or `sh -c` are
+    /// matched as well
+    DenyCommands { patterns: Vec<String> },
+    /// Window operations, clicks, typing, key presses and accessibility
+    /// actions may only reach windows whose class is listed
+    AllowWindowClasses { classes: Vec<String> },
+    /// `system_operation`s with one of the listed operation names, e.g. `set_env`
+    SystemOperations { operations: Vec<String> },
+    /// At most `max` actions (of `action_type`, or of any type) per `per_secs`
+    RateLimit {
+        #[serde(default)]
+        action_type: Option<String>,
+        max: usize,
+        per_secs: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyVerdict {
+    #[default]
+    Deny,
+    /// Park the action until a human approves or rejects it
+    Confirm,
+}
+
+/// Thresholds for the `high_cpu` and `high_memory` process events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessMonitorConfig {
+    /// Percent of one core, so a process saturating two cores is at 200
+    #[serde(default = "default_high_cpu_percent")]
+    pub high_cpu_percent: f32,
+    /// Percent of total memory
+    #[serde(default = "default_high_memory_percent")]
+    pub high_memory_percent: f64,
+    /// How long usage must stay over a threshold before the event fires
+    #[serde(default = "default_sustain_secs")]
+    pub sustain_secs: u64,
+}
+
+impl Default for ProcessMonitorConfig {
+    fn default() -> Self {
+        Self {
+            high_cpu_percent: default_high_cpu_percent(),
+            high_memory_percent: default_high_memory_percent(),
+            sustain_secs: default_sustain_secs(),
+        }
+    }
+}
+
+fn default_high_cpu_percent() -> f32 {
+    90.0
+}
+
+fn default_high_memory_percent() -> f64 {
+    25.0
+}
+
+fn default_sustain_secs() -> u64 {
+    5
+}
+
+/// A directory watched for file changes, from the config or `/api/watches`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatchSpec {
+    pub path: String,
+    #[serde(default = "default_true")]
+    pub recursive: bool,
+    /// Glob patterns for file names, or with a `/` for paths relative to
+    /// `path`; empty means every file
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    /// Changes to a file within this window are reported once
+    #[serde(default = "default_debounce_ms")]
+    pub debounce_ms: u64,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_debounce_ms() -> u64 {
+    500
+}
+
+fn default_confirm_timeout() -> u64 {
+    300
+}
+
+fn default_policy_rules() -> Vec<PolicyRule> {
+    let strings = |items: &[&str]| items.iter().map(|s| s.to_string()).collect();
+    vec![
+        PolicyRule {
+            name: "protect_system_files".to_string(),
+            kind: PolicyRuleKind::DenyPaths {
+                patterns: strings(&["/etc/shadow", "/etc/gshadow", "/etc/sudoers*", "~/.ssh", "~/.gnupg"]),
+            },
+            on_match: PolicyVerdict::Deny,
+        },
+        PolicyRule {
+            name: "destructive_commands".to_string(),
+            kind: PolicyRuleKind::DenyCommands {
+                patterns: strings(&["mkfs*", "dd *of=/dev/*", "rm -rf /", "rm -rf /[*]", "shred *", "wipefs*"]),
+            },
+            on_match: PolicyVerdict::Deny,
+        },
+        PolicyRule {
+            name: "privileged_commands".to_string(),
+            kind: PolicyRuleKind::DenyCommands {
+                patterns: strings(&["sudo*", "su", "su *", "pkexec*", "shutdown*", "reboot*", "poweroff*"]),
+            },
+            on_match: PolicyVerdict::Confirm,
+        },
+        PolicyRule {
+            name: "system_changes".to_string(),
+            kind: PolicyRuleKind::SystemOperations {
+                operations: strings(&["set_env", "set_volume", "set_brightness", "shutdown", "restart"]),
+            },
+            on_match: PolicyVerdict::Confirm,
+        },
+        PolicyRule {
+            name: "input_flood".to_string(),
+            kind: PolicyRuleKind::RateLimit {
+                action_type: None,
+                max: 100,
+                per_secs: 1,
+            },
+            on_match: PolicyVerdict::Deny,
+        },
+    ]
+}
+
 impl Default for Config {
     fn default() -> Self {
         Self {
@@ -59,6 +310,10 @@ impl Default for Config {
                 event_monitoring: true,
                 memory_persistence: true,
             },
+            vision: VisionConfig::default(),
+            policy: PolicyConfig::default(),
+            watches: Vec::new(),
+            process_monitor: ProcessMonitorConfig::default(),
         }
     }
 }
@@ -1,5 +1,46 @@
 /// Cross-platform path utilities for digiOS
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+
+/// Expand a leading `~` to the user's home directory
+pub fn expand_home(path: &str) -> PathBuf {
+    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+    match (path.strip_prefix('~'), home) {
+        (Some(rest), Ok(home)) if rest.is_empty() || rest.starts_with(['/', '\\']) => {
+            PathBuf::from(format!("{}{}", home, rest))
+        }
+        _ => PathBuf::from(path),
+    }
+}
+
+/// `path` made absolute, with symlinks resolved for as much of it as exists
+///
+/// The part that does not exist yet is appended with `.` and `..` resolved
+/// lexically, so the result says where the path would really end up once
+/// created, e.g. for a path under a symlink to another directory.
+pub fn resolve_existing(path: &Path) -> PathBuf {
+    let absolute = std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut resolved = PathBuf::new();
+    let mut exists = true;
+    for component in absolute.components() {
+        match component {
+            Component::CurDir => {}
+            // `resolved` is canonical while it exists, so its parent is the real one
+            Component::ParentDir => {
+                resolved.pop();
+            }
+            other => {
+                resolved.push(other);
+                if exists {
+                    match std::fs::canonicalize(&resolved) {
+                        Ok(real) => resolved = real,
+                        Err(_) => exists = false,
+                    }
+                }
+            }
+        }
+    }
+    resolved
+}
 
 pub fn get_config_dir() -> PathBuf {
     if cfg!(windows) {
@@ -42,3 +83,7 @@ pub fn get_memory_dir() -> PathBuf {
     get_data_dir().join("memory")
 }
 
+pub fn get_screenshots_dir() -> PathBuf {
+    get_data_dir().join("screenshots")
+}
+
@@ -1,4 +1,5 @@
 pub mod system;
+pub mod watch;
 
-pub use system::EventSystem;
-
+pub use system::{Event, EventFilter, EventSystem, FileChange, Subscription, TaskState};
+pub use watch::{FileWatcher, WatchInfo};
@@ -1,19 +1,347 @@
-use anyhow::Result;
-use tracing::info;
+use crate::core::config::WatchSpec;
+use crate::event::watch::{FileWatcher, WatchInfo};
+use crate::state::{ClipboardState, WindowInfo};
+use crate::vision::ChangedRegion;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{info, warn};
 
+/// Events buffered per subscriber before slow receivers start missing some
+const EVENT_CAPACITY: usize = 256;
+
+/// Something that happened on the system
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// The action engine finished an action, successfully or not
+    ActionExecuted {
+        timestamp: DateTime<Utc>,
+        action_id: String,
+        action_type: String,
+        caller: String,
+        success: bool,
+        error: Option<String>,
+        duration_ms: u64,
+    },
+    ProcessStarted {
+        timestamp: DateTime<Utc>,
+        pid: u32,
+        name: String,
+        cmdline: Vec<String>,
+    },
+    ProcessExited {
+        timestamp: DateTime<Utc>,
+        pid: u32,
+        name: String,
+        /// Only known for processes digiOS spawned itself
+        exit_code: Option<i32>,
+    },
+    /// A process stayed over the CPU threshold for the configured time
+    HighCpu {
+        timestamp: DateTime<Utc>,
+        pid: u32,
+        name: String,
+        /// Percent of one core
+        cpu_usage: f32,
+        threshold: f32,
+    },
+    /// A process stayed over the memory threshold for the configured time
+    HighMemory {
+        timestamp: DateTime<Utc>,
+        pid: u32,
+        name: String,
+        memory_bytes: u64,
+        /// Percent of total memory
+        memory_percent: f64,
+        threshold: f64,
+    },
+    WindowFocused {
+        timestamp: DateTime<Utc>,
+        window: WindowInfo,
+    },
+    FileChanged {
+        timestamp: DateTime<Utc>,
+        /// Id of the watch that saw the change
+        watch: String,
+        path: PathBuf,
+        change: FileChange,
+        /// Previous path, for renames
+        #[serde(skip_serializing_if = "Option::is_none")]
+        from: Option<PathBuf>,
+    },
+    ModelLoaded {
+        timestamp: DateTime<Utc>,
+        model: String,
+    },
+    /// A plan started, made progress or finished
+    TaskStateChanged {
+        timestamp: DateTime<Utc>,
+        task_id: String,
+        caller: String,
+        state: TaskState,
+        completed_steps: usize,
+        total_steps: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// The clipboard holds something new; only its format, size and hash
+    /// are published
+    ClipboardChanged {
+        timestamp: DateTime<Utc>,
+        clipboard: ClipboardState,
+    },
+    /// The monitored part of the screen changed since the previous capture
+    ScreenChanged {
+        timestamp: DateTime<Utc>,
+        monitor: Option<usize>,
+        changed_fraction: f64,
+        perceptual_difference: f64,
+        /// In screen coordinates, largest first
+        regions: Vec<ChangedRegion>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileChange {
+    Created,
+    Modified,
+    Deleted,
+    Renamed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Started,
+    /// Another step finished
+    Progress,
+    Completed,
+    /// Finished, but at least one step failed
+    Failed,
+    /// Stopped early by a failed assertion
+    Aborted,
+}
+
+/// Event types, listed once: the match in `kind` is exhaustive, so a new
+/// variant cannot be left out of `TYPES`
+macro_rules! event_types {
+    ($($variant:ident => $name:literal,)*) => {
+        impl Event {
+            /// Every event type, as it appears in the `type` field
+            pub const TYPES: &'static [&'static str] = &[$($name),*];
+
+            /// The `type` field of this event
+            pub fn kind(&self) -> &'static str {
+                match self {
+                    $(Event::$variant { .. } => $name,)*
+                }
+            }
+        }
+    };
+}
+
+event_types! {
+    ActionExecuted => "action_executed",
+    ProcessStarted => "process_started",
+    ProcessExited => "process_exited",
+    HighCpu => "high_cpu",
+    HighMemory => "high_memory",
+    WindowFocused => "window_focused",
+    FileChanged => "file_changed",
+    ModelLoaded => "model_loaded",
+    TaskStateChanged => "task_state_changed",
+    ClipboardChanged => "clipboard_changed",
+    ScreenChanged => "screen_changed",
+}
+
+impl Event {
+    pub fn timestamp(&self) -> DateTime<Utc> {
+        match self {
+            Event::ActionExecuted { timestamp, .. }
+            | Event::ProcessStarted { timestamp, .. }
+            | Event::ProcessExited { timestamp, .. }
+            | Event::HighCpu { timestamp, .. }
+            | Event::HighMemory { timestamp, .. }
+            | Event::WindowFocused { timestamp, .. }
+            | Event::FileChanged { timestamp, .. }
+            | Event::ModelLoaded { timestamp, .. }
+            | Event::TaskStateChanged { timestamp, .. }
+            | Event::ClipboardChanged { timestamp, .. }
+            | Event::ScreenChanged { timestamp, .. } => *timestamp,
+        }
+    }
+
+    fn pid(&self) -> Option<u32> {
+        match self {
+            Event::ProcessStarted { pid, .. }
+            | Event::ProcessExited { pid, .. }
+            | Event::HighCpu { pid, .. }
+            | Event::HighMemory { pid, .. } => Some(*pid),
+            Event::WindowFocused { window, .. } => window.pid,
+            _ => None,
+        }
+    }
+
+    /// Who caused the event, for events that record it
+    pub fn caller(&self) -> Option<&str> {
+        match self {
+            Event::ActionExecuted { caller, .. } | Event::TaskStateChanged { caller, .. } => {
+                Some(caller)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Which events a subscriber wants; every field that is set must match
+///
+/// A field an event doesn't carry counts as a mismatch, so e.g. a `pid`
+/// filter only passes process and window events.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EventFilter {
+    /// Event types, e.g. `process_started`; empty means all
+    #[serde(default)]
+    pub types: Vec<String>,
+    #[serde(default)]
+    pub pid: Option<u32>,
+    /// File events at or below this path
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    #[serde(default)]
+    pub action_type: Option<String>,
+    #[serde(default)]
+    pub caller: Option<String>,
+}
+
+impl EventFilter {
+    /// Only events of the given types
+    pub fn types(types: &[&str]) -> Self {
+        Self {
+            types: types.iter().map(|t| t.to_string()).collect(),
+            ..Self::default()
+        }
+    }
+
+    /// Reject unknown event types, which would otherwise silently match nothing
+    pub fn validate(&self) -> Result<()> {
+        for kind in &self.types {
+            if !Event::TYPES.contains(&kind.as_str()) {
+                bail!("Unknown event type {:?}; known types: {}", kind, Event::TYPES.join(", "));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn matches(&self, event: &Event) -> bool {
+        if !self.types.is_empty() && !self.types.iter().any(|t| t == event.kind()) {
+            return false;
+        }
+        if self.pid.is_some() && event.pid() != self.pid {
+            return false;
+        }
+        if let Some(prefix) = &self.path {
+            match event {
+                Event::FileChanged { path, .. } if path.starts_with(prefix) => {}
+                _ => return false,
+            }
+        }
+        if let Some(wanted) = &self.action_type {
+            match event {
+                Event::ActionExecuted { action_type, .. } if action_type == wanted => {}
+                _ => return false,
+            }
+        }
+        if let Some(wanted) = &self.caller {
+            if event.caller() != Some(wanted.as_str()) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// A filtered view of the bus, from the moment it was created
+pub struct Subscription {
+    receiver: broadcast::Receiver<Event>,
+    filter: EventFilter,
+    missed: u64,
+}
+
+impl Subscription {
+    /// Next matching event; `None` once the event system is gone
+    pub async fn recv(&mut self) -> Option<Event> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) if self.filter.matches(&event) => return Some(event),
+                Ok(_) => {}
+                Err(RecvError::Lagged(count)) => {
+                    warn!("Event subscriber fell behind and missed {} events", count);
+                    self.missed += count;
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+
+    /// Events dropped because this subscriber didn't keep up
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    pub fn filter(&self) -> &EventFilter {
+        &self.filter
+    }
+
+    /// Change which events are delivered from now on
+    pub fn set_filter(&mut self, filter: EventFilter) {
+        self.filter = filter;
+    }
+}
+
+/// Event System - Publish/subscribe bus shared by every subsystem
+///
+/// Publishing never blocks: each subscriber buffers up to `EVENT_CAPACITY`
+/// events and skips ahead (counting what it missed) when it falls behind.
 pub struct EventSystem {
-    // TODO: Implement event monitoring
+    sender: broadcast::Sender<Event>,
+    watcher: FileWatcher,
 }
 
 impl EventSystem {
     pub async fn new() -> Result<Self> {
         info!("Initializing Event System");
-        Ok(Self {})
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        let watcher = FileWatcher::new(sender.clone());
+        Ok(Self { sender, watcher })
     }
 
-    pub async fn process_events(&self) -> Result<()> {
-        // TODO: Process system events
-        Ok(())
+    /// Deliver an event to every current subscriber; dropped if there are none
+    pub fn publish(&self, event: Event) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self, filter: EventFilter) -> Subscription {
+        Subscription {
+            receiver: self.sender.subscribe(),
+            filter,
+            missed: 0,
+        }
     }
-}
 
+    /// Start watching a directory, publishing `FileChanged` events for it
+    pub fn watch(&self, spec: WatchSpec) -> Result<WatchInfo> {
+        self.watcher.add(spec)
+    }
+
+    pub fn unwatch(&self, id: &str) -> Result<WatchInfo> {
+        self.watcher.remove(id)
+    }
+
+    pub fn watches(&self) -> Vec<WatchInfo> {
+        self.watcher.list()
+    }
+}
@@ -0,0 +1,370 @@
+use crate::core::config::WatchSpec;
+use crate::core::paths::expand_home;
+use crate::event::system::{Event, FileChange};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use notify::event::{ModifyKind, RenameMode};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// A file that keeps changing is still reported after this many debounce windows
+const MAX_DEBOUNCE_WINDOWS: u32 = 10;
+
+/// A directory being watched
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchInfo {
+    pub id: String,
+    /// `spec.path` with `~` expanded
+    pub root: PathBuf,
+    #[serde(flatten)]
+    pub spec: WatchSpec,
+    pub created_at: DateTime<Utc>,
+}
+
+struct Watch {
+    info: WatchInfo,
+    patterns: Vec<glob::Pattern>,
+    // Dropping the watcher removes its inotify watches
+    _watcher: RecommendedWatcher,
+}
+
+impl Watch {
+    /// Whether `path` passes the watch's glob patterns
+    fn wants(&self, path: &Path) -> bool {
+        if self.patterns.is_empty() {
+            return true;
+        }
+        let relative = path.strip_prefix(&self.info.root).unwrap_or(path);
+        let name = path.file_name().map(Path::new).unwrap_or(relative);
+        self.patterns.iter().any(|pattern| {
+            if pattern.as_str().contains('/') {
+                pattern.matches_path(relative)
+            } else {
+                pattern.matches_path(name)
+            }
+        })
+    }
+}
+
+/// A change waiting out its debounce window
+struct Pending {
+    change: FileChange,
+    from: Option<PathBuf>,
+    first_seen: Instant,
+    due: Instant,
+}
+
+/// File Watcher - Directory watches (inotify on Linux) feeding `FileChanged` events
+///
+/// Raw notifications go through a debouncer that coalesces bursts per file,
+/// e.g. create + write becomes one `created`, and create + delete nothing.
+pub struct FileWatcher {
+    watches: Arc<Mutex<HashMap<String, Watch>>>,
+    raw: mpsc::UnboundedSender<(String, notify::Event)>,
+}
+
+impl FileWatcher {
+    pub fn new(sender: broadcast::Sender<Event>) -> Self {
+        let (raw, receiver) = mpsc::unbounded_channel();
+        let watches = Arc::new(Mutex::new(HashMap::new()));
+        tokio::spawn(debounce(receiver, watches.clone(), sender));
+        Self { watches, raw }
+    }
+
+    pub fn add(&self, spec: WatchSpec) -> Result<WatchInfo> {
+        let root = expand_home(&spec.path);
+        if !root.is_dir() {
+            bail!("{} is not a directory", root.display());
+        }
+        let patterns = spec
+            .patterns
+            .iter()
+            .map(|p| glob::Pattern::new(p).with_context(|| format!("Invalid watch pattern {:?}", p)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+        let raw = self.raw.clone();
+        let watch_id = id.clone();
+        let mut watcher = notify::recommended_watcher(move |result: notify::Result<notify::Event>| {
+            match result {
+                Ok(event) => {
+                    let _ = raw.send((watch_id.clone(), event));
+                }
+                Err(e) => warn!("File watch {} error: {}", watch_id, e),
+            }
+        })
+        .context("Failed to create file watcher")?;
+        let mode = if spec.recursive {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        watcher
+            .watch(&root, mode)
+            .with_context(|| format!("Failed to watch {}", root.display()))?;
+
+        let info = WatchInfo {
+            id: id.clone(),
+            root,
+            spec,
+            created_at: Utc::now(),
+        };
+        info!("Watching {} as {}", info.root.display(), id);
+        let mut watches = self.watches.lock().map_err(|_| anyhow!("Watch table poisoned"))?;
+        watches.insert(
+            id,
+            Watch {
+                info: info.clone(),
+                patterns,
+                _watcher: watcher,
+            },
+        );
+        Ok(info)
+    }
+
+    pub fn remove(&self, id: &str) -> Result<WatchInfo> {
+        let mut watches = self.watches.lock().map_err(|_| anyhow!("Watch table poisoned"))?;
+        let watch = watches
+            .remove(id)
+            .ok_or_else(|| anyhow!("No watch with id {}", id))?;
+        info!("Stopped watching {} ({})", watch.info.root.display(), id);
+        Ok(watch.info)
+    }
+
+    /// Active watches, oldest first
+    pub fn list(&self) -> Vec<WatchInfo> {
+        let Ok(watches) = self.watches.lock() else {
+            return Vec::new();
+        };
+        let mut list: Vec<WatchInfo> = watches.values().map(|w| w.info.clone()).collect();
+        list.sort_by_key(|w| w.created_at);
+        list
+    }
+}
+
+/// Collect raw notifications and publish each file's net change once its
+/// debounce window has passed without further activity
+async fn debounce(
+    mut raw: mpsc::UnboundedReceiver<(String, notify::Event)>,
+    watches: Arc<Mutex<HashMap<String, Watch>>>,
+    sender: broadcast::Sender<Event>,
+) {
+    let mut pending: HashMap<(String, PathBuf), Pending> = HashMap::new();
+    loop {
+        let next_due = pending.values().map(|p| p.due).min();
+        tokio::select! {
+            received = raw.recv() => {
+                let Some((id, mut event)) = received else {
+                    return;
+                };
+                let delay = {
+                    let Ok(watches) = watches.lock() else {
+                        return;
+                    };
+                    let Some(watch) = watches.get(&id) else {
+                        // Removed while the notification was in flight
+                        continue;
+                    };
+                    let Some(wanted) = filter_paths(event, |path| watch.wants(path)) else {
+                        continue;
+                    };
+                    event = wanted;
+                    Duration::from_millis(watch.info.spec.debounce_ms)
+                };
+                record(&mut pending, &id, event, delay);
+            }
+            _ = tokio::time::sleep_until(next_due.unwrap_or_else(Instant::now)), if next_due.is_some() => {
+                let now = Instant::now();
+                let due: Vec<_> = pending
+                    .iter()
+                    .filter(|(_, p)| p.due <= now)
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                for key in due {
+                    let Some(change) = pending.remove(&key) else {
+                        continue;
+                    };
+                    let (watch, path) = key;
+                    let _ = sender.send(Event::FileChanged {
+                        timestamp: Utc::now(),
+                        watch,
+                        path,
+                        change: change.change,
+                        from: change.from,
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// `event` with only the paths `wants` lets through, or `None` if none is left
+///
+/// A rename with one side filtered out becomes a delete or a create of the
+/// other side, so the filtered-out name never shows up in `from`.
+fn filter_paths(mut event: notify::Event, wants: impl Fn(&Path) -> bool) -> Option<notify::Event> {
+    if let (EventKind::Modify(ModifyKind::Name(RenameMode::Both)), [from, to]) =
+        (event.kind, &event.paths[..])
+    {
+        let (mode, keep) = match (wants(from), wants(to)) {
+            (true, true) => return Some(event),
+            (true, false) => (RenameMode::From, 0),
+            (false, true) => (RenameMode::To, 1),
+            (false, false) => return None,
+        };
+        event.kind = EventKind::Modify(ModifyKind::Name(mode));
+        event.paths = vec![event.paths.swap_remove(keep)];
+        return Some(event);
+    }
+    event.paths.retain(|path| wants(path));
+    (!event.paths.is_empty()).then_some(event)
+}
+
+/// Fold one raw notification into the pending changes
+fn record(
+    pending: &mut HashMap<(String, PathBuf), Pending>,
+    id: &str,
+    event: notify::Event,
+    delay: Duration,
+) {
+    let key = |path: &Path| (id.to_string(), path.to_path_buf());
+    let mut paths = event.paths.into_iter();
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(ModifyKind::Name(RenameMode::To)) => {
+            for path in paths {
+                update(pending, key(&path), FileChange::Created, None, delay);
+            }
+        }
+        EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(RenameMode::From)) => {
+            for path in paths {
+                update(pending, key(&path), FileChange::Deleted, None, delay);
+            }
+        }
+        EventKind::Modify(ModifyKind::Name(RenameMode::Both)) => {
+            let (Some(from), Some(to)) = (paths.next(), paths.next()) else {
+                return;
+            };
+            // The backend already reported both halves as a delete and a create
+            let source = pending.remove(&key(&from));
+            pending.remove(&key(&to));
+            if source.is_some_and(|p| p.change == FileChange::Created) {
+                // Created and renamed within one window is simply a new file
+                update(pending, key(&to), FileChange::Created, None, delay);
+            } else {
+                update(pending, key(&to), FileChange::Renamed, Some(from), delay);
+            }
+        }
+        EventKind::Modify(ModifyKind::Metadata(_)) | EventKind::Access(_) => {}
+        EventKind::Modify(_) | EventKind::Any | EventKind::Other => {
+            for path in paths {
+                update(pending, key(&path), FileChange::Modified, None, delay);
+            }
+        }
+    }
+}
+
+fn update(
+    pending: &mut HashMap<(String, PathBuf), Pending>,
+    key: (String, PathBuf),
+    change: FileChange,
+    from: Option<PathBuf>,
+    delay: Duration,
+) {
+    let now = Instant::now();
+    let merged = match pending.remove(&key) {
+        Some(previous) => merge(previous, change, from, now, delay),
+        None => Some(Pending {
+            change,
+            from,
+            first_seen: now,
+            due: now + delay,
+        }),
+    };
+    if let Some(merged) = merged {
+        pending.insert(key, merged);
+    }
+}
+
+/// Combine an earlier pending change with a new one; `None` cancels both
+fn merge(
+    previous: Pending,
+    change: FileChange,
+    from: Option<PathBuf>,
+    now: Instant,
+    delay: Duration,
+) -> Option<Pending> {
+    use FileChange::*;
+    let (change, from) = match (previous.change, change) {
+        (Created, Deleted) => return None,
+        (Created, _) => (Created, None),
+        (Deleted, Created) => (Modified, None),
+        (Renamed, Modified) => (Renamed, previous.from),
+        (_, change) => (change, from),
+    };
+    let deadline = previous.first_seen + delay * MAX_DEBOUNCE_WINDOWS;
+    Some(Pending {
+        change,
+        from,
+        first_seen: previous.first_seen,
+        due: (now + delay).min(deadline),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rename(from: &str, to: &str) -> notify::Event {
+        notify::Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::Both)))
+            .add_path(PathBuf::from(from))
+            .add_path(PathBuf::from(to))
+    }
+
+    fn pdfs(path: &Path) -> bool {
+        path.extension().is_some_and(|extension| extension == "pdf")
+    }
+
+    fn changes(event: notify::Event) -> Vec<(PathBuf, FileChange, Option<PathBuf>)> {
+        let mut pending = HashMap::new();
+        if let Some(event) = filter_paths(event, pdfs) {
+            record(&mut pending, "w", event, Duration::from_millis(100));
+        }
+        let mut changes: Vec<_> = pending
+            .into_iter()
+            .map(|((_, path), p)| (path, p.change, p.from))
+            .collect();
+        changes.sort_by(|a, b| a.0.cmp(&b.0));
+        changes
+    }
+
+    #[test]
+    fn renames_out_of_the_filter_are_deletes() {
+        let changes = changes(rename("/w/a.pdf", "/w/a.tmp"));
+        assert_eq!(changes, [(PathBuf::from("/w/a.pdf"), FileChange::Deleted, None)]);
+    }
+
+    #[test]
+    fn renames_into_the_filter_are_creates() {
+        let changes = changes(rename("/w/a.tmp", "/w/a.pdf"));
+        assert_eq!(changes, [(PathBuf::from("/w/a.pdf"), FileChange::Created, None)]);
+    }
+
+    #[test]
+    fn filtered_paths_are_dropped_from_events() {
+        let renamed = changes(rename("/w/a.pdf", "/w/b.pdf"));
+        let expected = (PathBuf::from("/w/b.pdf"), FileChange::Renamed, Some("/w/a.pdf".into()));
+        assert_eq!(renamed, [expected]);
+
+        let removed = notify::Event::new(EventKind::Remove(notify::event::RemoveKind::File))
+            .add_path(PathBuf::from("/w/a.tmp"))
+            .add_path(PathBuf::from("/w/c.pdf"));
+        assert_eq!(changes(removed), [(PathBuf::from("/w/c.pdf"), FileChange::Deleted, None)]);
+        assert!(changes(rename("/w/a.tmp", "/w/b.tmp")).is_empty());
+    }
+}
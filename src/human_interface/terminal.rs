@@ -1,17 +1,22 @@
-use anyhow::Result;
+use crate::task::{MacroSystem, RecordRequest, RecordSource, ReplayOptions};
+use anyhow::{bail, Result};
+use serde_json::Value;
 use tracing::info;
 use std::io::{self, Write};
+use std::sync::Arc;
 
 /// Terminal Interface - Allows humans to interact with digiOS
 #[derive(Clone)]
 pub struct TerminalInterface {
     running: bool,
+    macros: Option<Arc<MacroSystem>>,
 }
 
 impl TerminalInterface {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(macros: Option<Arc<MacroSystem>>) -> Result<Self> {
         Ok(Self {
             running: false,
+            macros,
         })
     }
 
@@ -34,7 +39,11 @@ impl TerminalInterface {
                 continue;
             }
             
-            match command {
+            let mut words = command.split_whitespace();
+            let verb = words.next().unwrap_or_default();
+            let args: Vec<&str> = words.collect();
+
+            match verb {
                 "exit" | "quit" => {
                     println!("Goodbye!");
                     break;
@@ -48,6 +57,11 @@ impl TerminalInterface {
                 "capabilities" => {
                     Self::show_capabilities().await?;
                 }
+                "macros" | "macro" => {
+                    if let Err(e) = self.macro_command(&args).await {
+                        println!("Error: {:#}", e);
+                    }
+                }
                 _ => {
                     println!("Unknown command: {}. Type 'help' for available commands.", command);
                 }
@@ -62,10 +76,95 @@ impl TerminalInterface {
         println!("  help         - Show this help message");
         println!("  status       - Show system status");
         println!("  capabilities - Show system capabilities");
+        println!("  macros       - List recorded macros");
+        println!("  macro show <name>                          - Show a macro's steps");
+        println!("  macro run <name> [var=value ...] [speed=N] - Replay a macro");
+        println!("  macro record <name> [input]                - Record engine actions (or real input)");
+        println!("  macro stop                                 - Stop recording and save");
+        println!("  macro delete <name>                        - Delete a macro");
         println!("  exit/quit    - Exit terminal interface");
         println!();
     }
 
+    async fn macro_command(&self, args: &[&str]) -> Result<()> {
+        let Some(macros) = &self.macros else {
+            bail!("Macros are not available");
+        };
+        match args {
+            [] | ["list"] => {
+                let list = macros.list()?;
+                if list.is_empty() {
+                    println!("No macros recorded");
+                }
+                for summary in list {
+                    println!(
+                        "  {:<24} {:>3} steps  {:>7} ms  {}",
+                        summary["name"].as_str().unwrap_or_default(),
+                        summary["steps"],
+                        summary["duration_ms"],
+                        summary["description"].as_str().unwrap_or_default(),
+                    );
+                }
+                if let Some(recording) = macros.recording() {
+                    println!("Recording: {}", recording["recording"]["name"]);
+                }
+            }
+            ["show", name] => {
+                println!("{}", serde_json::to_string_pretty(&macros.get(name)?)?);
+            }
+            ["run", name, rest @ ..] => {
+                let mut options = ReplayOptions::default();
+                for arg in rest {
+                    let Some((key, value)) = arg.split_once('=') else {
+                        bail!("Expected var=value, got {}", arg);
+                    };
+                    if key == "speed" {
+                        options.speed = value.parse()?;
+                    } else {
+                        // Numbers and booleans keep their type; anything else is a string
+                        let value = serde_json::from_str(value)
+                            .unwrap_or_else(|_| Value::String(value.to_string()));
+                        options.variables.insert(key.to_string(), value);
+                    }
+                }
+                let stored = macros.get(name)?;
+                let actions = stored.resolve(&options.variables)?;
+                let result = macros.replay(&stored, actions, &options).await?;
+                println!(
+                    "Macro {}: {} of {} steps run, {}",
+                    name,
+                    result["executed"],
+                    result["total"],
+                    if result["success"] == true { "succeeded" } else { "failed" },
+                );
+            }
+            ["record", name, rest @ ..] => {
+                let source = match rest {
+                    [] => RecordSource::Actions,
+                    ["input"] => RecordSource::Input,
+                    _ => bail!("Usage: macro record <name> [input]"),
+                };
+                macros.start_recording(RecordRequest {
+                    name: name.to_string(),
+                    description: String::new(),
+                    source,
+                    caller: None,
+                })?;
+                println!("Recording macro {}; run 'macro stop' to save it", name);
+            }
+            ["stop"] => {
+                let recorded = macros.stop_recording().await?;
+                println!("Saved macro {} ({} steps)", recorded.name, recorded.steps.len());
+            }
+            ["delete", name] => {
+                macros.delete(name)?;
+                println!("Deleted macro {}", name);
+            }
+            _ => bail!("Unknown macro command; type 'help' for usage"),
+        }
+        Ok(())
+    }
+
     async fn show_status() -> Result<()> {
         println!("\nSystem Status:");
         println!("  Status: Running");
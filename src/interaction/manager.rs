@@ -1,4 +1,5 @@
 use crate::human_interface::TerminalInterface;
+use crate::task::MacroSystem;
 use anyhow::Result;
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -24,9 +25,9 @@ impl InteractionManager {
         })
     }
 
-    pub async fn start_terminal(&self) -> Result<()> {
+    pub async fn start_terminal(&self, macros: Option<Arc<MacroSystem>>) -> Result<()> {
         info!("Starting terminal interface");
-        let terminal = TerminalInterface::new().await?;
+        let terminal = TerminalInterface::new(macros).await?;
         
         // Clone terminal for background task
         let mut terminal_clone = terminal.clone();
@@ -1,3 +1,4 @@
+use aios::action::LifecycleRequest;
 use aios::boot::InitSystem;
 use anyhow::Result;
 use tracing::info;
@@ -27,11 +28,46 @@ async fn main() -> Result<()> {
     // Interaction system is started by init system
     // Human interface will be enabled if DIGIOS_HUMAN_INTERFACE is set
 
-    // Keep running until interrupted
-    tokio::signal::ctrl_c().await?;
-    
+    // Keep running until interrupted or asked to stop through a system operation
+    let request = match init.lifecycle_requests() {
+        Some(mut requests) => tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result?;
+                None
+            }
+            request = requests.wait_for(|r| r.is_some()) => request.ok().and_then(|r| *r),
+        },
+        None => {
+            tokio::signal::ctrl_c().await?;
+            None
+        }
+    };
+
+    if request == Some(LifecycleRequest::Restart) {
+        info!("Restarting digiOS...");
+        return restart();
+    }
+
     info!("Shutting down digiOS...");
     // Cleanup will happen automatically
 
     Ok(())
 }
+
+/// Replace this process with a fresh copy of itself
+#[cfg(unix)]
+fn restart() -> Result<()> {
+    use std::os::unix::process::CommandExt;
+    let error = std::process::Command::new(std::env::current_exe()?)
+        .args(std::env::args_os().skip(1))
+        .exec();
+    Err(error.into())
+}
+
+#[cfg(not(unix))]
+fn restart() -> Result<()> {
+    std::process::Command::new(std::env::current_exe()?)
+        .args(std::env::args_os().skip(1))
+        .spawn()?;
+    Ok(())
+}
@@ -1,6 +1,6 @@
 use crate::core::paths;
 use anyhow::Result;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tracing::info;
 
 pub struct MemorySystem {
@@ -21,5 +21,9 @@ impl MemorySystem {
         
         Ok(Self { path: memory_path })
     }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
 }
 
@@ -0,0 +1,308 @@
+use crate::action::schema::{ClipboardFormat, ClipboardGetParams, ClipboardSetParams};
+use crate::core::paths::expand_home;
+use anyhow::{anyhow, bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::BuildHasher;
+use std::io::Cursor;
+use std::sync::OnceLock;
+
+/// What the clipboard holds, as reported in state snapshots and change events
+///
+/// Only summaries: the contents may be passwords or other secrets, and go
+/// through `clipboard_get` instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ClipboardState {
+    pub text: Option<ClipboardText>,
+    pub image: Option<ClipboardImage>,
+}
+
+/// Summary of text on the clipboard
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClipboardText {
+    pub chars: usize,
+    pub bytes: usize,
+    pub hash: String,
+}
+
+/// Summary of a PNG on the clipboard
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ClipboardImage {
+    pub width: u32,
+    pub height: u32,
+    pub bytes: usize,
+    pub hash: String,
+}
+
+impl ClipboardText {
+    fn describe(text: &str) -> Self {
+        Self {
+            chars: text.chars().count(),
+            bytes: text.len(),
+            hash: content_hash(text.as_bytes()),
+        }
+    }
+}
+
+impl ClipboardImage {
+    fn describe(png: &[u8]) -> Result<Self> {
+        let (width, height) = image::io::Reader::new(Cursor::new(png))
+            .with_guessed_format()?
+            .into_dimensions()
+            .context("Clipboard image is not a readable PNG")?;
+        Ok(Self {
+            width,
+            height,
+            bytes: png.len(),
+            hash: content_hash(png),
+        })
+    }
+}
+
+/// Hash that tells contents apart within this process
+///
+/// The key is random per process, so a published hash of short text cannot
+/// be looked up in a precomputed table or compared across restarts.
+fn content_hash(bytes: &[u8]) -> String {
+    static KEY: OnceLock<RandomState> = OnceLock::new();
+    format!("{:016x}", KEY.get_or_init(RandomState::new).hash_one(bytes))
+}
+
+/// Clipboard System - Reads and replaces the clipboard (text and PNG images)
+///
+/// Text goes through the `clipboard` crate; images use the `image/png`
+/// selection target, which only the X11 backend supports. The clipboard
+/// connections live for the whole process so the selections we set stay
+/// available to other applications.
+pub struct ClipboardSystem;
+
+impl ClipboardSystem {
+    pub fn text() -> Result<Option<String>> {
+        let text = platform::get_text()?;
+        Ok((!text.is_empty()).then_some(text))
+    }
+
+    pub fn set_text(text: String) -> Result<()> {
+        platform::set_text(text)
+    }
+
+    /// PNG bytes, if the clipboard currently offers an image
+    pub fn png() -> Result<Option<Vec<u8>>> {
+        let png = platform::get_png()?;
+        Ok((!png.is_empty()).then_some(png))
+    }
+
+    pub fn set_png(png: Vec<u8>) -> Result<()> {
+        platform::set_png(png)
+    }
+
+    pub fn state() -> Result<ClipboardState> {
+        let text = Self::text()?.map(|text| ClipboardText::describe(&text));
+        // Images are optional: a backend without image support still reports text
+        let image = match Self::png() {
+            Ok(Some(png)) => ClipboardImage::describe(&png).ok(),
+            _ => None,
+        };
+        Ok(ClipboardState { text, image })
+    }
+
+    pub async fn get(params: ClipboardGetParams) -> Result<Value> {
+        tokio::task::spawn_blocking(move || Self::run_get(params)).await?
+    }
+
+    pub async fn set(params: ClipboardSetParams) -> Result<Value> {
+        tokio::task::spawn_blocking(move || Self::run_set(params)).await?
+    }
+
+    fn run_get(params: ClipboardGetParams) -> Result<Value> {
+        match params.format {
+            ClipboardFormat::Text => {
+                let text = Self::text()?;
+                Ok(json!({"format": "text", "text": text}))
+            }
+            ClipboardFormat::Image => {
+                let png = Self::png()?.ok_or_else(|| anyhow!("Clipboard holds no PNG image"))?;
+                let image = ClipboardImage::describe(&png)?;
+                let mut result = json!({
+                    "format": "image",
+                    "width": image.width,
+                    "height": image.height,
+                    "bytes": image.bytes,
+                });
+                match params.path {
+                    Some(path) => {
+                        let target = expand_home(&path);
+                        if let Some(parent) = target.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::write(&target, &png)
+                            .with_context(|| format!("Failed to write {}", target.display()))?;
+                        result["path"] = json!(target);
+                    }
+                    None => result["data"] = json!(BASE64.encode(&png)),
+                }
+                Ok(result)
+            }
+        }
+    }
+
+    fn run_set(params: ClipboardSetParams) -> Result<Value> {
+        match params {
+            ClipboardSetParams::Text { text } => {
+                let characters = text.chars().count();
+                Self::set_text(text)?;
+                Ok(json!({"format": "text", "characters": characters}))
+            }
+            ClipboardSetParams::Image { path, data } => {
+                let bytes = match (path, data) {
+                    (Some(path), None) => {
+                        let source = expand_home(&path);
+                        fs::read(&source)
+                            .with_context(|| format!("Failed to read {}", source.display()))?
+                    }
+                    (None, Some(data)) => {
+                        BASE64.decode(data).context("Invalid base64 image data")?
+                    }
+                    _ => bail!("Image clipboard_set needs exactly one of path or data"),
+                };
+                let png = to_png(bytes)?;
+                let image = ClipboardImage::describe(&png)?;
+                Self::set_png(png)?;
+                Ok(json!({
+                    "format": "image",
+                    "width": image.width,
+                    "height": image.height,
+                    "bytes": image.bytes,
+                }))
+            }
+        }
+    }
+}
+
+/// Pass PNG data through unchanged and convert any other image format
+fn to_png(bytes: Vec<u8>) -> Result<Vec<u8>> {
+    let format = image::guess_format(&bytes).context("Unrecognized image data")?;
+    if format == image::ImageFormat::Png {
+        return Ok(bytes);
+    }
+    let decoded = image::load_from_memory_with_format(&bytes, format)?;
+    let mut png = Vec::new();
+    decoded.write_to(&mut Cursor::new(&mut png), image::ImageOutputFormat::Png)?;
+    Ok(png)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use anyhow::{anyhow, Result};
+    use clipboard::{ClipboardContext, ClipboardProvider};
+    use std::sync::{Mutex, MutexGuard};
+    use std::time::Duration;
+    use x11_clipboard::Clipboard;
+
+    const PNG_TARGET: &str = "image/png";
+    const LOAD_TIMEOUT: Duration = Duration::from_secs(3);
+
+    // Each context owns a worker thread that serves our selections to other
+    // clients, so they are created once and never dropped
+    static TEXT: Mutex<Option<ClipboardContext>> = Mutex::new(None);
+    static IMAGE: Mutex<Option<Clipboard>> = Mutex::new(None);
+
+    fn text_context() -> Result<MutexGuard<'static, Option<ClipboardContext>>> {
+        let mut context = TEXT
+            .lock()
+            .map_err(|_| anyhow!("Clipboard lock poisoned"))?;
+        if context.is_none() {
+            let created = ClipboardContext::new()
+                .map_err(|e| anyhow!("Clipboard unavailable (is DISPLAY set?): {}", e))?;
+            *context = Some(created);
+        }
+        Ok(context)
+    }
+
+    fn image_context() -> Result<MutexGuard<'static, Option<Clipboard>>> {
+        let mut context = IMAGE
+            .lock()
+            .map_err(|_| anyhow!("Clipboard lock poisoned"))?;
+        if context.is_none() {
+            let created = Clipboard::new()
+                .map_err(|e| anyhow!("Clipboard unavailable (is DISPLAY set?): {}", e))?;
+            *context = Some(created);
+        }
+        Ok(context)
+    }
+
+    pub fn get_text() -> Result<String> {
+        let mut context = text_context()?;
+        let context = context.as_mut().expect("initialized above");
+        context
+            .get_contents()
+            .map_err(|e| anyhow!("Failed to read clipboard text: {}", e))
+    }
+
+    pub fn set_text(text: String) -> Result<()> {
+        let mut context = text_context()?;
+        let context = context.as_mut().expect("initialized above");
+        context
+            .set_contents(text)
+            .map_err(|e| anyhow!("Failed to set clipboard text: {}", e))
+    }
+
+    /// Empty when the clipboard owner cannot convert to PNG
+    pub fn get_png() -> Result<Vec<u8>> {
+        let context = image_context()?;
+        let clipboard = context.as_ref().expect("initialized above");
+        let atoms = &clipboard.getter.atoms;
+        let target = clipboard
+            .getter
+            .get_atom(PNG_TARGET)
+            .map_err(|e| anyhow!("Failed to intern {}: {}", PNG_TARGET, e))?;
+        clipboard
+            .load(atoms.clipboard, target, atoms.property, LOAD_TIMEOUT)
+            .map_err(|e| anyhow!("Failed to read clipboard image: {}", e))
+    }
+
+    pub fn set_png(png: Vec<u8>) -> Result<()> {
+        let context = image_context()?;
+        let clipboard = context.as_ref().expect("initialized above");
+        let target = clipboard
+            .setter
+            .get_atom(PNG_TARGET)
+            .map_err(|e| anyhow!("Failed to intern {}: {}", PNG_TARGET, e))?;
+        clipboard
+            .store(clipboard.setter.atoms.clipboard, target, png)
+            .map_err(|e| anyhow!("Failed to set clipboard image: {}", e))
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use anyhow::{anyhow, bail, Result};
+    use clipboard::{ClipboardContext, ClipboardProvider};
+
+    fn context() -> Result<ClipboardContext> {
+        ClipboardContext::new().map_err(|e| anyhow!("Clipboard unavailable: {}", e))
+    }
+
+    pub fn get_text() -> Result<String> {
+        context()?
+            .get_contents()
+            .map_err(|e| anyhow!("Failed to read clipboard text: {}", e))
+    }
+
+    pub fn set_text(text: String) -> Result<()> {
+        context()?
+            .set_contents(text)
+            .map_err(|e| anyhow!("Failed to set clipboard text: {}", e))
+    }
+
+    pub fn get_png() -> Result<Vec<u8>> {
+        bail!("Clipboard images are only supported on X11")
+    }
+
+    pub fn set_png(_png: Vec<u8>) -> Result<()> {
+        bail!("Clipboard images are only supported on X11")
+    }
+}
@@ -1,20 +1,80 @@
 use anyhow::Result;
 use serde_json::Value;
-use sysinfo::System;
-use tracing::info;
-use std::sync::Mutex;
+use sysinfo::{Pid, Process, System};
+use crate::action::ProcessManager;
+use crate::core::config::ProcessMonitorConfig;
+use crate::event::{Event, EventSystem};
+use crate::state::clipboard::{ClipboardState, ClipboardSystem};
+use crate::state::windows::WindowSystem;
+use tracing::{debug, info};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What `update` remembers about a process between refreshes
+struct TrackedProcess {
+    name: String,
+    /// Tells a reused pid apart from the process that had it before
+    start_time: u64,
+    high_cpu: Sustained,
+    high_memory: Sustained,
+}
+
+/// A usage alert that fires once usage has stayed over its threshold for a
+/// while, and re-arms when usage drops back under it
+#[derive(Default)]
+struct Sustained {
+    since: Option<Instant>,
+    fired: bool,
+}
+
+impl Sustained {
+    /// Whether the alert should fire now
+    fn observe(&mut self, over: bool, now: Instant, sustain: Duration) -> bool {
+        if !over {
+            *self = Self::default();
+            return false;
+        }
+        let since = *self.since.get_or_insert(now);
+        if self.fired || now.duration_since(since) < sustain {
+            return false;
+        }
+        self.fired = true;
+        true
+    }
+}
 
 pub struct StateManager {
     system: Mutex<System>,
+    /// Processes seen by the last `update`, `None` until the first refresh
+    processes: Mutex<Option<HashMap<u32, TrackedProcess>>>,
+    /// Where exit codes of processes digiOS spawned come from
+    children: Arc<ProcessManager>,
+    monitor: ProcessMonitorConfig,
+    /// Summary of the clipboard as last seen by `update`, `None` until the first successful read
+    clipboard: Mutex<Option<ClipboardState>>,
+    /// Id of the focused window at the last `update`, `None` until the first successful read
+    focused: Mutex<Option<Option<u32>>>,
+    events: Arc<EventSystem>,
 }
 
 impl StateManager {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(
+        events: Arc<EventSystem>,
+        children: Arc<ProcessManager>,
+        monitor: ProcessMonitorConfig,
+    ) -> Result<Self> {
         info!("Initializing State Manager");
         let system = System::new_all();
         
         Ok(Self {
             system: Mutex::new(system),
+            processes: Mutex::new(None),
+            children,
+            monitor,
+            clipboard: Mutex::new(None),
+            focused: Mutex::new(None),
+            events,
         })
     }
 
@@ -22,18 +82,214 @@ impl StateManager {
         // Refresh system state
         if let Ok(mut system) = self.system.lock() {
             system.refresh_all();
+            self.update_processes(&system);
         }
+        self.update_clipboard().await;
+        self.update_focus().await;
         Ok(())
     }
 
-    pub fn get_windows(&self) -> Vec<Value> {
-        // TODO: Implement window enumeration
-        vec![]
+    /// Diff the process table against the last refresh, publishing process
+    /// lifecycle and high usage events
+    fn update_processes(&self, system: &System) {
+        let Ok(mut tracked) = self.processes.lock() else {
+            return;
+        };
+        // Whatever runs at the first refresh was already there, not just started
+        let baseline = tracked.is_none();
+        let known = tracked.get_or_insert_with(HashMap::new);
+        let now = Instant::now();
+        let sustain = Duration::from_secs(self.monitor.sustain_secs);
+        let total_memory = system.total_memory().max(1);
+        let mut alive = HashSet::new();
+
+        for (pid, process) in system.processes() {
+            // Linux lists threads alongside processes
+            if process.thread_kind().is_some() {
+                continue;
+            }
+            let pid = pid.as_u32();
+            alive.insert(pid);
+            if known.get(&pid).is_some_and(|p| p.start_time != process.start_time()) {
+                // The pid was reused between two refreshes
+                if let Some(previous) = known.remove(&pid) {
+                    let exit = self.children.exit_of(pid, previous.start_time).flatten();
+                    self.publish_exit(pid, previous.name, exit.and_then(|exit| exit.code));
+                }
+            }
+            let entry = known.entry(pid).or_insert_with(|| {
+                if !baseline {
+                    self.events.publish(Event::ProcessStarted {
+                        timestamp: chrono::Utc::now(),
+                        pid,
+                        name: process.name().to_string(),
+                        cmdline: process.cmd().to_vec(),
+                    });
+                }
+                TrackedProcess {
+                    name: process.name().to_string(),
+                    start_time: process.start_time(),
+                    high_cpu: Sustained::default(),
+                    high_memory: Sustained::default(),
+                }
+            });
+
+            let cpu_usage = process.cpu_usage();
+            let threshold = self.monitor.high_cpu_percent;
+            if entry.high_cpu.observe(cpu_usage >= threshold, now, sustain) {
+                self.events.publish(Event::HighCpu {
+                    timestamp: chrono::Utc::now(),
+                    pid,
+                    name: entry.name.clone(),
+                    cpu_usage,
+                    threshold,
+                });
+            }
+            let memory_percent = process.memory() as f64 * 100.0 / total_memory as f64;
+            let threshold = self.monitor.high_memory_percent;
+            if entry.high_memory.observe(memory_percent >= threshold, now, sustain) {
+                self.events.publish(Event::HighMemory {
+                    timestamp: chrono::Utc::now(),
+                    pid,
+                    name: entry.name.clone(),
+                    memory_bytes: process.memory(),
+                    memory_percent,
+                    threshold,
+                });
+            }
+        }
+
+        let gone: Vec<u32> = known.keys().filter(|pid| !alive.contains(pid)).copied().collect();
+        for pid in gone {
+            let Some(start_time) = known.get(&pid).map(|process| process.start_time) else {
+                continue;
+            };
+            let exit_code = match self.children.exit_of(pid, start_time) {
+                // Our own child whose exit status isn't collected yet; report it next time
+                Some(None) => continue,
+                Some(Some(exit)) => exit.code,
+                None => None,
+            };
+            if let Some(process) = known.remove(&pid) {
+                self.publish_exit(pid, process.name, exit_code);
+            }
+        }
+    }
+
+    fn publish_exit(&self, pid: u32, name: String, exit_code: Option<i32>) {
+        self.events.publish(Event::ProcessExited {
+            timestamp: chrono::Utc::now(),
+            pid,
+            name,
+            exit_code,
+        });
     }
 
+    /// Publish `WindowFocused` when a different window gains focus
+    async fn update_focus(&self) {
+        let windows = match tokio::task::spawn_blocking(WindowSystem::list).await {
+            Ok(Ok(windows)) => windows,
+            Ok(Err(e)) => {
+                debug!("Window enumeration unavailable: {}", e);
+                return;
+            }
+            Err(_) => return,
+        };
+        let focused = windows.into_iter().find(|w| w.focused);
+        let Ok(mut last) = self.focused.lock() else {
+            return;
+        };
+        let id = focused.as_ref().map(|w| w.id);
+        let changed = last.is_some_and(|previous| previous != id);
+        *last = Some(id);
+        if let (true, Some(window)) = (changed, focused) {
+            self.events.publish(Event::WindowFocused {
+                timestamp: chrono::Utc::now(),
+                window,
+            });
+        }
+    }
+
+    /// Poll the clipboard and publish `ClipboardChanged` when its contents differ
+    async fn update_clipboard(&self) {
+        let current = match tokio::task::spawn_blocking(ClipboardSystem::state).await {
+            Ok(Ok(current)) => current,
+            Ok(Err(e)) => {
+                debug!("Clipboard unavailable: {:#}", e);
+                return;
+            }
+            Err(_) => return,
+        };
+        let Ok(mut last) = self.clipboard.lock() else {
+            return;
+        };
+        let changed = last.as_ref().is_some_and(|previous| *previous != current);
+        *last = Some(current.clone());
+        if changed {
+            self.events.publish(Event::ClipboardChanged {
+                timestamp: chrono::Utc::now(),
+                clipboard: current,
+            });
+        }
+    }
+
+    /// Clipboard summary as of the last `update`
+    pub fn get_clipboard(&self) -> Option<ClipboardState> {
+        self.clipboard.lock().ok().and_then(|c| c.clone())
+    }
+
+    /// Everything the state manager tracks, in one JSON document
+    pub async fn snapshot(&self) -> Value {
+        serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "windows": self.get_windows().await,
+            "processes": self.get_processes(),
+            "clipboard": self.get_clipboard(),
+        })
+    }
+
+    pub async fn get_windows(&self) -> Vec<Value> {
+        // Window enumeration makes blocking X11 round trips
+        match tokio::task::spawn_blocking(WindowSystem::list).await {
+            Ok(Ok(windows)) => windows
+                .into_iter()
+                .filter_map(|w| serde_json::to_value(w).ok())
+                .collect(),
+            Ok(Err(e)) => {
+                debug!("Window enumeration unavailable: {}", e);
+                vec![]
+            }
+            Err(_) => vec![],
+        }
+    }
+
+    /// Running processes, busiest first
     pub fn get_processes(&self) -> Vec<Value> {
-        // TODO: Implement process enumeration
-        vec![]
+        let Ok(system) = self.system.lock() else {
+            return vec![];
+        };
+        let mut processes: Vec<_> = system.processes().iter().collect();
+        processes.sort_by(|a, b| b.1.cpu_usage().total_cmp(&a.1.cpu_usage()));
+        processes
+            .into_iter()
+            .map(|(pid, process)| describe_process(*pid, process))
+            .collect()
     }
 }
 
+/// JSON summary of a sysinfo process, shared by state snapshots and `process_operation`
+pub fn describe_process(pid: Pid, process: &Process) -> Value {
+    serde_json::json!({
+        "pid": pid.as_u32(),
+        "parent_pid": process.parent().map(|p| p.as_u32()),
+        "name": process.name(),
+        "cmdline": process.cmd(),
+        "exe": process.exe(),
+        "status": process.status().to_string(),
+        "cpu_usage": process.cpu_usage(),
+        "memory_bytes": process.memory(),
+        "start_time": process.start_time(),
+        "run_time_secs": process.run_time(),
+    })
+}
+
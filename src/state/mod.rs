@@ -1,4 +1,7 @@
+pub mod clipboard;
 pub mod manager;
+pub mod windows;
 
-pub use manager::StateManager;
-
+pub use clipboard::{ClipboardImage, ClipboardState, ClipboardSystem, ClipboardText};
+pub use manager::{describe_process, StateManager};
+pub use windows::{WindowInfo, WindowSystem};
@@ -0,0 +1,370 @@
+use crate::action::schema::{WindowOperation, WindowSelector};
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// A top-level application window
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowInfo {
+    pub id: u32,
+    pub title: String,
+    pub class: String,
+    pub pid: Option<u32>,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub focused: bool,
+    pub workspace: Option<u32>,
+    pub minimized: bool,
+    pub maximized: bool,
+}
+
+impl WindowSelector {
+    pub fn matches(&self, window: &WindowInfo) -> bool {
+        let contains = |haystack: &str, needle: &Option<String>| {
+            needle
+                .as_ref()
+                .is_none_or(|n| haystack.to_lowercase().contains(&n.to_lowercase()))
+        };
+        self.id.is_none_or(|id| id == window.id)
+            && contains(&window.title, &self.title)
+            && contains(&window.class, &self.class)
+    }
+}
+
+/// Window System - Enumerates and manipulates windows through X11/EWMH
+pub struct WindowSystem;
+
+impl WindowSystem {
+    /// Top-level windows in stacking order, bottom to top
+    pub fn list() -> Result<Vec<WindowInfo>> {
+        platform::list()
+    }
+
+    /// The topmost visible window containing the screen point, if any
+    pub fn at(x: i32, y: i32) -> Result<Option<WindowInfo>> {
+        Ok(Self::list()?.into_iter().rev().find(|w| {
+            let (x, y) = (i64::from(x), i64::from(y));
+            let (left, top) = (i64::from(w.x), i64::from(w.y));
+            !w.minimized
+                && (left..left + i64::from(w.width)).contains(&x)
+                && (top..top + i64::from(w.height)).contains(&y)
+        }))
+    }
+
+    /// The window holding the input focus, if any
+    pub fn focused() -> Result<Option<WindowInfo>> {
+        Ok(Self::list()?.into_iter().find(|w| w.focused))
+    }
+
+    /// Resolve a selector to a single window
+    pub fn find(selector: &WindowSelector) -> Result<WindowInfo> {
+        if selector.id.is_none() && selector.title.is_none() && selector.class.is_none() {
+            return Err(anyhow!("Window operation needs an id, title or class"));
+        }
+        Self::list()?
+            .into_iter()
+            .find(|w| selector.matches(w))
+            .ok_or_else(|| anyhow!("No window matches {:?}", selector))
+    }
+
+    pub async fn execute(operation: WindowOperation) -> Result<Value> {
+        tokio::task::spawn_blocking(move || Self::run(operation)).await?
+    }
+
+    pub(crate) fn run(operation: WindowOperation) -> Result<Value> {
+        let window = match &operation {
+            WindowOperation::List => {
+                let windows = Self::list()?;
+                return Ok(json!({"count": windows.len(), "windows": windows}));
+            }
+            WindowOperation::Focus { window, .. }
+            | WindowOperation::Minimize { window, .. }
+            | WindowOperation::Maximize { window, .. }
+            | WindowOperation::Restore { window, .. }
+            | WindowOperation::Close { window, .. }
+            | WindowOperation::Move { window, .. }
+            | WindowOperation::Resize { window, .. } => Self::find(window)?,
+        };
+
+        match &operation {
+            WindowOperation::Focus { .. } => platform::focus(window.id)?,
+            WindowOperation::Move { x, y, .. } => platform::configure(window.id, Some((*x, *y)), None)?,
+            WindowOperation::Resize { width, height, .. } => {
+                platform::configure(window.id, None, Some((*width, *height)))?
+            }
+            WindowOperation::Minimize { .. } => platform::minimize(window.id)?,
+            WindowOperation::Maximize { .. } => platform::set_maximized(window.id, true)?,
+            WindowOperation::Restore { .. } => {
+                platform::set_maximized(window.id, false)?;
+                platform::focus(window.id)?;
+            }
+            WindowOperation::Close { .. } => platform::close(window.id)?,
+            WindowOperation::List => unreachable!(),
+        }
+        Ok(json!({"window": window}))
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::WindowInfo;
+    use anyhow::{Context, Result};
+    use x11rb::connection::Connection;
+    use x11rb::protocol::xproto::{
+        AtomEnum, ClientMessageEvent, ConfigureWindowAux, ConnectionExt, EventMask, MapState,
+        Window,
+    };
+    use x11rb::rust_connection::RustConnection;
+    use x11rb::CURRENT_TIME;
+
+    x11rb::atom_manager! {
+        Atoms: AtomsCookie {
+            _NET_CLIENT_LIST,
+            _NET_CLIENT_LIST_STACKING,
+            _NET_ACTIVE_WINDOW,
+            _NET_WM_NAME,
+            _NET_WM_PID,
+            _NET_WM_DESKTOP,
+            _NET_WM_STATE,
+            _NET_WM_STATE_HIDDEN,
+            _NET_WM_STATE_MAXIMIZED_VERT,
+            _NET_WM_STATE_MAXIMIZED_HORZ,
+            _NET_CLOSE_WINDOW,
+            WM_CHANGE_STATE,
+            UTF8_STRING,
+        }
+    }
+
+    /// EWMH source indication: requests come from a pager-like tool
+    const SOURCE_PAGER: u32 = 2;
+    const ICONIC_STATE: u32 = 3;
+    const STATE_REMOVE: u32 = 0;
+    const STATE_ADD: u32 = 1;
+
+    struct Display {
+        conn: RustConnection,
+        root: Window,
+        atoms: Atoms,
+    }
+
+    fn connect() -> Result<Display> {
+        let (conn, screen) =
+            x11rb::connect(None).context("No X11 display available for window operations")?;
+        let root = conn.setup().roots[screen].root;
+        let atoms = Atoms::new(&conn)?.reply()?;
+        Ok(Display { conn, root, atoms })
+    }
+
+    impl Display {
+        fn property32(&self, window: Window, property: u32) -> Vec<u32> {
+            self.conn
+                .get_property(false, window, property, AtomEnum::ANY, 0, 1024)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .and_then(|reply| reply.value32().map(|values| values.collect()))
+                .unwrap_or_default()
+        }
+
+        fn property_bytes(&self, window: Window, property: u32) -> Vec<u8> {
+            self.conn
+                .get_property(false, window, property, AtomEnum::ANY, 0, 4096)
+                .ok()
+                .and_then(|cookie| cookie.reply().ok())
+                .map(|reply| reply.value)
+                .unwrap_or_default()
+        }
+
+        fn title(&self, window: Window) -> String {
+            let name = self.property_bytes(window, self.atoms._NET_WM_NAME);
+            let name = if name.is_empty() {
+                self.property_bytes(window, AtomEnum::WM_NAME.into())
+            } else {
+                name
+            };
+            String::from_utf8_lossy(&name).to_string()
+        }
+
+        /// WM_CLASS holds "instance\0class\0"; the class part is the useful one
+        fn class(&self, window: Window) -> String {
+            let raw = self.property_bytes(window, AtomEnum::WM_CLASS.into());
+            raw.split(|b| *b == 0)
+                .rfind(|part| !part.is_empty())
+                .map(|part| String::from_utf8_lossy(part).to_string())
+                .unwrap_or_default()
+        }
+
+        /// Managed client windows bottom to top, falling back to mapped root
+        /// children without a WM
+        fn clients(&self) -> Result<Vec<Window>> {
+            let clients = self.property32(self.root, self.atoms._NET_CLIENT_LIST_STACKING);
+            if !clients.is_empty() {
+                return Ok(clients);
+            }
+            // Window managers that skip the stacking list keep mapping order here
+            let clients = self.property32(self.root, self.atoms._NET_CLIENT_LIST);
+            if !clients.is_empty() {
+                return Ok(clients);
+            }
+            let tree = self.conn.query_tree(self.root)?.reply()?;
+            Ok(tree
+                .children
+                .into_iter()
+                .filter(|w| {
+                    self.conn
+                        .get_window_attributes(*w)
+                        .ok()
+                        .and_then(|c| c.reply().ok())
+                        .is_some_and(|a| a.map_state == MapState::VIEWABLE && !a.override_redirect)
+                })
+                .collect())
+        }
+
+        fn describe(&self, window: Window, active: Option<Window>) -> Option<WindowInfo> {
+            let geometry = self.conn.get_geometry(window).ok()?.reply().ok()?;
+            let origin = self
+                .conn
+                .translate_coordinates(window, self.root, 0, 0)
+                .ok()?
+                .reply()
+                .ok()?;
+            let state = self.property32(window, self.atoms._NET_WM_STATE);
+
+            Some(WindowInfo {
+                id: window,
+                title: self.title(window),
+                class: self.class(window),
+                pid: self.property32(window, self.atoms._NET_WM_PID).first().copied(),
+                x: origin.dst_x.into(),
+                y: origin.dst_y.into(),
+                width: geometry.width.into(),
+                height: geometry.height.into(),
+                focused: active == Some(window),
+                workspace: self
+                    .property32(window, self.atoms._NET_WM_DESKTOP)
+                    .first()
+                    .copied(),
+                minimized: state.contains(&self.atoms._NET_WM_STATE_HIDDEN),
+                maximized: state.contains(&self.atoms._NET_WM_STATE_MAXIMIZED_VERT)
+                    && state.contains(&self.atoms._NET_WM_STATE_MAXIMIZED_HORZ),
+            })
+        }
+
+        fn send_client_message(&self, window: Window, kind: u32, data: [u32; 5]) -> Result<()> {
+            let event = ClientMessageEvent::new(32, window, kind, data);
+            self.conn.send_event(
+                false,
+                self.root,
+                EventMask::SUBSTRUCTURE_REDIRECT | EventMask::SUBSTRUCTURE_NOTIFY,
+                event,
+            )?;
+            self.conn.flush()?;
+            Ok(())
+        }
+    }
+
+    pub fn list() -> Result<Vec<WindowInfo>> {
+        let display = connect()?;
+        let active = display
+            .property32(display.root, display.atoms._NET_ACTIVE_WINDOW)
+            .first()
+            .copied();
+        Ok(display
+            .clients()?
+            .into_iter()
+            .filter_map(|w| display.describe(w, active))
+            .collect())
+    }
+
+    pub fn focus(window: Window) -> Result<()> {
+        let display = connect()?;
+        display.send_client_message(
+            window,
+            display.atoms._NET_ACTIVE_WINDOW,
+            [SOURCE_PAGER, CURRENT_TIME, 0, 0, 0],
+        )?;
+        // Without an EWMH window manager the message above is ignored
+        display.conn.configure_window(
+            window,
+            &ConfigureWindowAux::new().stack_mode(x11rb::protocol::xproto::StackMode::ABOVE),
+        )?;
+        display.conn.map_window(window)?;
+        display.conn.flush()?;
+        Ok(())
+    }
+
+    pub fn configure(window: Window, position: Option<(i32, i32)>, size: Option<(u32, u32)>) -> Result<()> {
+        let display = connect()?;
+        let mut aux = ConfigureWindowAux::new();
+        if let Some((x, y)) = position {
+            aux = aux.x(x).y(y);
+        }
+        if let Some((width, height)) = size {
+            aux = aux.width(width).height(height);
+        }
+        display.conn.configure_window(window, &aux)?;
+        display.conn.flush()?;
+        Ok(())
+    }
+
+    pub fn minimize(window: Window) -> Result<()> {
+        let display = connect()?;
+        display.send_client_message(window, display.atoms.WM_CHANGE_STATE, [ICONIC_STATE, 0, 0, 0, 0])
+    }
+
+    pub fn set_maximized(window: Window, maximized: bool) -> Result<()> {
+        let display = connect()?;
+        let action = if maximized { STATE_ADD } else { STATE_REMOVE };
+        display.send_client_message(
+            window,
+            display.atoms._NET_WM_STATE,
+            [
+                action,
+                display.atoms._NET_WM_STATE_MAXIMIZED_VERT,
+                display.atoms._NET_WM_STATE_MAXIMIZED_HORZ,
+                SOURCE_PAGER,
+                0,
+            ],
+        )
+    }
+
+    pub fn close(window: Window) -> Result<()> {
+        let display = connect()?;
+        display.send_client_message(
+            window,
+            display.atoms._NET_CLOSE_WINDOW,
+            [CURRENT_TIME, SOURCE_PAGER, 0, 0, 0],
+        )
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::WindowInfo;
+    use anyhow::{bail, Result};
+
+    pub fn list() -> Result<Vec<WindowInfo>> {
+        bail!("Window enumeration is only implemented for X11")
+    }
+
+    pub fn focus(_window: u32) -> Result<()> {
+        bail!("Window operations are only implemented for X11")
+    }
+
+    pub fn configure(_window: u32, _position: Option<(i32, i32)>, _size: Option<(u32, u32)>) -> Result<()> {
+        bail!("Window operations are only implemented for X11")
+    }
+
+    pub fn minimize(_window: u32) -> Result<()> {
+        bail!("Window operations are only implemented for X11")
+    }
+
+    pub fn set_maximized(_window: u32, _maximized: bool) -> Result<()> {
+        bail!("Window operations are only implemented for X11")
+    }
+
+    pub fn close(_window: u32) -> Result<()> {
+        bail!("Window operations are only implemented for X11")
+    }
+}
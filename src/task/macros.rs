@@ -0,0 +1,705 @@
+use crate::action::input_record::InputRecorder;
+use crate::action::{Action, ActionEngine, ActionResult, HistoryQuery};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+use tracing::info;
+
+/// Callers of replayed actions start with this, so recordings skip them
+const REPLAY_CALLER_PREFIX: &str = "macro:";
+/// Background replays kept for status queries; finished ones go first
+const MAX_REPLAYS: usize = 32;
+
+/// One step of a macro
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MacroStep {
+    /// Milliseconds to wait after the previous step before running this one
+    #[serde(default)]
+    pub delay_ms: u64,
+    /// The action, as JSON; strings may contain `{{name}}` placeholders
+    pub action: Value,
+}
+
+/// A named, replayable sequence of actions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Macro {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub created_at: DateTime<Utc>,
+    pub steps: Vec<MacroStep>,
+}
+
+impl Macro {
+    /// Placeholder names used anywhere in the steps
+    pub fn variables(&self) -> Vec<String> {
+        let mut names = BTreeSet::new();
+        for step in &self.steps {
+            collect_placeholders(&step.action, &mut names);
+        }
+        names.into_iter().collect()
+    }
+
+    pub fn duration_ms(&self) -> u64 {
+        self.steps.iter().map(|s| s.delay_ms).sum()
+    }
+
+    pub fn summary(&self) -> Value {
+        json!({
+            "name": self.name,
+            "description": self.description,
+            "created_at": self.created_at,
+            "steps": self.steps.len(),
+            "duration_ms": self.duration_ms(),
+            "variables": self.variables(),
+        })
+    }
+
+    /// Check the steps without placeholders; the others are checked when resolved
+    pub fn validate(&self) -> Result<()> {
+        for (index, step) in self.steps.iter().enumerate() {
+            let mut names = BTreeSet::new();
+            collect_placeholders(&step.action, &mut names);
+            if names.is_empty() {
+                serde_json::from_value::<Action>(step.action.clone())
+                    .with_context(|| format!("Invalid action at step {}", index))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The steps as actions, with every placeholder filled in from `variables`
+    ///
+    /// A string that is exactly one placeholder takes the variable's JSON
+    /// value, so `"x": "{{left}}"` can become a number; placeholders inside
+    /// longer strings are replaced by the value's text.
+    pub fn resolve(&self, variables: &HashMap<String, Value>) -> Result<Vec<Action>> {
+        let missing: Vec<_> = self
+            .variables()
+            .into_iter()
+            .filter(|name| !variables.contains_key(name))
+            .collect();
+        if !missing.is_empty() {
+            bail!(
+                "Macro {} needs values for: {}",
+                self.name,
+                missing.join(", ")
+            );
+        }
+        self.steps
+            .iter()
+            .enumerate()
+            .map(|(index, step)| {
+                serde_json::from_value(substitute(&step.action, variables))
+                    .with_context(|| format!("Invalid action at step {}", index))
+            })
+            .collect()
+    }
+}
+
+/// Where a recording takes its steps from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordSource {
+    /// Actions executed through the engine while recording
+    #[default]
+    Actions,
+    /// Real keyboard and mouse input
+    Input,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub source: RecordSource,
+    /// With `actions`, only record actions from this history caller (e.g. `api:agent`)
+    #[serde(default)]
+    pub caller: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReplayOptions {
+    /// Playback speed: 2.0 halves every delay, 0.5 doubles them
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    #[serde(default)]
+    pub variables: HashMap<String, Value>,
+    #[serde(default = "default_true")]
+    pub stop_on_failure: bool,
+}
+
+impl Default for ReplayOptions {
+    fn default() -> Self {
+        Self {
+            speed: default_speed(),
+            variables: HashMap::new(),
+            stop_on_failure: true,
+        }
+    }
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReplayState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// A replay started with [`MacroSystem::start_replay`]
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayStatus {
+    pub id: String,
+    #[serde(rename = "macro")]
+    pub name: String,
+    pub started_at: DateTime<Utc>,
+    pub state: ReplayState,
+    pub total: usize,
+    pub executed: usize,
+    /// The replay's outcome, as [`MacroSystem::replay`] returns it, once finished
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+}
+
+struct Replay {
+    status: ReplayStatus,
+    cancel: watch::Sender<bool>,
+}
+
+struct Recording {
+    request: RecordRequest,
+    started_at: DateTime<Utc>,
+    input: Option<InputRecorder>,
+}
+
+/// Macro System - Records, stores and replays macros
+///
+/// Macros are JSON files named `<name>.json` in the macro directory. A
+/// recording of `actions` is cut from the action history when it stops, so
+/// it only covers what the history still holds.
+pub struct MacroSystem {
+    dir: PathBuf,
+    engine: Arc<ActionEngine>,
+    recording: Mutex<Option<Recording>>,
+    /// Background replays, oldest first
+    replays: Mutex<Vec<Replay>>,
+}
+
+impl MacroSystem {
+    pub fn new(dir: PathBuf, engine: Arc<ActionEngine>) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create macro directory {}", dir.display()))?;
+        Ok(Self {
+            dir,
+            engine,
+            recording: Mutex::new(None),
+            replays: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Summaries of every stored macro, by name
+    pub fn list(&self) -> Result<Vec<Value>> {
+        let mut macros = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            if let Ok(stored) = self.get(name) {
+                macros.push(stored);
+            }
+        }
+        macros.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(macros.iter().map(Macro::summary).collect())
+    }
+
+    pub fn get(&self, name: &str) -> Result<Macro> {
+        check_name("macro", name)?;
+        let path = self.dir.join(format!("{}.json", name));
+        let bytes = fs::read(&path).map_err(|_| anyhow!("No macro named {}", name))?;
+        serde_json::from_slice(&bytes)
+            .with_context(|| format!("Failed to parse {}", path.display()))
+    }
+
+    pub fn save(&self, stored: &Macro) -> Result<()> {
+        check_name("macro", &stored.name)?;
+        stored.validate()?;
+        let path = self.dir.join(format!("{}.json", stored.name));
+        fs::write(&path, serde_json::to_vec_pretty(stored)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        check_name("macro", name)?;
+        fs::remove_file(self.dir.join(format!("{}.json", name)))
+            .map_err(|_| anyhow!("No macro named {}", name))
+    }
+
+    pub fn start_recording(&self, request: RecordRequest) -> Result<Value> {
+        check_name("macro", &request.name)?;
+        let mut recording = self
+            .recording
+            .lock()
+            .map_err(|_| anyhow!("Recorder lock poisoned"))?;
+        if let Some(active) = recording.as_ref() {
+            bail!("Already recording macro {}", active.request.name);
+        }
+        let input = match request.source {
+            RecordSource::Actions => None,
+            RecordSource::Input => Some(InputRecorder::start()?),
+        };
+        info!("Recording macro {} from {:?}", request.name, request.source);
+        let started_at = Utc::now();
+        let status = json!({"recording": request, "started_at": started_at});
+        *recording = Some(Recording {
+            request,
+            started_at,
+            input,
+        });
+        Ok(status)
+    }
+
+    /// The recording in progress, if any
+    pub fn recording(&self) -> Option<Value> {
+        let recording = self.recording.lock().ok()?;
+        recording
+            .as_ref()
+            .map(|r| json!({"recording": r.request, "started_at": r.started_at}))
+    }
+
+    /// Stop recording and save what was captured
+    pub async fn stop_recording(&self) -> Result<Macro> {
+        let recording = self
+            .recording
+            .lock()
+            .map_err(|_| anyhow!("Recorder lock poisoned"))?
+            .take()
+            .ok_or_else(|| anyhow!("No macro is being recorded"))?;
+
+        let steps = match recording.input {
+            Some(input) => {
+                let inputs = tokio::task::spawn_blocking(move || input.stop()).await??;
+                let mut previous = 0;
+                inputs
+                    .into_iter()
+                    .map(|input| {
+                        let delay_ms = input.offset_ms.saturating_sub(previous);
+                        previous = input.offset_ms;
+                        step(delay_ms, &input.action)
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            }
+            None => self.recorded_actions(&recording)?,
+        };
+        if steps.is_empty() {
+            bail!("Nothing was recorded for macro {}", recording.request.name);
+        }
+
+        let recorded = Macro {
+            name: recording.request.name,
+            description: recording.request.description,
+            created_at: recording.started_at,
+            steps,
+        };
+        self.save(&recorded)?;
+        info!(
+            "Saved macro {} with {} steps",
+            recorded.name,
+            recorded.steps.len()
+        );
+        Ok(recorded)
+    }
+
+    fn recorded_actions(&self, recording: &Recording) -> Result<Vec<MacroStep>> {
+        let query = HistoryQuery {
+            limit: Some(usize::MAX),
+            caller: recording.request.caller.clone(),
+            success: Some(true),
+            since: Some(recording.started_at),
+            ..Default::default()
+        };
+        let (_, mut entries) = self.engine.history().query(&query);
+        entries.reverse();
+
+        let mut previous = None;
+        entries
+            .into_iter()
+            .filter(|entry| !entry.caller.starts_with(REPLAY_CALLER_PREFIX))
+            .map(|entry| {
+                let delay_ms = previous
+                    .map(|p: DateTime<Utc>| (entry.timestamp - p).num_milliseconds().max(0) as u64)
+                    .unwrap_or(0);
+                previous = Some(entry.timestamp);
+                step(delay_ms, &entry.action)
+            })
+            .collect()
+    }
+
+    /// Run already resolved `actions` with the macro's timing
+    pub async fn replay(
+        &self,
+        stored: &Macro,
+        actions: Vec<Action>,
+        options: &ReplayOptions,
+    ) -> Result<Value> {
+        check_speed(options)?;
+        let (_cancel, mut cancelled) = watch::channel(false);
+        self.run_replay(stored, actions, options, &mut cancelled, None).await
+    }
+
+    /// Start replaying in the background
+    ///
+    /// The replay can be followed with [`replay_status`](Self::replay_status)
+    /// and stopped with [`cancel_replay`](Self::cancel_replay); the handle
+    /// resolves to the same outcome [`replay`](Self::replay) returns.
+    pub fn start_replay(
+        self: &Arc<Self>,
+        stored: Macro,
+        actions: Vec<Action>,
+        options: ReplayOptions,
+    ) -> Result<(ReplayStatus, JoinHandle<Result<Value>>)> {
+        check_speed(&options)?;
+        let (cancel, mut cancelled) = watch::channel(false);
+        let status = ReplayStatus {
+            id: uuid::Uuid::new_v4().to_string(),
+            name: stored.name.clone(),
+            started_at: Utc::now(),
+            state: ReplayState::Running,
+            total: actions.len(),
+            executed: 0,
+            result: None,
+        };
+        {
+            let mut replays = self
+                .replays
+                .lock()
+                .map_err(|_| anyhow!("Replay table poisoned"))?;
+            while replays.len() >= MAX_REPLAYS {
+                let Some(index) = replays
+                    .iter()
+                    .position(|r| r.status.state != ReplayState::Running)
+                else {
+                    break;
+                };
+                replays.remove(index);
+            }
+            replays.push(Replay {
+                status: status.clone(),
+                cancel,
+            });
+        }
+
+        let macros = self.clone();
+        let id = status.id.clone();
+        let handle = tokio::spawn(async move {
+            let result = macros
+                .run_replay(&stored, actions, &options, &mut cancelled, Some(&id))
+                .await;
+            macros.update_replay(&id, |status| {
+                let (state, outcome) = match &result {
+                    Ok(outcome) if outcome["cancelled"] == true => {
+                        (ReplayState::Cancelled, outcome.clone())
+                    }
+                    Ok(outcome) if outcome["success"] == true => {
+                        (ReplayState::Completed, outcome.clone())
+                    }
+                    Ok(outcome) => (ReplayState::Failed, outcome.clone()),
+                    Err(e) => (ReplayState::Failed, json!({"error": format!("{:#}", e)})),
+                };
+                status.state = state;
+                status.result = Some(outcome);
+            });
+            result
+        });
+        Ok((status, handle))
+    }
+
+    /// Background replays, newest first
+    pub fn replays(&self) -> Vec<ReplayStatus> {
+        self.replays
+            .lock()
+            .map(|r| r.iter().rev().map(|r| r.status.clone()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn replay_status(&self, id: &str) -> Result<ReplayStatus> {
+        let replays = self.replays.lock().map_err(|_| anyhow!("Replay table poisoned"))?;
+        replays
+            .iter()
+            .find(|r| r.status.id == id)
+            .map(|r| r.status.clone())
+            .ok_or_else(|| anyhow!("No replay {}", id))
+    }
+
+    /// Stop a background replay before its next step
+    ///
+    /// A step already running, such as a `wait_for_*`, finishes first.
+    pub fn cancel_replay(&self, id: &str) -> Result<ReplayStatus> {
+        let replays = self.replays.lock().map_err(|_| anyhow!("Replay table poisoned"))?;
+        let replay = replays
+            .iter()
+            .find(|r| r.status.id == id)
+            .ok_or_else(|| anyhow!("No replay {}", id))?;
+        if replay.status.state != ReplayState::Running {
+            bail!("Replay {} already finished", id);
+        }
+        info!("Cancelling replay {} of macro {}", id, replay.status.name);
+        let _ = replay.cancel.send(true);
+        Ok(replay.status.clone())
+    }
+
+    fn update_replay(&self, id: &str, update: impl FnOnce(&mut ReplayStatus)) {
+        if let Ok(mut replays) = self.replays.lock() {
+            if let Some(replay) = replays.iter_mut().find(|r| r.status.id == id) {
+                update(&mut replay.status);
+            }
+        }
+    }
+
+    async fn run_replay(
+        &self,
+        stored: &Macro,
+        actions: Vec<Action>,
+        options: &ReplayOptions,
+        cancelled: &mut watch::Receiver<bool>,
+        id: Option<&str>,
+    ) -> Result<Value> {
+        info!("Replaying macro {} at {}x", stored.name, options.speed);
+        let caller = format!("{}{}", REPLAY_CALLER_PREFIX, stored.name);
+        let total = actions.len();
+        let mut results: Vec<ActionResult> = Vec::with_capacity(total);
+        let mut stopped_at = None;
+        let mut cancelled_at = None;
+
+        for (index, (step, action)) in stored.steps.iter().zip(actions).enumerate() {
+            if *cancelled.borrow() {
+                cancelled_at = Some(index);
+                break;
+            }
+            if step.delay_ms > 0 {
+                let delay = step.delay_ms as f64 / 1000.0 / options.speed;
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs_f64(delay)) => {}
+                    Ok(_) = cancelled.wait_for(|cancel| *cancel) => {
+                        cancelled_at = Some(index);
+                        break;
+                    }
+                }
+            }
+            let result = self.engine.execute_as(action, &caller).await?;
+            let failed = !result.success;
+            results.push(result);
+            if let Some(id) = id {
+                self.update_replay(id, |status| status.executed = index + 1);
+            }
+            if failed && options.stop_on_failure {
+                stopped_at = Some(index);
+                break;
+            }
+        }
+
+        let success = cancelled_at.is_none() && results.iter().all(|r| r.success);
+        Ok(json!({
+            "macro": stored.name,
+            "success": success,
+            "total": total,
+            "executed": results.len(),
+            "stopped_at": stopped_at,
+            "cancelled": cancelled_at.is_some(),
+            "results": results,
+        }))
+    }
+}
+
+fn check_speed(options: &ReplayOptions) -> Result<()> {
+    if !(options.speed.is_finite() && options.speed > 0.0) {
+        bail!("speed must be a positive number");
+    }
+    Ok(())
+}
+
+fn step(delay_ms: u64, action: &Action) -> Result<MacroStep> {
+    Ok(MacroStep {
+        delay_ms,
+        action: serde_json::to_value(action)?,
+    })
+}
+
+/// Macro and trigger names double as file names
+pub(crate) fn check_name(kind: &str, name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !valid {
+        bail!(
+            "Invalid {} name {:?}: use letters, digits, '-' and '_'",
+            kind,
+            name
+        );
+    }
+    Ok(())
+}
+
+/// Byte ranges and names of the `{{name}}` placeholders in `text`; names may
+/// be dotted, e.g. `{{window.title}}`
+fn placeholders(text: &str) -> Vec<(usize, usize, &str)> {
+    let mut found = Vec::new();
+    let mut rest = 0;
+    while let Some(open) = text[rest..].find("{{").map(|i| i + rest) {
+        let Some(close) = text[open + 2..].find("}}").map(|i| i + open + 2) else {
+            break;
+        };
+        let name = text[open + 2..close].trim();
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+            found.push((open, close + 2, name));
+        }
+        rest = close + 2;
+    }
+    found
+}
+
+pub(crate) fn collect_placeholders(value: &Value, names: &mut BTreeSet<String>) {
+    match value {
+        Value::String(text) => names.extend(
+            placeholders(text)
+                .into_iter()
+                .map(|(_, _, name)| name.to_string()),
+        ),
+        Value::Array(items) => items.iter().for_each(|v| collect_placeholders(v, names)),
+        Value::Object(map) => map.values().for_each(|v| collect_placeholders(v, names)),
+        _ => {}
+    }
+}
+
+pub(crate) fn substitute(value: &Value, variables: &HashMap<String, Value>) -> Value {
+    match value {
+        Value::String(text) => {
+            let found = placeholders(text);
+            if let [(0, end, name)] = found[..] {
+                if end == text.len() {
+                    return variables
+                        .get(name)
+                        .cloned()
+                        .unwrap_or_else(|| value.clone());
+                }
+            }
+            let mut result = String::with_capacity(text.len());
+            let mut copied = 0;
+            for (start, end, name) in found {
+                result.push_str(&text[copied..start]);
+                match variables.get(name) {
+                    Some(Value::String(s)) => result.push_str(s),
+                    Some(other) => result.push_str(&other.to_string()),
+                    None => result.push_str(&text[start..end]),
+                }
+                copied = end;
+            }
+            result.push_str(&text[copied..]);
+            Value::String(result)
+        }
+        Value::Array(items) => {
+            Value::Array(items.iter().map(|v| substitute(v, variables)).collect())
+        }
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, v)| (key.clone(), substitute(v, variables)))
+                .collect(),
+        ),
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(pairs: &[(&str, Value)]) -> HashMap<String, Value> {
+        pairs.iter().map(|(name, value)| (name.to_string(), value.clone())).collect()
+    }
+
+    fn click_macro() -> Macro {
+        Macro {
+            name: "click-at".to_string(),
+            description: String::new(),
+            created_at: Utc::now(),
+            steps: vec![MacroStep {
+                delay_ms: 0,
+                action: json!({
+                    "action_type": "click",
+                    "params": { "x": "{{left}}", "y": "{{ top }}" },
+                }),
+            }],
+        }
+    }
+
+    #[test]
+    fn placeholders_need_valid_names() {
+        let names = |text| placeholders(text).into_iter().map(|(_, _, n)| n).collect::<Vec<_>>();
+        assert_eq!(names("{{a}} and {{ window.title }}"), ["a", "window.title"]);
+        assert!(names("{{}} {{a b}} {{a-b}} {{unclosed").is_empty());
+        assert_eq!(placeholders("x {{a}}"), [(2, 7, "a")]);
+    }
+
+    #[test]
+    fn whole_placeholder_keeps_its_json_type() {
+        let values = variables(&[("n", json!(42)), ("s", json!("hi"))]);
+        assert_eq!(substitute(&json!("{{n}}"), &values), json!(42));
+        assert_eq!(substitute(&json!(" {{n}}"), &values), json!(" 42"));
+        assert_eq!(substitute(&json!("{{s}}, {{n}}!"), &values), json!("hi, 42!"));
+        // Unknown placeholders are left as they are
+        assert_eq!(substitute(&json!("{{missing}} {{s}}"), &values), json!("{{missing}} hi"));
+        assert_eq!(substitute(&json!({"a": ["{{n}}"]}), &values), json!({"a": [42]}));
+    }
+
+    #[test]
+    fn resolve_reports_missing_and_ignores_unused_variables() {
+        let stored = click_macro();
+        assert_eq!(stored.variables(), ["left", "top"]);
+        let error = stored.resolve(&variables(&[("left", json!(10))])).unwrap_err();
+        assert!(error.to_string().contains("top"), "{}", error);
+
+        let values = variables(&[("left", json!(10)), ("top", json!(20)), ("extra", json!(1))]);
+        let actions = stored.resolve(&values).unwrap();
+        let Action::Click(params) = &actions[0] else {
+            panic!("expected a click, got {:?}", actions[0]);
+        };
+        assert_eq!((params.x, params.y), (10, 20));
+
+        // A value of the wrong type fails when the step is parsed
+        let text = variables(&[("left", json!("ten")), ("top", json!(20))]);
+        assert!(stored.resolve(&text).is_err());
+    }
+
+    #[test]
+    fn names_are_safe_file_names() {
+        assert!(check_name("macro", "rename-files_2").is_ok());
+        for name in ["", "../etc", "a b", "a.json", "ä", &"x".repeat(65)] {
+            assert!(check_name("macro", name).is_err(), "{:?} accepted", name);
+        }
+    }
+}
@@ -1,4 +1,10 @@
+pub mod macros;
 pub mod planner;
+pub mod triggers;
 
+pub use macros::{
+    Macro, MacroStep, MacroSystem, RecordRequest, RecordSource, ReplayOptions, ReplayState,
+    ReplayStatus,
+};
 pub use planner::TaskPlanner;
-
+pub use triggers::{CompareOp, FieldCondition, Trigger, TriggerFiring, TriggerSpec, TriggerSystem};
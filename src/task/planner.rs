@@ -1,4 +1,5 @@
 use crate::action::{ActionEngine, Action};
+use crate::event::{Event, EventSystem, TaskState};
 use crate::state::StateManager;
 use crate::vision::VisionSystem;
 use anyhow::Result;
@@ -9,6 +10,7 @@ pub struct TaskPlanner {
     action_engine: Arc<ActionEngine>,
     vision: Arc<VisionSystem>,
     state_manager: Arc<StateManager>,
+    events: Arc<EventSystem>,
 }
 
 impl TaskPlanner {
@@ -16,11 +18,13 @@ impl TaskPlanner {
         action_engine: Arc<ActionEngine>,
         vision: Arc<VisionSystem>,
         state_manager: Arc<StateManager>,
+        events: Arc<EventSystem>,
     ) -> Self {
         Self {
             action_engine,
             vision,
             state_manager,
+            events,
         }
     }
 
@@ -30,13 +34,74 @@ impl TaskPlanner {
         Ok(vec![])
     }
 
-    pub async fn execute_plan(&self, actions: Vec<Action>) -> Result<Value> {
+    /// Run a plan on behalf of `caller`, or with `dry_run` only predict its effects
+    pub async fn execute_plan(
+        &self,
+        actions: Vec<Action>,
+        caller: &str,
+        dry_run: bool,
+    ) -> Result<Value> {
+        if dry_run {
+            let results = self.action_engine.dry_run(&actions).await;
+            let would_succeed = results.iter().all(|r| r.success);
+            return Ok(serde_json::json!({
+                "dry_run": true,
+                "would_succeed": would_succeed,
+                "results": results,
+            }));
+        }
+
+        let task_id = uuid::Uuid::new_v4().to_string();
+        let total = actions.len();
+        let publish = |state: TaskState, completed: usize, error: Option<String>| {
+            self.events.publish(Event::TaskStateChanged {
+                timestamp: chrono::Utc::now(),
+                task_id: task_id.clone(),
+                caller: caller.to_string(),
+                state,
+                completed_steps: completed,
+                total_steps: total,
+                error,
+            });
+        };
+        publish(TaskState::Started, 0, None);
+
         let mut results = vec![];
         for action in actions {
-            let result = self.action_engine.execute(action).await?;
+            let assertion = action.is_assertion();
+            let result = match self.action_engine.execute_as(action, caller).await {
+                Ok(result) => result,
+                Err(e) => {
+                    publish(TaskState::Failed, results.len(), Some(format!("{:#}", e)));
+                    return Err(e);
+                }
+            };
+            let failed = assertion && !result.success;
+            let error = result.error.clone();
             results.push(result);
+            // A failed assertion means the screen isn't in the state the rest
+            // of the plan was written for
+            if failed {
+                publish(TaskState::Aborted, results.len(), error);
+                return Ok(serde_json::json!({
+                    "task_id": task_id,
+                    "results": results,
+                    "aborted": true,
+                    "failed_step": results.len() - 1,
+                    "skipped": total - results.len(),
+                }));
+            }
+            publish(TaskState::Progress, results.len(), None);
         }
-        Ok(serde_json::json!({"results": results}))
+
+        let first_error = results.iter().find_map(|r| r.error.clone());
+        let state = if first_error.is_some() {
+            TaskState::Failed
+        } else {
+            TaskState::Completed
+        };
+        publish(state, results.len(), first_error);
+        Ok(serde_json::json!({"task_id": task_id, "results": results}))
     }
 }
 
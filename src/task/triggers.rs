@@ -0,0 +1,691 @@
+use crate::action::Action;
+use crate::core::paths::expand_home;
+use crate::event::{Event, EventFilter, EventSystem};
+use crate::task::macros::{check_name, collect_placeholders, substitute};
+use crate::task::TaskPlanner;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
+
+/// How a condition compares an event field with its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    /// Substring of a string field, or element of an array field
+    Contains,
+    /// Glob pattern over a string field, e.g. `*.pdf`
+    Matches,
+    /// The field is present and not null
+    Exists,
+    /// The field is absent or null
+    Missing,
+}
+
+/// A test on one field of the event, e.g. `{"field": "exit_code", "op": "ne", "value": 0}`
+///
+/// A field that is absent or null fails every op except `missing`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FieldCondition {
+    /// Dotted path into the event JSON, e.g. `name` or `window.class`
+    pub field: String,
+    pub op: CompareOp,
+    /// Unused by `exists` and `missing`
+    #[serde(default)]
+    pub value: Value,
+}
+
+impl FieldCondition {
+    fn validate(&self) -> Result<()> {
+        if self.field.is_empty() {
+            bail!("Condition field must not be empty");
+        }
+        match self.op {
+            CompareOp::Matches => {
+                let pattern = self
+                    .value
+                    .as_str()
+                    .ok_or_else(|| anyhow!("matches on {} needs a string pattern", self.field))?;
+                glob::Pattern::new(pattern)
+                    .with_context(|| format!("Invalid pattern {:?}", pattern))?;
+            }
+            CompareOp::Gt | CompareOp::Gte | CompareOp::Lt | CompareOp::Lte
+                if !(self.value.is_number() || self.value.is_string()) =>
+            {
+                bail!("{:?} on {} needs a number or string", self.op, self.field);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn holds(&self, event: &Value) -> bool {
+        let field = lookup(event, &self.field).filter(|v| !v.is_null());
+        let Some(field) = field else {
+            return self.op == CompareOp::Missing;
+        };
+        match self.op {
+            CompareOp::Eq => json_eq(field, &self.value),
+            CompareOp::Ne => !json_eq(field, &self.value),
+            CompareOp::Gt => compare(field, &self.value).is_some_and(|o| o.is_gt()),
+            CompareOp::Gte => compare(field, &self.value).is_some_and(|o| o.is_ge()),
+            CompareOp::Lt => compare(field, &self.value).is_some_and(|o| o.is_lt()),
+            CompareOp::Lte => compare(field, &self.value).is_some_and(|o| o.is_le()),
+            CompareOp::Contains => match (field, &self.value) {
+                (Value::String(text), Value::String(part)) => text.contains(part.as_str()),
+                (Value::Array(items), value) => items.iter().any(|item| json_eq(item, value)),
+                _ => false,
+            },
+            CompareOp::Matches => match (field.as_str(), self.value.as_str()) {
+                (Some(text), Some(pattern)) => {
+                    glob::Pattern::new(pattern).is_ok_and(|p| p.matches(text))
+                }
+                _ => false,
+            },
+            CompareOp::Exists => true,
+            CompareOp::Missing => false,
+        }
+    }
+}
+
+/// What a trigger reacts to and what it runs, as sent to `PUT /api/triggers/:name`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerSpec {
+    #[serde(default)]
+    pub description: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Which events are considered, e.g. `{"types": ["file_changed"], "path": "~/Downloads"}`
+    pub event: EventFilter,
+    /// Further tests on the event's fields, all of which must hold
+    #[serde(default)]
+    pub conditions: Vec<FieldCondition>,
+    /// Minimum time between two firings, 5 seconds by default
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+    /// The plan to run; strings may contain `{{field}}` placeholders filled
+    /// from the event, e.g. `{{path}}` or `{{window.title}}`
+    pub actions: Vec<Value>,
+}
+
+/// The outcome of a trigger's most recent firing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TriggerFiring {
+    pub fired_at: DateTime<Utc>,
+    /// Type of the event that fired the trigger; the event itself isn't kept
+    /// since it may carry window titles, command lines or paths
+    #[serde(default)]
+    pub event_type: String,
+    /// `None` while the plan is still running
+    #[serde(default)]
+    pub success: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// An event-condition-action rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Trigger {
+    pub name: String,
+    #[serde(flatten)]
+    pub spec: TriggerSpec,
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub fire_count: u64,
+    #[serde(default)]
+    pub last_firing: Option<TriggerFiring>,
+}
+
+impl Trigger {
+    pub fn summary(&self) -> Value {
+        json!({
+            "name": self.name,
+            "description": self.spec.description,
+            "enabled": self.spec.enabled,
+            "event": self.spec.event,
+            "conditions": self.spec.conditions.len(),
+            "cooldown_secs": self.spec.cooldown_secs,
+            "steps": self.spec.actions.len(),
+            "fire_count": self.fire_count,
+            "last_firing": self.last_firing,
+        })
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        check_name("trigger", &self.name)?;
+        self.spec.event.validate()?;
+        for condition in &self.spec.conditions {
+            condition.validate()?;
+        }
+        if self.spec.actions.is_empty() {
+            bail!("Trigger {} has no actions", self.name);
+        }
+        // Steps with placeholders can only be checked once an event fills them in
+        for (index, action) in self.spec.actions.iter().enumerate() {
+            let mut names = BTreeSet::new();
+            collect_placeholders(action, &mut names);
+            if names.is_empty() {
+                serde_json::from_value::<Action>(action.clone())
+                    .with_context(|| format!("Invalid action at step {}", index))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// History caller of the trigger's plans
+    fn caller(&self) -> String {
+        format!("trigger:{}", self.name)
+    }
+
+    /// Whether `event` fires the trigger at `now`, ignoring whether it is enabled
+    fn matches(&self, event: &Event, fields: &Value, now: DateTime<Utc>) -> bool {
+        // The trigger's own plan must not set it off again
+        if event.caller() == Some(self.caller().as_str()) {
+            return false;
+        }
+        if !self.spec.event.matches(event) {
+            return false;
+        }
+        if !self.spec.conditions.iter().all(|c| c.holds(fields)) {
+            return false;
+        }
+        self.last_firing.as_ref().is_none_or(|last| {
+            let elapsed = (now - last.fired_at).num_seconds();
+            u64::try_from(elapsed).is_ok_and(|secs| secs >= self.spec.cooldown_secs)
+        })
+    }
+
+    /// The plan with every placeholder filled in from `event`
+    fn resolve(&self, event: &Value) -> Result<Vec<Action>> {
+        let mut variables = HashMap::new();
+        flatten(event, String::new(), &mut variables);
+        let mut names = BTreeSet::new();
+        for action in &self.spec.actions {
+            collect_placeholders(action, &mut names);
+        }
+        let missing: Vec<_> = names
+            .into_iter()
+            .filter(|name| !variables.contains_key(name))
+            .collect();
+        if !missing.is_empty() {
+            bail!("The event has no field(s): {}", missing.join(", "));
+        }
+        self.spec
+            .actions
+            .iter()
+            .enumerate()
+            .map(|(index, action)| {
+                serde_json::from_value(substitute(action, &variables))
+                    .with_context(|| format!("Invalid action at step {}", index))
+            })
+            .collect()
+    }
+}
+
+/// Added to the longest watch debounce for a trigger's settle window
+const SETTLE_MARGIN: chrono::Duration = chrono::Duration::seconds(2);
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_cooldown_secs() -> u64 {
+    5
+}
+
+/// Triggers that ignore events because their plan is running or has only
+/// just finished
+#[derive(Debug, Default)]
+struct Quiet {
+    /// `None` while the plan runs, then the end of its settle window
+    until: HashMap<String, Option<DateTime<Utc>>>,
+}
+
+impl Quiet {
+    fn start(&mut self, name: &str) {
+        self.until.insert(name.to_string(), None);
+    }
+
+    fn finish(&mut self, name: &str, until: DateTime<Utc>) {
+        self.until.insert(name.to_string(), Some(until));
+    }
+
+    /// Whether `name` ignores events at `now`
+    fn holds(&mut self, name: &str, now: DateTime<Utc>) -> bool {
+        match self.until.get(name) {
+            Some(None) => true,
+            Some(Some(until)) if now < *until => true,
+            Some(Some(_)) => {
+                self.until.remove(name);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+/// Trigger System - Runs plans in response to events
+///
+/// Triggers are JSON files named `<name>.json` in the trigger directory and
+/// are cached in memory. Plans run as caller `trigger:<name>`, and events
+/// carrying that caller never fire the same trigger. Most events carry no
+/// caller, so a trigger also ignores every event while its plan runs and for
+/// a settle window after it ends: the longest watch debounce, plus
+/// [`SETTLE_MARGIN`] for the once-a-second state polling. Changes the plan
+/// caused therefore can't fire it again.
+pub struct TriggerSystem {
+    dir: PathBuf,
+    planner: Arc<TaskPlanner>,
+    events: Arc<EventSystem>,
+    triggers: Mutex<BTreeMap<String, Trigger>>,
+    quiet: Mutex<Quiet>,
+}
+
+impl TriggerSystem {
+    pub fn new(dir: PathBuf, planner: Arc<TaskPlanner>, events: Arc<EventSystem>) -> Result<Self> {
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create trigger directory {}", dir.display()))?;
+        let mut triggers = BTreeMap::new();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let loaded = fs::read(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|bytes| Ok(serde_json::from_slice::<Trigger>(&bytes)?))
+                .and_then(|trigger| trigger.validate().map(|_| trigger));
+            match loaded {
+                Ok(trigger) => {
+                    triggers.insert(trigger.name.clone(), trigger);
+                }
+                Err(e) => warn!("Skipping trigger {}: {:#}", path.display(), e),
+            }
+        }
+        info!("Loaded {} trigger(s)", triggers.len());
+        Ok(Self {
+            dir,
+            planner,
+            events,
+            triggers: Mutex::new(triggers),
+            quiet: Mutex::new(Quiet::default()),
+        })
+    }
+
+    /// Start dispatching events to the triggers
+    pub fn start(self: &Arc<Self>) {
+        let triggers = self.clone();
+        let mut events = self.events.subscribe(EventFilter::default());
+        tokio::spawn(async move {
+            while let Some(event) = events.recv().await {
+                triggers.dispatch(&event);
+            }
+        });
+    }
+
+    /// Summaries of every trigger, by name
+    pub fn list(&self) -> Vec<Value> {
+        self.triggers
+            .lock()
+            .map(|triggers| triggers.values().map(Trigger::summary).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get(&self, name: &str) -> Result<Trigger> {
+        let triggers = self.triggers.lock().map_err(|_| anyhow!("Trigger table poisoned"))?;
+        triggers
+            .get(name)
+            .cloned()
+            .ok_or_else(|| anyhow!("No trigger named {}", name))
+    }
+
+    /// Create or replace a trigger; replacing keeps its firing history
+    pub fn save(&self, name: &str, mut spec: TriggerSpec) -> Result<Trigger> {
+        if let Some(path) = &spec.event.path {
+            spec.event.path = Some(expand_home(&path.to_string_lossy()));
+        }
+        let mut triggers = self.triggers.lock().map_err(|_| anyhow!("Trigger table poisoned"))?;
+        let previous = triggers.get(name);
+        let trigger = Trigger {
+            name: name.to_string(),
+            spec,
+            created_at: previous.map(|t| t.created_at).unwrap_or_else(Utc::now),
+            fire_count: previous.map(|t| t.fire_count).unwrap_or(0),
+            last_firing: previous.and_then(|t| t.last_firing.clone()),
+        };
+        trigger.validate()?;
+        self.write(&trigger)?;
+        triggers.insert(trigger.name.clone(), trigger.clone());
+        Ok(trigger)
+    }
+
+    pub fn delete(&self, name: &str) -> Result<()> {
+        check_name("trigger", name)?;
+        let mut triggers = self.triggers.lock().map_err(|_| anyhow!("Trigger table poisoned"))?;
+        if triggers.remove(name).is_none() {
+            bail!("No trigger named {}", name);
+        }
+        fs::remove_file(self.dir.join(format!("{}.json", name)))
+            .with_context(|| format!("Failed to delete trigger {}", name))
+    }
+
+    pub fn set_enabled(&self, name: &str, enabled: bool) -> Result<Trigger> {
+        let mut triggers = self.triggers.lock().map_err(|_| anyhow!("Trigger table poisoned"))?;
+        let trigger = triggers
+            .get_mut(name)
+            .ok_or_else(|| anyhow!("No trigger named {}", name))?;
+        trigger.spec.enabled = enabled;
+        self.write(trigger)?;
+        info!("Trigger {} {}", name, if enabled { "enabled" } else { "disabled" });
+        Ok(trigger.clone())
+    }
+
+    fn write(&self, trigger: &Trigger) -> Result<()> {
+        let path = self.dir.join(format!("{}.json", trigger.name));
+        fs::write(&path, serde_json::to_vec_pretty(trigger)?)
+            .with_context(|| format!("Failed to write {}", path.display()))
+    }
+
+    /// Fire every enabled trigger that `event` matches
+    fn dispatch(self: &Arc<Self>, event: &Event) {
+        let Ok(fields) = serde_json::to_value(event) else {
+            return;
+        };
+        let now = Utc::now();
+        let mut fired = Vec::new();
+        {
+            let (Ok(mut triggers), Ok(mut quiet)) = (self.triggers.lock(), self.quiet.lock())
+            else {
+                return;
+            };
+            for trigger in triggers.values_mut() {
+                if !trigger.spec.enabled
+                    || quiet.holds(&trigger.name, now)
+                    || !trigger.matches(event, &fields, now)
+                {
+                    continue;
+                }
+                // Recorded up front so the cooldown covers events arriving while the plan runs
+                trigger.fire_count += 1;
+                trigger.last_firing = Some(TriggerFiring {
+                    fired_at: now,
+                    event_type: event.kind().to_string(),
+                    success: None,
+                    task_id: None,
+                    error: None,
+                });
+                quiet.start(&trigger.name);
+                fired.push(trigger.clone());
+            }
+        }
+        for trigger in fired {
+            info!("Trigger {} fired on {}", trigger.name, event.kind());
+            let triggers = self.clone();
+            let fields = fields.clone();
+            tokio::spawn(async move { triggers.fire(trigger, fields).await });
+        }
+    }
+
+    async fn fire(&self, trigger: Trigger, event: Value) {
+        let outcome = match trigger.resolve(&event) {
+            Ok(actions) => self.planner.execute_plan(actions, &trigger.caller(), false).await,
+            Err(e) => Err(e),
+        };
+        let (success, task_id, error) = match outcome {
+            Ok(result) => {
+                let task_id = result["task_id"].as_str().map(str::to_string);
+                let error = result["results"]
+                    .as_array()
+                    .and_then(|results| results.iter().find_map(|r| r["error"].as_str()))
+                    .map(str::to_string);
+                (error.is_none(), task_id, error)
+            }
+            Err(e) => (false, None, Some(format!("{:#}", e))),
+        };
+        if let Some(error) = &error {
+            warn!("Trigger {} failed: {}", trigger.name, error);
+        }
+
+        // Events the plan caused may still be on their way, e.g. in a watch's debounce
+        let longest_debounce = self
+            .events
+            .watches()
+            .iter()
+            .map(|watch| watch.spec.debounce_ms)
+            .max()
+            .unwrap_or(0);
+        // Capped well within chrono's range; no real debounce comes close
+        let debounce = i64::from(u32::try_from(longest_debounce).unwrap_or(u32::MAX));
+        let settle = chrono::Duration::milliseconds(debounce) + SETTLE_MARGIN;
+        if let Ok(mut quiet) = self.quiet.lock() {
+            quiet.finish(&trigger.name, Utc::now() + settle);
+        }
+        let Ok(mut triggers) = self.triggers.lock() else {
+            return;
+        };
+        // The trigger may have been deleted or replaced while its plan ran
+        let Some(current) = triggers.get_mut(&trigger.name) else {
+            return;
+        };
+        if let Some(last) = current.last_firing.as_mut() {
+            last.success = Some(success);
+            last.task_id = task_id;
+            last.error = error;
+        }
+        if let Err(e) = self.write(current) {
+            warn!("Failed to save trigger {}: {:#}", trigger.name, e);
+        }
+    }
+}
+
+/// The value at a dotted path such as `window.class`; numeric segments index arrays
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(value, |value, key| match value {
+        Value::Object(map) => map.get(key),
+        Value::Array(items) => key.parse::<usize>().ok().and_then(|i| items.get(i)),
+        _ => None,
+    })
+}
+
+/// Every field of `value` under its dotted path, for placeholder substitution
+fn flatten(value: &Value, prefix: String, out: &mut HashMap<String, Value>) {
+    if let Value::Object(map) = value {
+        for (key, child) in map {
+            let path = if prefix.is_empty() {
+                key.clone()
+            } else {
+                format!("{}.{}", prefix, key)
+            };
+            flatten(child, path, out);
+        }
+    }
+    if !prefix.is_empty() {
+        out.insert(prefix, value.clone());
+    }
+}
+
+/// Equality that treats `3` and `3.0` as the same number
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(x), Some(y)) => x == y,
+        _ => a == b,
+    }
+}
+
+/// Numbers compare numerically and strings lexically (so RFC 3339 timestamps work)
+fn compare(a: &Value, b: &Value) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (Value::Number(_), Value::Number(_)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::FileChange;
+
+    fn trigger(spec: Value) -> Trigger {
+        Trigger {
+            name: "sort-downloads".to_string(),
+            spec: serde_json::from_value(spec).expect("valid trigger spec"),
+            created_at: Utc::now(),
+            fire_count: 0,
+            last_firing: None,
+        }
+    }
+
+    fn file_changed(path: &str) -> Event {
+        Event::FileChanged {
+            timestamp: Utc::now(),
+            watch: "w".to_string(),
+            path: PathBuf::from(path),
+            change: FileChange::Created,
+            from: None,
+        }
+    }
+
+    fn fires(trigger: &Trigger, event: &Event, now: DateTime<Utc>) -> bool {
+        trigger.matches(event, &serde_json::to_value(event).unwrap(), now)
+    }
+
+    #[test]
+    fn plans_do_not_fire_their_own_trigger_again() {
+        // The plan writes into the directory the trigger watches
+        let trigger = trigger(json!({
+            "event": {"types": ["file_changed"]},
+            "cooldown_secs": 0,
+            "actions": [],
+        }));
+        let event = file_changed("/downloads/report.pdf");
+        let mut quiet = Quiet::default();
+        let start = Utc::now();
+        quiet.start(&trigger.name);
+        assert!(quiet.holds(&trigger.name, start));
+
+        // The plan's own change arrives after it ended, once debounced
+        let end = start + chrono::Duration::seconds(1);
+        quiet.finish(&trigger.name, end + chrono::Duration::milliseconds(500) + SETTLE_MARGIN);
+        let debounced = end + chrono::Duration::milliseconds(500);
+        assert!(fires(&trigger, &event, debounced));
+        assert!(quiet.holds(&trigger.name, debounced));
+
+        // Later changes fire it as usual
+        let later = end + chrono::Duration::seconds(10);
+        assert!(!quiet.holds(&trigger.name, later));
+        assert!(fires(&trigger, &event, later));
+    }
+
+    #[test]
+    fn events_from_the_trigger_itself_are_ignored() {
+        let trigger = trigger(json!({"event": {"types": ["action_executed"]}, "actions": []}));
+        let executed = |caller: &str| Event::ActionExecuted {
+            timestamp: Utc::now(),
+            action_id: "a".to_string(),
+            action_type: "click".to_string(),
+            caller: caller.to_string(),
+            success: true,
+            error: None,
+            duration_ms: 1,
+        };
+        assert!(!fires(&trigger, &executed("trigger:sort-downloads"), Utc::now()));
+        assert!(fires(&trigger, &executed("api"), Utc::now()));
+    }
+
+    fn condition(field: &str, op: &str, value: Value) -> FieldCondition {
+        serde_json::from_value(json!({"field": field, "op": op, "value": value})).unwrap()
+    }
+
+    #[test]
+    fn conditions_compare_numbers_strings_and_paths() {
+        let event = json!({
+            "exit_code": 3,
+            "name": "firefox",
+            "window": {"class": "Navigator", "title": null},
+            "cmdline": ["firefox", "--private"],
+            "timestamp": "2026-01-02T03:04:05Z",
+        });
+        let holds = |field, op, value| condition(field, op, value).holds(&event);
+        assert!(holds("exit_code", "eq", json!(3.0)));
+        assert!(holds("exit_code", "ne", json!(0)));
+        assert!(holds("exit_code", "gt", json!(2)) && holds("exit_code", "lte", json!(3)));
+        assert!(!holds("exit_code", "lt", json!(3)));
+        // Numbers and strings don't compare with each other
+        assert!(!holds("exit_code", "gt", json!("2")));
+        assert!(holds("timestamp", "gte", json!("2026-01-01T00:00:00Z")));
+        assert!(holds("window.class", "eq", json!("Navigator")));
+        assert!(holds("cmdline.1", "eq", json!("--private")));
+        assert!(holds("cmdline", "contains", json!("--private")));
+        assert!(holds("name", "contains", json!("fox")));
+        assert!(holds("name", "matches", json!("fire*")));
+        assert!(!holds("name", "matches", json!("*.pdf")));
+    }
+
+    #[test]
+    fn missing_and_null_fields_only_satisfy_missing() {
+        let event = json!({"window": {"title": null}});
+        for field in ["window.title", "window.class", "pid", "window.title.deeper"] {
+            assert!(condition(field, "missing", Value::Null).holds(&event), "{}", field);
+            assert!(!condition(field, "exists", Value::Null).holds(&event), "{}", field);
+            assert!(!condition(field, "ne", json!(1)).holds(&event), "{}", field);
+        }
+        assert!(condition("window", "exists", Value::Null).holds(&event));
+    }
+
+    #[test]
+    fn placeholders_see_dotted_fields() {
+        let mut fields = HashMap::new();
+        let event = json!({"window": {"class": "x", "pid": 4}, "path": "/a"});
+        flatten(&event, String::new(), &mut fields);
+        assert_eq!(fields["window.class"], json!("x"));
+        assert_eq!(fields["window.pid"], json!(4));
+        assert_eq!(fields["window"], json!({"class": "x", "pid": 4}));
+        assert_eq!(fields["path"], json!("/a"));
+        assert_eq!(lookup(&json!({"a": [{"b": 1}]}), "a.0.b"), Some(&json!(1)));
+        assert_eq!(lookup(&json!({"a": [1]}), "a.x"), None);
+    }
+
+    #[test]
+    fn cooldown_spaces_out_firings() {
+        let mut trigger = trigger(json!({"event": {"types": ["file_changed"]}, "actions": []}));
+        assert_eq!(trigger.spec.cooldown_secs, 5);
+        let event = file_changed("/downloads/a.pdf");
+        let fired_at = Utc::now();
+        trigger.last_firing = Some(TriggerFiring {
+            fired_at,
+            event_type: "file_changed".to_string(),
+            success: Some(true),
+            task_id: None,
+            error: None,
+        });
+        assert!(!fires(&trigger, &event, fired_at + chrono::Duration::seconds(4)));
+        assert!(fires(&trigger, &event, fired_at + chrono::Duration::seconds(5)));
+        // A clock that went backwards doesn't count as the cooldown passing
+        assert!(!fires(&trigger, &event, fired_at - chrono::Duration::seconds(60)));
+
+        let conditional = Trigger {
+            spec: TriggerSpec {
+                conditions: vec![condition("path", "matches", json!("*.pdf"))],
+                ..trigger.spec.clone()
+            },
+            last_firing: None,
+            ..trigger
+        };
+        assert!(fires(&conditional, &event, fired_at));
+        assert!(!fires(&conditional, &file_changed("/downloads/a.tmp"), fired_at));
+    }
+}
@@ -0,0 +1,759 @@
+use crate::action::schema::A11yQuery;
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+/// Nodes one walk of the tree visits before it stops
+const MAX_VISITED: usize = 5000;
+/// How deep a search descends below each application
+const MAX_SEARCH_DEPTH: usize = 32;
+/// Text content reported with a node, in characters
+const MAX_TEXT_CHARS: i32 = 200;
+/// Interactive nodes marked on an annotated screenshot
+const MAX_INTERACTIVE: usize = 300;
+
+/// Where a node is on screen
+#[derive(Debug, Clone, Serialize)]
+pub struct Bounds {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// One object of the accessibility tree
+#[derive(Debug, Clone, Serialize)]
+pub struct A11yNode {
+    /// Bus name and object path, e.g. `:1.42/org/a11y/atspi/accessible/12`
+    pub id: String,
+    pub app: String,
+    /// AT-SPI role name, e.g. `push button`
+    pub role: String,
+    pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    pub states: Vec<&'static str>,
+    /// AT-SPI interfaces without the `org.a11y.atspi.` prefix, e.g. `Action`
+    pub interfaces: Vec<String>,
+    /// Only nodes with the `Component` interface have a place on screen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<Bounds>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<String>,
+    /// Contents of text nodes, cut to `MAX_TEXT_CHARS`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    pub child_count: i32,
+    /// Children within the requested depth; see `child_count` for the rest
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<A11yNode>,
+}
+
+impl A11yNode {
+    pub fn has_state(&self, state: &str) -> bool {
+        self.states.contains(&state)
+    }
+
+    pub fn has_interface(&self, interface: &str) -> bool {
+        self.interfaces.iter().any(|i| i == interface)
+    }
+}
+
+/// What to do to a node
+#[derive(Debug, Clone)]
+pub enum A11yOperation {
+    /// Invoke the named action, or the first one
+    Press(Option<String>),
+    SetText(String),
+    Focus,
+}
+
+impl A11yOperation {
+    /// What the operation does, without the text it may carry
+    pub fn name(&self) -> &'static str {
+        match self {
+            A11yOperation::Press(_) => "press",
+            A11yOperation::SetText(_) => "set_text",
+            A11yOperation::Focus => "focus",
+        }
+    }
+}
+
+/// The accessibility tree of every application whose name contains `app`,
+/// down to `max_depth` levels below the application
+pub async fn tree(app: Option<&str>, max_depth: usize) -> Result<Vec<A11yNode>> {
+    platform::tree(app, max_depth).await
+}
+
+/// Nodes matching `query`, in tree order
+pub async fn find(query: &A11yQuery, limit: usize) -> Result<Vec<A11yNode>> {
+    check_query(query)?;
+    platform::find(query, limit).await
+}
+
+/// Perform `operation` on the single node matching `target`, returning the
+/// node as it is afterwards
+pub async fn perform(target: &A11yQuery, operation: A11yOperation) -> Result<A11yNode> {
+    let node = resolve(target).await?;
+    platform::perform(&node, &operation).await
+}
+
+/// The single node matching `target`
+pub async fn resolve(target: &A11yQuery) -> Result<A11yNode> {
+    check_query(target)?;
+    if target.id.is_none() && target.role.is_none() && target.name.is_none() {
+        bail!("Accessibility target needs an id, role or name");
+    }
+    let mut nodes = platform::find(target, 2).await?;
+    match nodes.len() {
+        0 => bail!("No accessible node matches {}", describe(target)),
+        1 => Ok(nodes.remove(0)),
+        _ => bail!(
+            "More than one accessible node matches {}; narrow it down or pass an id",
+            describe(target)
+        ),
+    }
+}
+
+/// Process id of the application `node` belongs to
+pub async fn process_id(node: &A11yNode) -> Result<u32> {
+    platform::process_id(node).await
+}
+
+/// Visible nodes that can be pressed or edited, for set-of-marks screenshots
+pub async fn interactive() -> Result<Vec<A11yNode>> {
+    platform::interactive(MAX_INTERACTIVE).await
+}
+
+fn check_query(query: &A11yQuery) -> Result<()> {
+    for state in &query.states {
+        if !STATES.contains(&normalize_state(state).as_str()) {
+            bail!(
+                "Unknown accessibility state {:?}; known states: {}",
+                state,
+                STATES.join(", ")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn describe(query: &A11yQuery) -> String {
+    serde_json::to_string(query).unwrap_or_default()
+}
+
+fn normalize_state(state: &str) -> String {
+    state.trim().to_lowercase().replace([' ', '-'], "_")
+}
+
+fn normalize_role(role: &str) -> String {
+    role.trim().to_lowercase().replace(['_', '-'], " ")
+}
+
+/// Whether a node's own fields satisfy the query; the application filter is
+/// applied while walking
+fn matches(query: &A11yQuery, role: &str, name: &str, states: &[&str]) -> bool {
+    if let Some(wanted) = &query.role {
+        if normalize_role(role) != normalize_role(wanted) {
+            return false;
+        }
+    }
+    if let Some(wanted) = &query.name {
+        if !name.to_lowercase().contains(&wanted.to_lowercase()) {
+            return false;
+        }
+    }
+    query
+        .states
+        .iter()
+        .all(|state| states.contains(&normalize_state(state).as_str()))
+}
+
+fn app_matches(filter: Option<&str>, app: &str) -> bool {
+    filter.is_none_or(|wanted| app.to_lowercase().contains(&wanted.to_lowercase()))
+}
+
+/// AT-SPI state names, indexed by their bit in the state set
+const STATES: &[&str] = &[
+    "invalid",
+    "active",
+    "armed",
+    "busy",
+    "checked",
+    "collapsed",
+    "defunct",
+    "editable",
+    "enabled",
+    "expandable",
+    "expanded",
+    "focusable",
+    "focused",
+    "has_tooltip",
+    "horizontal",
+    "iconified",
+    "modal",
+    "multi_line",
+    "multiselectable",
+    "opaque",
+    "pressed",
+    "resizable",
+    "selectable",
+    "selected",
+    "sensitive",
+    "showing",
+    "single_line",
+    "stale",
+    "transient",
+    "vertical",
+    "visible",
+    "manages_descendants",
+    "indeterminate",
+    "required",
+    "truncated",
+    "animated",
+    "invalid_entry",
+    "supports_autocompletion",
+    "selectable_text",
+    "is_default",
+    "visited",
+    "checkable",
+    "has_popup",
+    "read_only",
+];
+
+/// Names of the bits set in an AT-SPI state set (two 32-bit words)
+fn state_names(words: &[u32]) -> Vec<&'static str> {
+    STATES
+        .iter()
+        .enumerate()
+        .filter(|(bit, _)| {
+            words
+                .get(bit / 32)
+                .is_some_and(|word| word & (1 << (bit % 32)) != 0)
+        })
+        .map(|(_, name)| *name)
+        .collect()
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{
+        app_matches, matches, state_names, A11yNode, A11yOperation, Bounds, MAX_SEARCH_DEPTH,
+        MAX_TEXT_CHARS, MAX_VISITED,
+    };
+    use crate::action::schema::A11yQuery;
+    use anyhow::{anyhow, bail, Context, Result};
+    use futures::future::BoxFuture;
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+    use tracing::debug;
+    use zbus::zvariant::{DynamicType, OwnedObjectPath, OwnedValue, Type};
+    use zbus::Connection;
+
+    const REGISTRY: &str = "org.a11y.atspi.Registry";
+    const ROOT: &str = "/org/a11y/atspi/accessible/root";
+    const NULL: &str = "/org/a11y/atspi/null";
+    const PREFIX: &str = "org.a11y.atspi.";
+    const ACCESSIBLE: &str = "org.a11y.atspi.Accessible";
+
+    /// Bus name and object path of an accessible object
+    type ObjectRef = (String, OwnedObjectPath);
+
+    /// The accessibility bus, which is separate from the session bus
+    async fn connect() -> Result<Connection> {
+        let address = match std::env::var("AT_SPI_BUS_ADDRESS") {
+            Ok(address) if !address.is_empty() => address,
+            _ => {
+                let session = Connection::session()
+                    .await
+                    .context("No D-Bus session bus for accessibility")?;
+                let reply = session
+                    .call_method(
+                        Some("org.a11y.Bus"),
+                        "/org/a11y/bus",
+                        Some("org.a11y.Bus"),
+                        "GetAddress",
+                        &(),
+                    )
+                    .await
+                    .context("No accessibility bus (is at-spi2-core running?)")?;
+                reply.body().deserialize::<String>()?
+            }
+        };
+        zbus::connection::Builder::address(address.as_str())?
+            .build()
+            .await
+            .with_context(|| format!("Failed to connect to the accessibility bus at {}", address))
+    }
+
+    async fn call<B, R>(
+        conn: &Connection,
+        object: &ObjectRef,
+        interface: &str,
+        method: &str,
+        body: &B,
+    ) -> Result<R>
+    where
+        B: Serialize + DynamicType,
+        R: DeserializeOwned + Type,
+    {
+        let reply = conn
+            .call_method(
+                Some(object.0.as_str()),
+                object.1.as_str(),
+                Some(interface),
+                method,
+                body,
+            )
+            .await
+            .with_context(|| format!("{}.{} on {}", interface, method, id(object)))?;
+        Ok(reply.body().deserialize::<R>()?)
+    }
+
+    async fn property<R: TryFrom<OwnedValue>>(
+        conn: &Connection,
+        object: &ObjectRef,
+        name: &str,
+    ) -> Result<R> {
+        let value: OwnedValue = call(
+            conn,
+            object,
+            "org.freedesktop.DBus.Properties",
+            "Get",
+            &(ACCESSIBLE, name),
+        )
+        .await?;
+        R::try_from(value)
+            .map_err(|_| anyhow!("Unexpected type for property {} of {}", name, id(object)))
+    }
+
+    fn id(object: &ObjectRef) -> String {
+        format!("{}{}", object.0, object.1.as_str())
+    }
+
+    /// Split an id back into bus name and path at the first `/`
+    fn parse_id(id: &str) -> Result<ObjectRef> {
+        let slash = id
+            .find('/')
+            .ok_or_else(|| anyhow!("Invalid accessibility node id {:?}", id))?;
+        let path = OwnedObjectPath::try_from(&id[slash..])
+            .map_err(|_| anyhow!("Invalid accessibility node id {:?}", id))?;
+        Ok((id[..slash].to_string(), path))
+    }
+
+    async fn children(conn: &Connection, object: &ObjectRef) -> Result<Vec<ObjectRef>> {
+        let children: Vec<ObjectRef> = call(conn, object, ACCESSIBLE, "GetChildren", &()).await?;
+        Ok(children
+            .into_iter()
+            .filter(|(_, path)| path.as_str() != NULL)
+            .collect())
+    }
+
+    /// Applications registered with the accessibility bus, with their names
+    async fn applications(
+        conn: &Connection,
+        filter: Option<&str>,
+    ) -> Result<Vec<(ObjectRef, String)>> {
+        let root: ObjectRef = (REGISTRY.to_string(), OwnedObjectPath::try_from(ROOT)?);
+        let mut apps = Vec::new();
+        for app in children(conn, &root).await? {
+            let name: String = property(conn, &app, "Name").await.unwrap_or_default();
+            if app_matches(filter, &name) {
+                apps.push((app, name));
+            }
+        }
+        Ok(apps)
+    }
+
+    /// Role, name, states and interfaces: enough to decide whether a node matches
+    struct Summary {
+        role: String,
+        name: String,
+        states: Vec<&'static str>,
+        interfaces: Vec<String>,
+    }
+
+    async fn summary(conn: &Connection, object: &ObjectRef) -> Result<Summary> {
+        let role: String = call(conn, object, ACCESSIBLE, "GetRoleName", &()).await?;
+        let name: String = property(conn, object, "Name").await?;
+        let states: Vec<u32> = call(conn, object, ACCESSIBLE, "GetState", &()).await?;
+        let interfaces: Vec<String> = call(conn, object, ACCESSIBLE, "GetInterfaces", &()).await?;
+        Ok(Summary {
+            role,
+            name,
+            states: state_names(&states),
+            interfaces: interfaces
+                .into_iter()
+                .map(|i| i.strip_prefix(PREFIX).map(str::to_string).unwrap_or(i))
+                .collect(),
+        })
+    }
+
+    /// Everything about a node except its children
+    async fn describe(
+        conn: &Connection,
+        object: &ObjectRef,
+        app: &str,
+        summary: Summary,
+    ) -> Result<A11yNode> {
+        let description: String = property(conn, object, "Description")
+            .await
+            .unwrap_or_default();
+        let child_count: i32 = property(conn, object, "ChildCount")
+            .await
+            .unwrap_or_default();
+        let has = |interface: &str| summary.interfaces.iter().any(|i| i == interface);
+
+        let bounds = if has("Component") {
+            // Coordinate type 0 is the screen
+            let (x, y, width, height): (i32, i32, i32, i32) = call(
+                conn,
+                object,
+                "org.a11y.atspi.Component",
+                "GetExtents",
+                &0u32,
+            )
+            .await?;
+            Some(Bounds {
+                x,
+                y,
+                width: width.max(0) as u32,
+                height: height.max(0) as u32,
+            })
+        } else {
+            None
+        };
+        let actions = if has("Action") {
+            let actions: Vec<(String, String, String)> =
+                call(conn, object, "org.a11y.atspi.Action", "GetActions", &()).await?;
+            actions.into_iter().map(|(name, _, _)| name).collect()
+        } else {
+            Vec::new()
+        };
+        let text = if has("Text") {
+            let count: i32 = conn
+                .call_method(
+                    Some(object.0.as_str()),
+                    object.1.as_str(),
+                    Some("org.freedesktop.DBus.Properties"),
+                    "Get",
+                    &("org.a11y.atspi.Text", "CharacterCount"),
+                )
+                .await
+                .ok()
+                .and_then(|reply| reply.body().deserialize::<OwnedValue>().ok())
+                .and_then(|value| i32::try_from(value).ok())
+                .unwrap_or(0);
+            let text: String = call(
+                conn,
+                object,
+                "org.a11y.atspi.Text",
+                "GetText",
+                &(0i32, count.min(MAX_TEXT_CHARS)),
+            )
+            .await?;
+            Some(text)
+        } else {
+            None
+        };
+
+        Ok(A11yNode {
+            id: id(object),
+            app: app.to_string(),
+            role: summary.role,
+            name: summary.name,
+            description,
+            states: summary.states,
+            interfaces: summary.interfaces,
+            bounds,
+            actions,
+            text,
+            child_count,
+            children: Vec::new(),
+        })
+    }
+
+    pub async fn tree(app: Option<&str>, max_depth: usize) -> Result<Vec<A11yNode>> {
+        let conn = connect().await?;
+        let mut visited = 0;
+        let mut trees = Vec::new();
+        for (object, name) in applications(&conn, app).await? {
+            match subtree(&conn, object, &name, max_depth, &mut visited).await {
+                Ok(node) => trees.push(node),
+                Err(e) => debug!("Skipping application {}: {:#}", name, e),
+            }
+        }
+        Ok(trees)
+    }
+
+    fn subtree<'a>(
+        conn: &'a Connection,
+        object: ObjectRef,
+        app: &'a str,
+        depth: usize,
+        visited: &'a mut usize,
+    ) -> BoxFuture<'a, Result<A11yNode>> {
+        Box::pin(async move {
+            *visited += 1;
+            let summary = summary(conn, &object).await?;
+            let mut node = describe(conn, &object, app, summary).await?;
+            // Tables and lists managing their descendants can hold thousands of rows
+            if depth == 0 || node.has_state("manages_descendants") {
+                return Ok(node);
+            }
+            for child in children(conn, &object).await? {
+                if *visited >= MAX_VISITED {
+                    break;
+                }
+                match subtree(conn, child, app, depth - 1, visited).await {
+                    Ok(child) => node.children.push(child),
+                    Err(e) => debug!("Skipping accessible child: {:#}", e),
+                }
+            }
+            Ok(node)
+        })
+    }
+
+    pub async fn find(query: &A11yQuery, limit: usize) -> Result<Vec<A11yNode>> {
+        let conn = connect().await?;
+        if let Some(id) = &query.id {
+            let object = parse_id(id)?;
+            let summary = summary(&conn, &object).await?;
+            let application: ObjectRef =
+                call(&conn, &object, ACCESSIBLE, "GetApplication", &()).await?;
+            let app: String = property(&conn, &application, "Name")
+                .await
+                .unwrap_or_default();
+            if !app_matches(query.app.as_deref(), &app)
+                || !matches(query, &summary.role, &summary.name, &summary.states)
+            {
+                return Ok(Vec::new());
+            }
+            return Ok(vec![describe(&conn, &object, &app, summary).await?]);
+        }
+        search(&conn, query.app.as_deref(), limit, |summary| {
+            matches(query, &summary.role, &summary.name, &summary.states)
+        })
+        .await
+    }
+
+    pub async fn interactive(limit: usize) -> Result<Vec<A11yNode>> {
+        let conn = connect().await?;
+        let nodes = search(&conn, None, limit, |summary| {
+            summary.states.contains(&"showing")
+                && summary.states.contains(&"visible")
+                && summary
+                    .interfaces
+                    .iter()
+                    .any(|i| i == "Action" || i == "EditableText")
+        })
+        .await?;
+        Ok(nodes.into_iter().filter(|n| n.bounds.is_some()).collect())
+    }
+
+    /// Depth-first walk of every application, describing the nodes `wanted`
+    /// accepts until `limit` are found
+    async fn search(
+        conn: &Connection,
+        app: Option<&str>,
+        limit: usize,
+        wanted: impl Fn(&Summary) -> bool,
+    ) -> Result<Vec<A11yNode>> {
+        let mut found = Vec::new();
+        let mut visited = 0;
+        for (application, name) in applications(conn, app).await? {
+            let mut pending = vec![(application, 0)];
+            while let Some((object, depth)) = pending.pop() {
+                if found.len() >= limit || visited >= MAX_VISITED {
+                    return Ok(found);
+                }
+                visited += 1;
+                let summary = match summary(conn, &object).await {
+                    Ok(summary) => summary,
+                    Err(e) => {
+                        debug!("Skipping accessible node: {:#}", e);
+                        continue;
+                    }
+                };
+                let descend =
+                    depth < MAX_SEARCH_DEPTH && !summary.states.contains(&"manages_descendants");
+                if wanted(&summary) {
+                    match describe(conn, &object, &name, summary).await {
+                        Ok(node) => found.push(node),
+                        Err(e) => debug!("Skipping accessible node: {:#}", e),
+                    }
+                }
+                if descend {
+                    let children = children(conn, &object).await.unwrap_or_default();
+                    // Reversed so the walk visits children in order
+                    pending.extend(children.into_iter().rev().map(|child| (child, depth + 1)));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    pub async fn perform(node: &A11yNode, operation: &A11yOperation) -> Result<A11yNode> {
+        let conn = connect().await?;
+        let object = parse_id(&node.id)?;
+        let done: bool = match operation {
+            A11yOperation::Press(action) => {
+                if !node.has_interface("Action") || node.actions.is_empty() {
+                    bail!("{} {:?} has no actions", node.role, node.name);
+                }
+                let index = match action {
+                    Some(wanted) => node
+                        .actions
+                        .iter()
+                        .position(|a| a.eq_ignore_ascii_case(wanted))
+                        .ok_or_else(|| {
+                            anyhow!(
+                                "{} {:?} has no action {:?}; it has {}",
+                                node.role,
+                                node.name,
+                                wanted,
+                                node.actions.join(", ")
+                            )
+                        })?,
+                    None => 0,
+                };
+                call(
+                    &conn,
+                    &object,
+                    "org.a11y.atspi.Action",
+                    "DoAction",
+                    &(index as i32),
+                )
+                .await?
+            }
+            A11yOperation::SetText(text) => {
+                if !node.has_interface("EditableText") {
+                    bail!("{} {:?} is not editable text", node.role, node.name);
+                }
+                call(
+                    &conn,
+                    &object,
+                    "org.a11y.atspi.EditableText",
+                    "SetTextContents",
+                    &text.as_str(),
+                )
+                .await?
+            }
+            A11yOperation::Focus => {
+                if !node.has_interface("Component") {
+                    bail!("{} {:?} cannot take focus", node.role, node.name);
+                }
+                call(&conn, &object, "org.a11y.atspi.Component", "GrabFocus", &()).await?
+            }
+        };
+        if !done {
+            bail!("{} {:?} refused {}", node.role, node.name, operation.name());
+        }
+        let summary = summary(&conn, &object).await?;
+        describe(&conn, &object, &node.app, summary).await
+    }
+
+    /// Ask the bus which process owns the node's connection
+    pub async fn process_id(node: &A11yNode) -> Result<u32> {
+        let conn = connect().await?;
+        let (bus, _) = parse_id(&node.id)?;
+        let bus_daemon = (
+            "org.freedesktop.DBus".to_string(),
+            OwnedObjectPath::try_from("/org/freedesktop/DBus")?,
+        );
+        call(
+            &conn,
+            &bus_daemon,
+            "org.freedesktop.DBus",
+            "GetConnectionUnixProcessID",
+            &bus.as_str(),
+        )
+        .await
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::{A11yNode, A11yOperation};
+    use crate::action::schema::A11yQuery;
+    use anyhow::{bail, Result};
+
+    pub async fn tree(_app: Option<&str>, _max_depth: usize) -> Result<Vec<A11yNode>> {
+        bail!("Accessibility inspection is only implemented for AT-SPI on Linux")
+    }
+
+    pub async fn find(_query: &A11yQuery, _limit: usize) -> Result<Vec<A11yNode>> {
+        bail!("Accessibility inspection is only implemented for AT-SPI on Linux")
+    }
+
+    pub async fn interactive(_limit: usize) -> Result<Vec<A11yNode>> {
+        bail!("Accessibility inspection is only implemented for AT-SPI on Linux")
+    }
+
+    pub async fn perform(_node: &A11yNode, _operation: &A11yOperation) -> Result<A11yNode> {
+        bail!("Accessibility actions are only implemented for AT-SPI on Linux")
+    }
+
+    pub async fn process_id(_node: &A11yNode) -> Result<u32> {
+        bail!("Accessibility inspection is only implemented for AT-SPI on Linux")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query(role: Option<&str>, name: Option<&str>, states: &[&str]) -> A11yQuery {
+        A11yQuery {
+            id: None,
+            role: role.map(str::to_string),
+            name: name.map(str::to_string),
+            app: None,
+            states: states.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn state_names_follow_bit_positions() {
+        assert!(state_names(&[]).is_empty());
+        assert_eq!(state_names(&[0b10]), ["active"]);
+        // Bit 8 is `enabled`, bit 25 `showing`, bit 32 (second word) `indeterminate`
+        assert_eq!(state_names(&[1 << 8 | 1 << 25, 1]), ["enabled", "showing", "indeterminate"]);
+        // Bits past the known states are ignored
+        assert_eq!(state_names(&[0, 1 << 31]), Vec::<&str>::new());
+    }
+
+    #[test]
+    fn normalize_role_accepts_atspi_spellings() {
+        assert_eq!(normalize_role("Push_Button"), "push button");
+        assert_eq!(normalize_role(" check-box "), "check box");
+        assert_eq!(normalize_role("text"), "text");
+    }
+
+    #[test]
+    fn matches_checks_role_name_and_states() {
+        let states = ["enabled", "showing"];
+        let button = |query: &A11yQuery| matches(query, "push button", "Save As…", &states);
+        assert!(button(&query(None, None, &[])));
+        assert!(button(&query(Some("PUSH_BUTTON"), Some("save as"), &["Showing"])));
+        assert!(!button(&query(Some("toggle button"), None, &[])));
+        assert!(!button(&query(None, Some("open"), &[])));
+        assert!(!button(&query(None, None, &["focused"])));
+    }
+
+    /// Needs a desktop session with the AT-SPI bus and at least one
+    /// accessible application running
+    #[tokio::test]
+    #[ignore]
+    async fn reads_live_applications() {
+        let applications = tree(None, 1).await.expect("AT-SPI bus reachable");
+        assert!(!applications.is_empty(), "no accessible applications");
+        let first = &applications[0];
+        let target = A11yQuery {
+            id: Some(first.id.clone()),
+            ..Default::default()
+        };
+        let node = resolve(&target).await.expect("node resolves by id");
+        assert_eq!(node.id, first.id);
+        assert!(process_id(&node).await.expect("owner pid") > 0);
+    }
+}
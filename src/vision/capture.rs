@@ -0,0 +1,292 @@
+use crate::action::schema::ScreenRegion;
+use anyhow::Result;
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_hollow_circle_mut, draw_line_segment_mut};
+use serde::Serialize;
+
+/// Monitor geometry in screen coordinates
+#[derive(Debug, Clone, Serialize)]
+pub struct Monitor {
+    pub name: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub primary: bool,
+}
+
+/// A captured frame and where it sits on the screen
+pub struct Frame {
+    pub image: RgbaImage,
+    /// Top-left corner of the frame in screen coordinates
+    pub origin: (i32, i32),
+    /// Pointer position in screen coordinates, if it could be queried
+    pub cursor: Option<(i32, i32)>,
+}
+
+impl Frame {
+    /// Pointer position relative to the frame, if it falls inside it
+    pub fn cursor_in_frame(&self) -> Option<(i32, i32)> {
+        let (cx, cy) = self.cursor?;
+        let (x, y) = (cx - self.origin.0, cy - self.origin.1);
+        let inside = x >= 0
+            && y >= 0
+            && (x as u32) < self.image.width()
+            && (y as u32) < self.image.height();
+        inside.then_some((x, y))
+    }
+
+    /// Draw a red crosshair-in-a-circle over the pointer position
+    ///
+    /// A white halo keeps the reticle visible on red or busy backgrounds.
+    pub fn draw_cursor_reticle(&mut self) {
+        let Some((x, y)) = self.cursor_in_frame() else {
+            return;
+        };
+        let red = Rgba([255, 32, 32, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+        let image = &mut self.image;
+
+        draw_hollow_circle_mut(image, (x, y), 15, white);
+        draw_hollow_circle_mut(image, (x, y), 12, white);
+        draw_hollow_circle_mut(image, (x, y), 13, red);
+        draw_hollow_circle_mut(image, (x, y), 14, red);
+
+        let (fx, fy) = (x as f32, y as f32);
+        for offset in [-1.0, 1.0] {
+            draw_line_segment_mut(image, (fx - 20.0, fy + offset), (fx - 4.0, fy + offset), white);
+            draw_line_segment_mut(image, (fx + 4.0, fy + offset), (fx + 20.0, fy + offset), white);
+            draw_line_segment_mut(image, (fx + offset, fy - 20.0), (fx + offset, fy - 4.0), white);
+            draw_line_segment_mut(image, (fx + offset, fy + 4.0), (fx + offset, fy + 20.0), white);
+        }
+        draw_line_segment_mut(image, (fx - 20.0, fy), (fx - 4.0, fy), red);
+        draw_line_segment_mut(image, (fx + 4.0, fy), (fx + 20.0, fy), red);
+        draw_line_segment_mut(image, (fx, fy - 20.0), (fx, fy - 4.0), red);
+        draw_line_segment_mut(image, (fx, fy + 4.0), (fx, fy + 20.0), red);
+    }
+}
+
+/// Monitors known to the display server, in RandR order
+pub fn monitors() -> Result<Vec<Monitor>> {
+    platform::monitors()
+}
+
+/// Grab the framebuffer
+///
+/// With `monitor` the capture is limited to that monitor, and `region` is
+/// taken relative to it; otherwise `region` is in screen coordinates. The
+/// region is clipped to the screen.
+pub fn grab(monitor: Option<usize>, region: Option<ScreenRegion>) -> Result<Frame> {
+    platform::grab(monitor, region)
+}
+
+#[cfg(target_os = "linux")]
+mod platform {
+    use super::{Frame, Monitor};
+    use crate::action::schema::ScreenRegion;
+    use anyhow::{anyhow, bail, Context, Result};
+    use image::RgbaImage;
+    use x11rb::connection::Connection;
+    use x11rb::protocol::randr::ConnectionExt as _;
+    use x11rb::protocol::xproto::{ConnectionExt, ImageFormat, ImageOrder, Screen};
+    use x11rb::rust_connection::RustConnection;
+
+    fn connect() -> Result<(RustConnection, usize)> {
+        x11rb::connect(None).context("No X11 display available for screen capture")
+    }
+
+    fn list_monitors(conn: &RustConnection, screen: &Screen) -> Vec<Monitor> {
+        // Xvfb and older servers may lack RandR 1.5; treat the screen as one monitor
+        let monitors = conn
+            .randr_get_monitors(screen.root, true)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map(|reply| reply.monitors)
+            .unwrap_or_default();
+        if monitors.is_empty() {
+            return vec![Monitor {
+                name: "screen".to_string(),
+                x: 0,
+                y: 0,
+                width: screen.width_in_pixels.into(),
+                height: screen.height_in_pixels.into(),
+                primary: true,
+            }];
+        }
+
+        monitors
+            .into_iter()
+            .map(|m| Monitor {
+                name: conn
+                    .get_atom_name(m.name)
+                    .ok()
+                    .and_then(|cookie| cookie.reply().ok())
+                    .map(|reply| String::from_utf8_lossy(&reply.name).to_string())
+                    .unwrap_or_default(),
+                x: m.x.into(),
+                y: m.y.into(),
+                width: m.width.into(),
+                height: m.height.into(),
+                primary: m.primary,
+            })
+            .collect()
+    }
+
+    pub fn monitors() -> Result<Vec<Monitor>> {
+        let (conn, screen_num) = connect()?;
+        let screen = &conn.setup().roots[screen_num];
+        Ok(list_monitors(&conn, screen))
+    }
+
+    pub fn grab(monitor: Option<usize>, region: Option<ScreenRegion>) -> Result<Frame> {
+        let (conn, screen_num) = connect()?;
+        let setup = conn.setup();
+        let screen = &setup.roots[screen_num];
+        let screen_bounds = (0, 0, u32::from(screen.width_in_pixels), u32::from(screen.height_in_pixels));
+
+        let bounds = match monitor {
+            Some(index) => {
+                let monitors = list_monitors(&conn, screen);
+                let m = monitors.get(index).ok_or_else(|| {
+                    anyhow!("No monitor {} (found {})", index, monitors.len())
+                })?;
+                (m.x, m.y, m.width, m.height)
+            }
+            None => screen_bounds,
+        };
+        let area = match region {
+            Some(r) => {
+                // User-supplied, so computed wide and checked before use
+                let x = i64::from(bounds.0) + i64::from(r.x);
+                let y = i64::from(bounds.1) + i64::from(r.y);
+                let fits = |start: i64, length: u32| {
+                    i32::try_from(start).is_ok() && i32::try_from(start + i64::from(length)).is_ok()
+                };
+                if !fits(x, r.width) || !fits(y, r.height) {
+                    bail!("Capture region {:?} does not fit in screen coordinates", r);
+                }
+                (x as i32, y as i32, r.width, r.height)
+            }
+            None => bounds,
+        };
+        let (x, y, width, height) = clip(area, bounds)
+            .and_then(|area| clip(area, screen_bounds))
+            .ok_or_else(|| anyhow!("Capture region lies outside the screen"))?;
+
+        let reply = conn
+            .get_image(
+                ImageFormat::Z_PIXMAP,
+                screen.root,
+                x as i16,
+                y as i16,
+                width as u16,
+                height as u16,
+                !0,
+            )?
+            .reply()
+            .context("GetImage failed")?;
+
+        let format = setup
+            .pixmap_formats
+            .iter()
+            .find(|f| f.depth == reply.depth)
+            .ok_or_else(|| anyhow!("No pixmap format for depth {}", reply.depth))?;
+        let visual = screen
+            .allowed_depths
+            .iter()
+            .flat_map(|d| d.visuals.iter())
+            .find(|v| v.visual_id == reply.visual)
+            .ok_or_else(|| anyhow!("Unknown visual {}", reply.visual))?;
+
+        let bytes_per_pixel = usize::from(format.bits_per_pixel / 8);
+        if !(2..=4).contains(&bytes_per_pixel) {
+            bail!("Unsupported pixel format: {} bits per pixel", format.bits_per_pixel);
+        }
+        let pad = usize::from(format.scanline_pad);
+        let stride = (width as usize * usize::from(format.bits_per_pixel)).div_ceil(pad) * pad / 8;
+        let big_endian = setup.image_byte_order == ImageOrder::MSB_FIRST;
+        let channels = [visual.red_mask, visual.green_mask, visual.blue_mask].map(Channel::new);
+
+        let mut image = RgbaImage::new(width, height);
+        for (px, py, pixel) in image.enumerate_pixels_mut() {
+            let start = py as usize * stride + px as usize * bytes_per_pixel;
+            let raw = &reply.data[start..start + bytes_per_pixel];
+            let value = if big_endian {
+                raw.iter().fold(0u32, |acc, b| (acc << 8) | u32::from(*b))
+            } else {
+                raw.iter().rev().fold(0u32, |acc, b| (acc << 8) | u32::from(*b))
+            };
+            let [r, g, b] = channels.map(|c| c.extract(value));
+            *pixel = image::Rgba([r, g, b, 255]);
+        }
+
+        let cursor = conn
+            .query_pointer(screen.root)
+            .ok()
+            .and_then(|cookie| cookie.reply().ok())
+            .map(|p| (i32::from(p.root_x), i32::from(p.root_y)));
+
+        Ok(Frame {
+            image,
+            origin: (x, y),
+            cursor,
+        })
+    }
+
+    /// Intersect `region` with `bounds`, both as (x, y, width, height)
+    fn clip(region: (i32, i32, u32, u32), bounds: (i32, i32, u32, u32)) -> Option<(i32, i32, u32, u32)> {
+        // Edges in i64, where `x + width` cannot overflow
+        let edges = |(x, y, width, height): (i32, i32, u32, u32)| {
+            let (x, y) = (i64::from(x), i64::from(y));
+            (x, y, x + i64::from(width), y + i64::from(height))
+        };
+        let (region_left, region_top, region_right, region_bottom) = edges(region);
+        let (bounds_left, bounds_top, bounds_right, bounds_bottom) = edges(bounds);
+        let left = region_left.max(bounds_left);
+        let top = region_top.max(bounds_top);
+        let right = region_right.min(bounds_right);
+        let bottom = region_bottom.min(bounds_bottom);
+        // Both corners come from i32 inputs, so the narrowing casts are lossless
+        (right > left && bottom > top)
+            .then(|| (left as i32, top as i32, (right - left) as u32, (bottom - top) as u32))
+    }
+
+    /// One color channel of a TrueColor visual, scaled to 8 bits
+    #[derive(Clone, Copy)]
+    struct Channel {
+        shift: u32,
+        max: u32,
+    }
+
+    impl Channel {
+        fn new(mask: u32) -> Self {
+            let shift = mask.trailing_zeros().min(31);
+            Self {
+                shift,
+                max: mask >> shift,
+            }
+        }
+
+        fn extract(self, pixel: u32) -> u8 {
+            if self.max == 0 {
+                return 0;
+            }
+            (((pixel >> self.shift) & self.max) * 255 / self.max) as u8
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+mod platform {
+    use super::{Frame, Monitor};
+    use crate::action::schema::ScreenRegion;
+    use anyhow::{bail, Result};
+
+    pub fn monitors() -> Result<Vec<Monitor>> {
+        bail!("Screen capture is only implemented for X11")
+    }
+
+    pub fn grab(_monitor: Option<usize>, _region: Option<ScreenRegion>) -> Result<Frame> {
+        bail!("Screen capture is only implemented for X11")
+    }
+}
@@ -0,0 +1,291 @@
+use image::{Rgba, RgbaImage};
+use serde::Serialize;
+
+/// Per-channel difference below which two pixels count as unchanged
+pub const PIXEL_TOLERANCE: u8 = 16;
+/// Side of the grid cells changed pixels are grouped into; changes closer
+/// than about this many pixels end up in the same region
+const CELL_SIZE: u32 = 16;
+/// Side of the blocks structural similarity is measured over
+const SSIM_BLOCK: u32 = 8;
+/// SSIM stabilizers for 8-bit luminance: (0.01 * 255)^2 and (0.03 * 255)^2
+const SSIM_C1: f64 = 6.5025;
+const SSIM_C2: f64 = 58.5225;
+
+/// A box around changed pixels; coordinates are relative to the compared
+/// images until [`ScreenDiff::offset`] moves them into screen space
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangedRegion {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub changed_pixels: u64,
+}
+
+/// How two captures of the same area differ
+#[derive(Debug, Clone, Serialize)]
+pub struct ScreenDiff {
+    pub width: u32,
+    pub height: u32,
+    pub changed_pixels: u64,
+    /// Fraction of pixels (0-1) with any channel off by more than the tolerance
+    pub changed_fraction: f64,
+    /// One minus the mean structural similarity of the luminance (0-1); unlike
+    /// `changed_fraction` it discounts noise and tracks what a person notices
+    pub perceptual_difference: f64,
+    /// Areas that changed, largest first
+    pub regions: Vec<ChangedRegion>,
+}
+
+impl ScreenDiff {
+    /// Shift every region by `(dx, dy)`, e.g. from capture to screen coordinates
+    pub fn offset(mut self, dx: i32, dy: i32) -> Self {
+        for region in &mut self.regions {
+            region.x += dx;
+            region.y += dy;
+        }
+        self
+    }
+}
+
+/// Bounding box and count of the changed pixels in one grid cell
+#[derive(Clone, Copy)]
+struct Cell {
+    left: u32,
+    top: u32,
+    right: u32,
+    bottom: u32,
+    changed: u64,
+}
+
+/// Compare two frames; frames of different sizes (a monitor was
+/// reconfigured) count as entirely changed
+pub fn diff(before: &RgbaImage, after: &RgbaImage) -> ScreenDiff {
+    let (width, height) = after.dimensions();
+    let total = u64::from(width) * u64::from(height);
+    if before.dimensions() != after.dimensions() {
+        return ScreenDiff {
+            width,
+            height,
+            changed_pixels: total,
+            changed_fraction: 1.0,
+            perceptual_difference: 1.0,
+            regions: vec![ChangedRegion {
+                x: 0,
+                y: 0,
+                width,
+                height,
+                changed_pixels: total,
+            }],
+        };
+    }
+
+    let columns = width.div_ceil(CELL_SIZE);
+    let rows = height.div_ceil(CELL_SIZE);
+    let mut cells = vec![None::<Cell>; (columns * rows) as usize];
+    let mut changed_pixels = 0u64;
+    for (x, y, a) in before.enumerate_pixels() {
+        let b = after.get_pixel(x, y);
+        if !a.0.iter().zip(b.0.iter()).any(|(p, q)| p.abs_diff(*q) > PIXEL_TOLERANCE) {
+            continue;
+        }
+        changed_pixels += 1;
+        let cell = &mut cells[((y / CELL_SIZE) * columns + x / CELL_SIZE) as usize];
+        match cell {
+            Some(cell) => {
+                cell.left = cell.left.min(x);
+                cell.top = cell.top.min(y);
+                cell.right = cell.right.max(x);
+                cell.bottom = cell.bottom.max(y);
+                cell.changed += 1;
+            }
+            None => {
+                *cell = Some(Cell {
+                    left: x,
+                    top: y,
+                    right: x,
+                    bottom: y,
+                    changed: 1,
+                })
+            }
+        }
+    }
+
+    ScreenDiff {
+        width,
+        height,
+        changed_pixels,
+        changed_fraction: if total == 0 {
+            0.0
+        } else {
+            changed_pixels as f64 / total as f64
+        },
+        perceptual_difference: if changed_pixels == 0 {
+            0.0
+        } else {
+            1.0 - structural_similarity(before, after)
+        },
+        regions: regions(&cells, columns, rows),
+    }
+}
+
+/// Join neighbouring changed cells (including diagonals) into regions
+fn regions(cells: &[Option<Cell>], columns: u32, rows: u32) -> Vec<ChangedRegion> {
+    let mut seen = vec![false; cells.len()];
+    let mut regions = Vec::new();
+    for start in 0..cells.len() {
+        if seen[start] || cells[start].is_none() {
+            continue;
+        }
+        seen[start] = true;
+        let mut pending = vec![start];
+        let mut bounds: Option<Cell> = None;
+        while let Some(index) = pending.pop() {
+            let Some(cell) = cells[index] else {
+                continue;
+            };
+            bounds = Some(match bounds {
+                Some(b) => Cell {
+                    left: b.left.min(cell.left),
+                    top: b.top.min(cell.top),
+                    right: b.right.max(cell.right),
+                    bottom: b.bottom.max(cell.bottom),
+                    changed: b.changed + cell.changed,
+                },
+                None => cell,
+            });
+            let (column, row) = (index as u32 % columns, index as u32 / columns);
+            for ny in row.saturating_sub(1)..=(row + 1).min(rows - 1) {
+                for nx in column.saturating_sub(1)..=(column + 1).min(columns - 1) {
+                    let neighbour = (ny * columns + nx) as usize;
+                    if !seen[neighbour] && cells[neighbour].is_some() {
+                        seen[neighbour] = true;
+                        pending.push(neighbour);
+                    }
+                }
+            }
+        }
+        if let Some(b) = bounds {
+            regions.push(ChangedRegion {
+                x: b.left as i32,
+                y: b.top as i32,
+                width: b.right - b.left + 1,
+                height: b.bottom - b.top + 1,
+                changed_pixels: b.changed,
+            });
+        }
+    }
+    regions.sort_by_key(|r| std::cmp::Reverse(r.changed_pixels));
+    regions
+}
+
+/// Mean SSIM of the luminance over `SSIM_BLOCK`-sized blocks
+fn structural_similarity(before: &RgbaImage, after: &RgbaImage) -> f64 {
+    let (width, height) = before.dimensions();
+    let (mut total, mut blocks) = (0.0, 0u64);
+    for top in (0..height).step_by(SSIM_BLOCK as usize) {
+        for left in (0..width).step_by(SSIM_BLOCK as usize) {
+            let (mut sum_a, mut sum_b, mut sq_a, mut sq_b, mut cross) = (0.0, 0.0, 0.0, 0.0, 0.0);
+            let mut n = 0.0;
+            for y in top..(top + SSIM_BLOCK).min(height) {
+                for x in left..(left + SSIM_BLOCK).min(width) {
+                    let a = luminance(before.get_pixel(x, y));
+                    let b = luminance(after.get_pixel(x, y));
+                    sum_a += a;
+                    sum_b += b;
+                    sq_a += a * a;
+                    sq_b += b * b;
+                    cross += a * b;
+                    n += 1.0;
+                }
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+            let var_a = sq_a / n - mean_a * mean_a;
+            let var_b = sq_b / n - mean_b * mean_b;
+            let covariance = cross / n - mean_a * mean_b;
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            blocks += 1;
+        }
+    }
+    if blocks == 0 {
+        return 1.0;
+    }
+    (total / blocks as f64).clamp(0.0, 1.0)
+}
+
+fn luminance(pixel: &Rgba<u8>) -> f64 {
+    let [r, g, b, _] = pixel.0;
+    0.299 * r as f64 + 0.587 * g as f64 + 0.114 * b as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GREY: Rgba<u8> = Rgba([128, 128, 128, 255]);
+
+    /// A grey image with the given (x, y, width, height) boxes painted white
+    fn image(boxes: &[(u32, u32, u32, u32)]) -> RgbaImage {
+        let mut image = RgbaImage::from_pixel(128, 96, GREY);
+        for &(x, y, width, height) in boxes {
+            for py in y..y + height {
+                for px in x..x + width {
+                    image.put_pixel(px, py, Rgba([255, 255, 255, 255]));
+                }
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn identical_images_have_no_regions() {
+        let before = image(&[(10, 10, 20, 20)]);
+        let result = diff(&before, &before.clone());
+        assert_eq!(result.changed_pixels, 0);
+        assert_eq!(result.changed_fraction, 0.0);
+        assert_eq!(result.perceptual_difference, 0.0);
+        assert!(result.regions.is_empty());
+        assert!((structural_similarity(&before, &before) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn separate_changes_are_separate_regions() {
+        let result = diff(&image(&[]), &image(&[(4, 4, 10, 6), (90, 60, 20, 20)]));
+        assert_eq!(result.changed_pixels, 460);
+        let boxes: Vec<_> = result
+            .regions
+            .iter()
+            .map(|r| (r.x, r.y, r.width, r.height, r.changed_pixels))
+            .collect();
+        // Largest first
+        assert_eq!(boxes, [(90, 60, 20, 20, 400), (4, 4, 10, 6, 60)]);
+        assert!(result.perceptual_difference > 0.0);
+
+        let moved = result.offset(100, 200);
+        assert_eq!((moved.regions[1].x, moved.regions[1].y), (104, 204));
+    }
+
+    #[test]
+    fn changes_spanning_cells_are_one_region() {
+        // Crosses four grid cells, which are joined back together
+        let result = diff(&image(&[]), &image(&[(10, 10, 30, 30)]));
+        assert_eq!(result.regions.len(), 1);
+        let region = &result.regions[0];
+        assert_eq!((region.x, region.y, region.width, region.height), (10, 10, 30, 30));
+        assert_eq!(region.changed_pixels, 900);
+    }
+
+    #[test]
+    fn small_differences_and_resizes() {
+        let mut noisy = image(&[]);
+        noisy.put_pixel(5, 5, Rgba([128 + PIXEL_TOLERANCE, 128, 128, 255]));
+        assert_eq!(diff(&image(&[]), &noisy).changed_pixels, 0);
+
+        let resized = RgbaImage::from_pixel(64, 64, GREY);
+        let result = diff(&image(&[]), &resized);
+        assert_eq!(result.changed_fraction, 1.0);
+        assert_eq!(result.regions.len(), 1);
+    }
+}
@@ -0,0 +1,545 @@
+use crate::action::schema::{MarkLabel, MarkSource};
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, Utc};
+use image::{Rgba, RgbaImage};
+use imageproc::drawing::{draw_filled_rect_mut, draw_hollow_rect_mut};
+use imageproc::rect::Rect;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Grid cells narrower than this can't fit a label such as `AB12` beside
+/// the grid line; grids with longer labels need wider cells, see [`Grid::new`]
+pub const MIN_GRID_CELL: u32 = 28;
+/// Boxes narrower or shorter than this are not worth a mark
+const MIN_MARK_SIDE: u32 = 4;
+/// Mark colors, dark enough for white label text
+const PALETTE: [Rgba<u8>; 8] = [
+    Rgba([220, 20, 60, 255]),
+    Rgba([0, 100, 200, 255]),
+    Rgba([0, 140, 70, 255]),
+    Rgba([150, 60, 200, 255]),
+    Rgba([200, 90, 0, 255]),
+    Rgba([0, 130, 140, 255]),
+    Rgba([170, 0, 120, 255]),
+    Rgba([90, 90, 90, 255]),
+];
+const GRID_COLOR: Rgba<u8> = Rgba([255, 0, 200, 255]);
+const GRID_OPACITY: f32 = 0.45;
+const WHITE: Rgba<u8> = Rgba([255, 255, 255, 255]);
+const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+/// A numbered box over something clickable, in screen coordinates
+#[derive(Debug, Clone, Serialize)]
+pub struct Mark {
+    pub id: u32,
+    pub source: MarkSource,
+    /// What the mark covers, e.g. the text or window title
+    pub label: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub center: (i32, i32),
+    /// The window a window mark stands for; `click_mark` focuses it before
+    /// clicking
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window: Option<u32>,
+}
+
+impl Mark {
+    /// An unnumbered mark; [`number`] assigns ids once all are collected
+    pub fn new(source: MarkSource, label: String, x: i32, y: i32, width: u32, height: u32) -> Self {
+        Self {
+            id: 0,
+            source,
+            label,
+            x,
+            y,
+            width,
+            height,
+            center: (x + width as i32 / 2, y + height as i32 / 2),
+            window: None,
+        }
+    }
+
+    /// The part of the mark inside `bounds` (x, y, width, height), if any
+    fn clipped(self, bounds: (i32, i32, u32, u32)) -> Option<Self> {
+        let (left, top) = (self.x.max(bounds.0), self.y.max(bounds.1));
+        let right = (self.x + self.width as i32).min(bounds.0 + bounds.2 as i32);
+        let bottom = (self.y + self.height as i32).min(bounds.1 + bounds.3 as i32);
+        if right - left < MIN_MARK_SIDE as i32 || bottom - top < MIN_MARK_SIDE as i32 {
+            return None;
+        }
+        let (width, height) = ((right - left) as u32, (bottom - top) as u32);
+        Some(Self {
+            window: self.window,
+            ..Self::new(self.source, self.label, left, top, width, height)
+        })
+    }
+}
+
+/// The largest uncovered part of `area`, with `above` the areas stacked over
+/// it; all are (x, y, width, height)
+///
+/// Only rectangles are kept, so the part found is the largest of the pieces
+/// the covering areas leave, not necessarily the largest rectangle visible.
+pub fn visible_part(
+    area: (i32, i32, u32, u32),
+    above: &[(i32, i32, u32, u32)],
+) -> Option<(i32, i32, u32, u32)> {
+    let edges = |(x, y, width, height): (i32, i32, u32, u32)| {
+        (x, y, x + width as i32, y + height as i32)
+    };
+    let mut pieces = vec![edges(area)];
+    for &cover in above {
+        let (cover_left, cover_top, cover_right, cover_bottom) = edges(cover);
+        pieces = pieces
+            .into_iter()
+            .flat_map(|(left, top, right, bottom)| {
+                let overlaps = cover_left < right
+                    && left < cover_right
+                    && cover_top < bottom
+                    && top < cover_bottom;
+                if !overlaps {
+                    return vec![(left, top, right, bottom)];
+                }
+                // Above, below, then beside the covering area
+                let (middle_top, middle_bottom) = (top.max(cover_top), bottom.min(cover_bottom));
+                [
+                    (left, top, right, cover_top),
+                    (left, cover_bottom, right, bottom),
+                    (left, middle_top, cover_left, middle_bottom),
+                    (cover_right, middle_top, right, middle_bottom),
+                ]
+                .into_iter()
+                .filter(|&(left, top, right, bottom)| right > left && bottom > top)
+                .collect()
+            })
+            .collect();
+    }
+    pieces
+        .into_iter()
+        .max_by_key(|&(left, top, right, bottom)| {
+            i64::from(right - left) * i64::from(bottom - top)
+        })
+        .map(|(left, top, right, bottom)| (left, top, (right - left) as u32, (bottom - top) as u32))
+}
+
+/// A coordinate grid over the capture; columns are lettered like a
+/// spreadsheet (`A`..`Z`, `AA`..) and rows numbered from 1
+#[derive(Debug, Clone, Serialize)]
+pub struct Grid {
+    pub cell_size: u32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl Grid {
+    pub fn new(cell_size: u32, width: u32, height: u32) -> Result<Self> {
+        if cell_size < MIN_GRID_CELL {
+            bail!(
+                "Grid cells must be at least {} pixels, got {}",
+                MIN_GRID_CELL,
+                cell_size
+            );
+        }
+        let (columns, rows) = (width.div_ceil(cell_size), height.div_ceil(cell_size));
+        // The last column has the most letters and the last row the most digits
+        let widest = Self::cell_label(columns.saturating_sub(1), rows.saturating_sub(1));
+        let needed = label_size(&widest, 1).0 + 1;
+        if cell_size < needed {
+            bail!(
+                "Grid cells must be at least {} pixels to fit labels such as {}, got {}",
+                needed,
+                widest,
+                cell_size
+            );
+        }
+        Ok(Self {
+            cell_size,
+            columns,
+            rows,
+        })
+    }
+
+    pub fn cell_label(column: u32, row: u32) -> String {
+        format!("{}{}", column_letters(column), row + 1)
+    }
+
+    /// Zero-based column and row of a label such as `C7`
+    fn cell(&self, label: &str) -> Option<(u32, u32)> {
+        let label = label.trim().to_ascii_uppercase();
+        let split = label.find(|c: char| c.is_ascii_digit())?;
+        let (letters, digits) = label.split_at(split);
+        if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_uppercase()) {
+            return None;
+        }
+        let column = letters.bytes().try_fold(0u32, |n, b| {
+            n.checked_mul(26)?.checked_add(u32::from(b - b'A') + 1)
+        })? - 1;
+        let row = digits.parse::<u32>().ok()?.checked_sub(1)?;
+        (column < self.columns && row < self.rows).then_some((column, row))
+    }
+}
+
+/// An annotated screenshot and the table to resolve its labels with
+#[derive(Debug, Clone, Serialize)]
+pub struct Annotation {
+    pub id: String,
+    pub path: PathBuf,
+    pub captured_at: DateTime<Utc>,
+    /// Top-left corner of the capture in screen coordinates
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub marks: Vec<Mark>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub grid: Option<Grid>,
+    /// Mark sources that could not be used, and why
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl Annotation {
+    /// Screen coordinates to click for a mark number or grid cell
+    pub fn resolve(&self, label: &MarkLabel) -> Result<(i32, i32)> {
+        match label {
+            MarkLabel::Number(number) => self.mark(*number),
+            MarkLabel::Label(label) => match label.trim().parse() {
+                Ok(number) => self.mark(number),
+                Err(_) => self.cell(label),
+            },
+        }
+    }
+
+    /// The window behind a mark, for marks that stand for one
+    pub fn window(&self, label: &MarkLabel) -> Option<u32> {
+        let number = match label {
+            MarkLabel::Number(number) => *number,
+            MarkLabel::Label(label) => label.trim().parse().ok()?,
+        };
+        self.marks.iter().find(|m| m.id == number)?.window
+    }
+
+    fn mark(&self, number: u32) -> Result<(i32, i32)> {
+        self.marks
+            .iter()
+            .find(|m| m.id == number)
+            .map(|m| m.center)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Annotation {} has no mark {} (it has {})",
+                    self.id,
+                    number,
+                    self.marks.len()
+                )
+            })
+    }
+
+    fn cell(&self, label: &str) -> Result<(i32, i32)> {
+        let grid = self
+            .grid
+            .as_ref()
+            .ok_or_else(|| anyhow!("Annotation {} has no grid to find {:?} in", self.id, label))?;
+        let (column, row) = grid.cell(label).ok_or_else(|| {
+            anyhow!(
+                "{:?} is not a cell of annotation {} (columns A-{}, rows 1-{})",
+                label,
+                self.id,
+                column_letters(grid.columns - 1),
+                grid.rows
+            )
+        })?;
+        // Cells on the right and bottom edges may be cut short by the capture
+        let left = column * grid.cell_size;
+        let top = row * grid.cell_size;
+        let right = (left + grid.cell_size).min(self.width);
+        let bottom = (top + grid.cell_size).min(self.height);
+        Ok((
+            self.x + ((left + right) / 2) as i32,
+            self.y + ((top + bottom) / 2) as i32,
+        ))
+    }
+}
+
+/// Clip marks to the capture and number them in reading order, so nearby
+/// numbers are nearby on screen
+pub fn number(marks: Vec<Mark>, bounds: (i32, i32, u32, u32)) -> Vec<Mark> {
+    let mut marks: Vec<Mark> = marks
+        .into_iter()
+        .filter_map(|m| m.clipped(bounds))
+        .collect();
+    marks.sort_by_key(|m| (m.y, m.x));
+    for (index, mark) in marks.iter_mut().enumerate() {
+        mark.id = index as u32 + 1;
+    }
+    marks
+}
+
+/// Draw the grid, then the marks over it, onto a capture whose top-left
+/// corner is at `origin` on screen
+pub fn draw(image: &mut RgbaImage, origin: (i32, i32), marks: &[Mark], grid: Option<&Grid>) {
+    if let Some(grid) = grid {
+        draw_grid(image, grid);
+    }
+    // Larger boxes first, so the labels of small ones stay on top
+    let mut order: Vec<&Mark> = marks.iter().collect();
+    order.sort_by_key(|m| std::cmp::Reverse(m.width * m.height));
+    for mark in order {
+        let color = PALETTE[mark.id as usize % PALETTE.len()];
+        let (x, y) = (mark.x - origin.0, mark.y - origin.1);
+        for inset in 0..2 {
+            if mark.width > 2 * inset && mark.height > 2 * inset {
+                let rect = Rect::at(x + inset as i32, y + inset as i32)
+                    .of_size(mark.width - 2 * inset, mark.height - 2 * inset);
+                draw_hollow_rect_mut(image, rect, color);
+            }
+        }
+        let label = mark.id.to_string();
+        let (width, height) = label_size(&label, 2);
+        // Above the box when there is room, otherwise just inside it
+        let top = if y >= height as i32 {
+            y - height as i32
+        } else {
+            y
+        };
+        // Kept on screen for boxes clipped at the right edge
+        let left = x.min(image.width() as i32 - width as i32).max(0);
+        draw_label(image, left, top, &label, 2, color, WHITE);
+    }
+}
+
+fn draw_grid(image: &mut RgbaImage, grid: &Grid) {
+    let (width, height) = image.dimensions();
+    for column in 1..grid.columns {
+        let x = column * grid.cell_size;
+        for y in 0..height {
+            blend(image, x, y, GRID_COLOR, GRID_OPACITY);
+        }
+    }
+    for row in 1..grid.rows {
+        let y = row * grid.cell_size;
+        for x in 0..width {
+            blend(image, x, y, GRID_COLOR, GRID_OPACITY);
+        }
+    }
+    for row in 0..grid.rows {
+        for column in 0..grid.columns {
+            let label = Grid::cell_label(column, row);
+            let (x, y) = (
+                (column * grid.cell_size) as i32 + 1,
+                (row * grid.cell_size) as i32 + 1,
+            );
+            draw_label(image, x, y, &label, 1, BLACK, WHITE);
+        }
+    }
+}
+
+fn blend(image: &mut RgbaImage, x: u32, y: u32, color: Rgba<u8>, opacity: f32) {
+    let pixel = image.get_pixel_mut(x, y);
+    for channel in 0..3 {
+        let mixed = pixel[channel] as f32 * (1.0 - opacity) + color[channel] as f32 * opacity;
+        pixel[channel] = mixed.round() as u8;
+    }
+}
+
+/// Spreadsheet-style letters for a zero-based column: A..Z, AA..
+fn column_letters(column: u32) -> String {
+    let mut letters = Vec::new();
+    let mut n = column + 1;
+    while n > 0 {
+        n -= 1;
+        letters.push(b'A' + (n % 26) as u8);
+        n /= 26;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap_or_default()
+}
+
+/// Glyph cells are this many font pixels wide and tall, including spacing
+const GLYPH_WIDTH: u32 = 6;
+const GLYPH_HEIGHT: u32 = 8;
+/// Background padding around label text, in font pixels
+const LABEL_PADDING: u32 = 1;
+
+fn label_size(text: &str, scale: u32) -> (u32, u32) {
+    let characters = text.chars().count() as u32;
+    (
+        (characters * GLYPH_WIDTH + 2 * LABEL_PADDING) * scale,
+        (GLYPH_HEIGHT + 2 * LABEL_PADDING) * scale,
+    )
+}
+
+/// Draw `text` (digits and capital letters) on a filled background
+fn draw_label(
+    image: &mut RgbaImage,
+    x: i32,
+    y: i32,
+    text: &str,
+    scale: u32,
+    background: Rgba<u8>,
+    foreground: Rgba<u8>,
+) {
+    let (width, height) = label_size(text, scale);
+    draw_filled_rect_mut(image, Rect::at(x, y).of_size(width, height), background);
+    let (image_width, image_height) = image.dimensions();
+    for (index, c) in text.chars().enumerate() {
+        let Some(rows) = glyph(c) else {
+            continue;
+        };
+        let left = x + ((LABEL_PADDING + index as u32 * GLYPH_WIDTH) * scale) as i32;
+        let top = y + (LABEL_PADDING * scale) as i32;
+        for (row, bits) in rows.iter().enumerate() {
+            for column in 0..5 {
+                if bits & (0x10 >> column) == 0 {
+                    continue;
+                }
+                for dy in 0..scale {
+                    for dx in 0..scale {
+                        let px = left + (column * scale + dx) as i32;
+                        let py = top + (row as u32 * scale + dy) as i32;
+                        if px >= 0
+                            && py >= 0
+                            && (px as u32) < image_width
+                            && (py as u32) < image_height
+                        {
+                            image.put_pixel(px as u32, py as u32, foreground);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 5x7 bitmap of a digit or capital letter, one row per byte, high bit left
+fn glyph(c: char) -> Option<[u8; 7]> {
+    Some(match c {
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1C, 0x12, 0x11, 0x11, 0x11, 0x12, 0x1C],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn annotation(marks: Vec<Mark>, grid: Option<Grid>) -> Annotation {
+        Annotation {
+            id: "test".to_string(),
+            path: PathBuf::from("test.png"),
+            captured_at: Utc::now(),
+            x: 100,
+            y: 50,
+            width: 100,
+            height: 70,
+            marks: number(marks, (100, 50, 100, 70)),
+            grid,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn cells_are_found_by_letters_and_row() {
+        let grid = Grid::new(MIN_GRID_CELL, 1920, 1080).unwrap();
+        assert_eq!((grid.columns, grid.rows), (69, 39));
+        assert_eq!(grid.cell("A1"), Some((0, 0)));
+        assert_eq!(grid.cell(" c7 "), Some((2, 6)));
+        assert_eq!(grid.cell("AA1"), Some((26, 0)));
+        assert_eq!(grid.cell("BQ39"), Some((68, 38)));
+        assert_eq!(Grid::cell_label(68, 38), "BQ39");
+        for label in ["BR1", "A40", "A0", "7C", "A", "A1B", "Ä1", ""] {
+            assert_eq!(grid.cell(label), None, "{:?}", label);
+        }
+    }
+
+    #[test]
+    fn grid_cells_must_fit_their_labels() {
+        assert!(Grid::new(MIN_GRID_CELL - 1, 100, 100).is_err());
+        // 27 columns and 100 rows need labels such as AA100
+        assert!(Grid::new(MIN_GRID_CELL, 27 * MIN_GRID_CELL, 100 * MIN_GRID_CELL).is_err());
+        let grid = Grid::new(33, 27 * MIN_GRID_CELL, 100 * MIN_GRID_CELL).unwrap();
+        assert_eq!((grid.columns, grid.rows), (23, 85));
+    }
+
+    #[test]
+    fn visible_part_is_the_largest_uncovered_piece() {
+        let area = (0, 0, 100, 100);
+        assert_eq!(visible_part(area, &[]), Some(area));
+        assert_eq!(visible_part(area, &[(200, 0, 50, 50)]), Some(area));
+        assert_eq!(visible_part(area, &[(-10, -10, 120, 120)]), None);
+        assert_eq!(visible_part(area, &[(0, 0, 40, 100)]), Some((40, 0, 60, 100)));
+        assert_eq!(visible_part(area, &[(10, 30, 80, 20)]), Some((0, 50, 100, 50)));
+        // Each cover cuts into what the ones before it left
+        assert_eq!(
+            visible_part(area, &[(10, 30, 80, 20), (0, 70, 100, 30)]),
+            Some((0, 0, 100, 30))
+        );
+    }
+
+    #[test]
+    fn marks_resolve_to_their_centres() {
+        let window = Mark {
+            window: Some(7),
+            ..Mark::new(MarkSource::Windows, "Editor".to_string(), 150, 55, 30, 20)
+        };
+        let text = Mark::new(MarkSource::Text, "OK".to_string(), 110, 60, 20, 10);
+        let annotation = annotation(vec![text, window], None);
+        // Numbered top to bottom
+        assert_eq!(annotation.resolve(&MarkLabel::Number(1)).unwrap(), (165, 65));
+        assert_eq!(annotation.resolve(&MarkLabel::Label(" 2 ".to_string())).unwrap(), (120, 65));
+        assert!(annotation.resolve(&MarkLabel::Number(3)).is_err());
+        assert!(annotation.resolve(&MarkLabel::Label("A1".to_string())).is_err());
+        assert_eq!(annotation.window(&MarkLabel::Number(1)), Some(7));
+        assert_eq!(annotation.window(&MarkLabel::Number(2)), None);
+    }
+
+    #[test]
+    fn cells_resolve_to_their_visible_centres() {
+        let grid = Grid::new(MIN_GRID_CELL, 100, 70).unwrap();
+        let annotation = annotation(Vec::new(), Some(grid));
+        let resolve = |label: &str| annotation.resolve(&MarkLabel::Label(label.to_string()));
+        assert_eq!(resolve("A1").unwrap(), (114, 64));
+        assert_eq!(resolve("B2").unwrap(), (142, 92));
+        // The last column and row are cut short by the capture
+        assert_eq!(resolve("D3").unwrap(), (192, 113));
+        assert!(resolve("E1").is_err());
+        assert!(annotation.window(&MarkLabel::Label("B2".to_string())).is_none());
+    }
+}
@@ -1,4 +1,15 @@
+pub mod accessibility;
+pub mod capture;
+pub mod diff;
+pub mod marks;
+pub mod ocr;
 pub mod system;
+pub mod template;
 
-pub use system::VisionSystem;
-
+pub use accessibility::{A11yNode, A11yOperation};
+pub use capture::Frame;
+pub use diff::{ChangedRegion, ScreenDiff};
+pub use marks::{Annotation, Mark};
+pub use ocr::{OcrEngine, OcrLine, OcrMatch, OcrResult, OcrWord};
+pub use system::{Screenshot, VisionSystem};
+pub use template::TemplateMatch;
@@ -0,0 +1,374 @@
+use crate::action::simulate::resolve_command;
+use crate::core::config::OcrConfig;
+use anyhow::{bail, Context, Result};
+use image::{ImageFormat, RgbaImage};
+use serde::Serialize;
+use std::io::{Cursor, Write};
+use std::process::{Command, Stdio};
+
+/// A recognized word; coordinates are relative to the recognized image until
+/// [`OcrResult::offset`] moves them into screen space
+#[derive(Debug, Clone, Serialize)]
+pub struct OcrWord {
+    pub text: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// 0-100
+    pub confidence: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OcrLine {
+    pub text: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Mean confidence of the words
+    pub confidence: f32,
+    pub words: Vec<OcrWord>,
+}
+
+impl OcrLine {
+    /// A line made of `words`, which must not be empty
+    pub fn new(words: Vec<OcrWord>) -> Self {
+        let (x, y, width, height) = bounds(&words);
+        let text = words.iter().map(|w| w.text.as_str()).collect::<Vec<_>>().join(" ");
+        let confidence = words.iter().map(|w| w.confidence).sum::<f32>() / words.len().max(1) as f32;
+        Self {
+            text,
+            x,
+            y,
+            width,
+            height,
+            confidence,
+            words,
+        }
+    }
+}
+
+/// Where some text was found, with the point to click it at
+#[derive(Debug, Clone, Serialize)]
+pub struct OcrMatch {
+    pub text: String,
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    pub confidence: f32,
+    pub center: (i32, i32),
+}
+
+/// Everything recognized in one image, line by line in reading order
+#[derive(Debug, Clone, Serialize)]
+pub struct OcrResult {
+    pub engine: String,
+    pub text: String,
+    pub lines: Vec<OcrLine>,
+}
+
+impl OcrResult {
+    pub fn new(engine: &str, lines: Vec<OcrLine>) -> Self {
+        let text = lines.iter().map(|l| l.text.as_str()).collect::<Vec<_>>().join("\n");
+        Self {
+            engine: engine.to_string(),
+            text,
+            lines,
+        }
+    }
+
+    /// Shift every box by `(dx, dy)`, e.g. from capture to screen coordinates
+    pub fn offset(mut self, dx: i32, dy: i32) -> Self {
+        for line in &mut self.lines {
+            line.x += dx;
+            line.y += dy;
+            for word in &mut line.words {
+                word.x += dx;
+                word.y += dy;
+            }
+        }
+        self
+    }
+
+    /// Drop words below `min_confidence`, and lines left without words
+    pub fn with_min_confidence(self, min_confidence: f32) -> Self {
+        if min_confidence <= 0.0 {
+            return self;
+        }
+        let lines = self
+            .lines
+            .into_iter()
+            .filter_map(|line| {
+                let words: Vec<_> = line
+                    .words
+                    .into_iter()
+                    .filter(|w| w.confidence >= min_confidence)
+                    .collect();
+                (!words.is_empty()).then(|| OcrLine::new(words))
+            })
+            .collect();
+        Self::new(&self.engine, lines)
+    }
+
+    pub fn word_count(&self) -> usize {
+        self.lines.iter().map(|l| l.words.len()).sum()
+    }
+
+    /// Every place `text` occurs, in reading order
+    ///
+    /// `text` is split into words that must appear consecutively on one line;
+    /// each is matched as a substring of the recognized word, so `Save`
+    /// finds `Save...` and `"Save As"` finds the two words together.
+    pub fn find(&self, text: &str, case_sensitive: bool) -> Vec<OcrMatch> {
+        let normalize = |s: &str| {
+            if case_sensitive {
+                s.to_string()
+            } else {
+                s.to_lowercase()
+            }
+        };
+        let wanted: Vec<String> = text.split_whitespace().map(normalize).collect();
+        if wanted.is_empty() {
+            return Vec::new();
+        }
+
+        let mut matches = Vec::new();
+        for line in &self.lines {
+            let words: Vec<String> = line.words.iter().map(|w| normalize(&w.text)).collect();
+            for start in 0..words.len().saturating_sub(wanted.len() - 1) {
+                let run = &words[start..start + wanted.len()];
+                let found = run.iter().zip(&wanted).enumerate().all(|(i, (word, part))| {
+                    // Inner words of a phrase must match whole; the ends may be partial
+                    if wanted.len() == 1 {
+                        word.contains(part.as_str())
+                    } else if i == 0 {
+                        word.ends_with(part.as_str())
+                    } else if i == wanted.len() - 1 {
+                        word.starts_with(part.as_str())
+                    } else {
+                        word == part
+                    }
+                });
+                if !found {
+                    continue;
+                }
+                let hit = &line.words[start..start + wanted.len()];
+                let (x, y, width, height) = bounds(hit);
+                matches.push(OcrMatch {
+                    text: hit.iter().map(|w| w.text.as_str()).collect::<Vec<_>>().join(" "),
+                    x,
+                    y,
+                    width,
+                    height,
+                    confidence: hit.iter().map(|w| w.confidence).fold(f32::MAX, f32::min),
+                    center: (x + width as i32 / 2, y + height as i32 / 2),
+                });
+            }
+        }
+        matches
+    }
+}
+
+/// An OCR backend
+pub trait OcrEngine: Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Whether the engine can run here, e.g. its binary is installed
+    fn available(&self) -> bool;
+
+    /// Lines of words in `image`, in reading order, with image-relative boxes
+    fn recognize(&self, image: &RgbaImage) -> Result<Vec<OcrLine>>;
+}
+
+/// The engine selected by the config
+pub fn engine(config: &OcrConfig) -> Box<dyn OcrEngine> {
+    match config {
+        OcrConfig::Tesseract { command, language, psm } => Box::new(Tesseract {
+            command: command.clone(),
+            language: language.clone(),
+            psm: *psm,
+        }),
+    }
+}
+
+/// The `tesseract` command-line tool, read through its TSV output
+pub struct Tesseract {
+    pub command: String,
+    /// Language pack(s), e.g. `eng` or `eng+deu`
+    pub language: String,
+    /// Page segmentation mode; tesseract's default when `None`
+    pub psm: Option<u8>,
+}
+
+impl OcrEngine for Tesseract {
+    fn name(&self) -> &str {
+        "tesseract"
+    }
+
+    fn available(&self) -> bool {
+        resolve_command(&self.command).is_some()
+    }
+
+    fn recognize(&self, image: &RgbaImage) -> Result<Vec<OcrLine>> {
+        // Piped in, so the screen contents never touch the filesystem
+        let mut png = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .context("Failed to encode the image for tesseract")?;
+        let mut command = Command::new(&self.command);
+        command.args(["stdin", "stdout", "-l", &self.language]);
+        if let Some(psm) = self.psm {
+            command.args(["--psm", &psm.to_string()]);
+        }
+        let output = command
+            .arg("tsv")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .and_then(|mut child| {
+                let mut stdin = child.stdin.take().expect("stdin is piped");
+                // Written from another thread so a full stdout pipe can't deadlock it
+                let writer = std::thread::spawn(move || stdin.write_all(&png));
+                let output = child.wait_with_output();
+                // tesseract may stop reading early; its exit status tells what went wrong
+                let _ = writer.join();
+                output
+            });
+
+        let output = output.with_context(|| format!("Failed to run {} (is it installed?)", self.command))?;
+        if !output.status.success() {
+            bail!(
+                "tesseract failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(parse_tsv(&String::from_utf8_lossy(&output.stdout)))
+    }
+}
+
+/// Page, block, paragraph and line number of a TSV row
+type LineKey = (u32, u32, u32, u32);
+
+/// Group the word rows (level 5) of tesseract's TSV output into lines
+///
+/// Columns: level, page, block, paragraph, line, word, left, top, width,
+/// height, confidence, text.
+fn parse_tsv(tsv: &str) -> Vec<OcrLine> {
+    let mut lines = Vec::new();
+    let mut current: Option<(LineKey, Vec<OcrWord>)> = None;
+    for row in tsv.lines().skip(1) {
+        let fields: Vec<&str> = row.splitn(12, '\t').collect();
+        let [level, page, block, paragraph, line, _, left, top, width, height, confidence, text] =
+            fields[..]
+        else {
+            continue;
+        };
+        let text = text.trim();
+        let confidence: f32 = confidence.parse().unwrap_or(-1.0);
+        if level != "5" || text.is_empty() || confidence < 0.0 {
+            continue;
+        }
+        let number = |s: &str| s.parse::<u32>().unwrap_or(0);
+        let key = (number(page), number(block), number(paragraph), number(line));
+        let word = OcrWord {
+            text: text.to_string(),
+            x: left.parse().unwrap_or(0),
+            y: top.parse().unwrap_or(0),
+            width: number(width),
+            height: number(height),
+            confidence,
+        };
+        match current.as_mut() {
+            Some((current_key, words)) if *current_key == key => words.push(word),
+            _ => {
+                if let Some((_, words)) = current.replace((key, vec![word])) {
+                    lines.push(OcrLine::new(words));
+                }
+            }
+        }
+    }
+    if let Some((_, words)) = current {
+        lines.push(OcrLine::new(words));
+    }
+    lines
+}
+
+/// Bounding box `(x, y, width, height)` around some words
+fn bounds(words: &[OcrWord]) -> (i32, i32, u32, u32) {
+    let Some(first) = words.first() else {
+        return (0, 0, 0, 0);
+    };
+    let (mut left, mut top) = (first.x, first.y);
+    let (mut right, mut bottom) = (first.x + first.width as i32, first.y + first.height as i32);
+    for word in &words[1..] {
+        left = left.min(word.x);
+        top = top.min(word.y);
+        right = right.max(word.x + word.width as i32);
+        bottom = bottom.max(word.y + word.height as i32);
+    }
+    (left, top, (right - left) as u32, (bottom - top) as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tesseract TSV for "Save As" and "Cancel" on two lines, with the page,
+    /// block, paragraph and line rows (confidence -1) tesseract emits
+    const TSV: &str = "\
+level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t640\t480\t-1\t
+2\t1\t1\t0\t0\t0\t10\t20\t200\t60\t-1\t
+3\t1\t1\t1\t0\t0\t10\t20\t200\t60\t-1\t
+4\t1\t1\t1\t1\t0\t10\t20\t90\t20\t-1\t
+5\t1\t1\t1\t1\t1\t10\t20\t40\t20\t96.5\tSave
+5\t1\t1\t1\t1\t2\t60\t22\t40\t18\t91\tAs
+4\t1\t1\t1\t2\t0\t10\t60\t60\t20\t-1\t
+5\t1\t1\t1\t2\t1\t10\t60\t60\t20\t88\tCancel
+5\t1\t1\t1\t2\t2\t80\t60\t5\t20\t-1\t 
+";
+
+    fn result() -> OcrResult {
+        OcrResult::new("tesseract", parse_tsv(TSV))
+    }
+
+    #[test]
+    fn words_are_grouped_into_lines() {
+        let lines = parse_tsv(TSV);
+        let texts: Vec<_> = lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, ["Save As", "Cancel"]);
+        let save_as = &lines[0];
+        assert_eq!((save_as.x, save_as.y, save_as.width, save_as.height), (10, 20, 90, 20));
+        assert!((save_as.confidence - 93.75).abs() < 1e-3);
+        assert_eq!(result().text, "Save As\nCancel");
+    }
+
+    #[test]
+    fn header_and_unrecognized_rows_are_skipped() {
+        assert!(parse_tsv("level\tpage_num\n").is_empty());
+        assert!(parse_tsv("").is_empty());
+        // The first row is the header and is never read as a word
+        let only_header = "5\t1\t1\t1\t1\t1\t0\t0\t1\t1\t90\tword\n";
+        assert!(parse_tsv(only_header).is_empty());
+        assert_eq!(result().word_count(), 3);
+    }
+
+    #[test]
+    fn find_matches_phrases_across_words() {
+        let found = result().find("save as", false);
+        assert_eq!(found.len(), 1);
+        let hit = &found[0];
+        assert_eq!(hit.text, "Save As");
+        assert_eq!((hit.x, hit.y, hit.width, hit.height), (10, 20, 90, 20));
+        assert_eq!(hit.center, (55, 30));
+        assert_eq!(hit.confidence, 91.0);
+
+        assert!(result().find("save as", true).is_empty());
+        assert_eq!(result().find("anc", false)[0].text, "Cancel");
+        assert!(result().find("As Cancel", false).is_empty());
+        assert!(result().find("  ", false).is_empty());
+    }
+}
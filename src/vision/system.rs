@@ -1,37 +1,555 @@
-use anyhow::Result;
+use crate::action::schema::{A11yQuery, AnnotateScreenParams, MarkSource, ScreenshotParams};
+use crate::core::config::{ScreenMonitorSpec, VisionConfig};
+use crate::core::paths;
+use crate::action::schema::ScreenRegion;
+use crate::event::{Event, EventSystem};
+use crate::vision::capture;
+use crate::state::{WindowInfo, WindowSystem};
+use crate::vision::accessibility::{self, A11yNode, A11yOperation};
+use crate::vision::diff::{self, ScreenDiff};
+use crate::vision::marks::{self, Annotation, Grid, Mark};
+use crate::vision::ocr::{self, OcrEngine, OcrResult};
+use crate::vision::template::{self, TemplateMatch};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use serde_json::Value;
-use tracing::info;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+/// Lower bound on the monitoring interval; each pass captures and compares
+/// the whole area
+const MIN_MONITOR_INTERVAL_MS: u64 = 100;
+/// Annotated screenshots whose marks `click_mark` can still resolve
+const MAX_ANNOTATIONS: usize = 16;
+
+/// A screenshot written to disk
+#[derive(Debug, Clone, Serialize)]
+pub struct Screenshot {
+    pub path: PathBuf,
+    /// Top-left corner of the capture in screen coordinates
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Pointer position relative to the capture, when it falls inside it
+    pub cursor: Option<(i32, i32)>,
+    pub captured_at: DateTime<Utc>,
+}
+
+struct ScreenMonitor {
+    /// Tells a restarted monitor from the one it replaced
+    id: uuid::Uuid,
+    spec: ScreenMonitorSpec,
+    started_at: DateTime<Utc>,
+    task: JoinHandle<()>,
+}
 
 pub struct VisionSystem {
-    // TODO: Add vision processing capabilities
+    screenshot_dir: PathBuf,
+    /// `None` when the `ocr` feature is off
+    ocr: Option<Arc<dyn OcrEngine>>,
+    events: Arc<EventSystem>,
+    /// Shared with the monitor task, which clears it if it ends on its own
+    monitor: Arc<Mutex<Option<ScreenMonitor>>>,
+    /// Most recent last
+    annotations: Mutex<VecDeque<Arc<Annotation>>>,
 }
 
 impl VisionSystem {
-    pub async fn new() -> Result<Self> {
+    pub async fn new(
+        config: &VisionConfig,
+        ocr_enabled: bool,
+        events: Arc<EventSystem>,
+    ) -> Result<Self> {
         info!("Initializing Vision System");
-        Ok(Self {})
+        let screenshot_dir = if config.screenshot_dir.is_empty() {
+            paths::get_screenshots_dir()
+        } else {
+            paths::expand_home(&config.screenshot_dir)
+        };
+        info!("Screenshots will be saved to: {:?}", screenshot_dir);
+        let ocr: Option<Arc<dyn OcrEngine>> = ocr_enabled.then(|| Arc::from(ocr::engine(&config.ocr)));
+        let vision = Self {
+            screenshot_dir,
+            ocr,
+            events,
+            monitor: Arc::new(Mutex::new(None)),
+            annotations: Mutex::new(VecDeque::new()),
+        };
+        if let Some(spec) = &config.monitor {
+            if let Err(e) = vision.start_monitoring(spec.clone()) {
+                warn!("Screen monitoring not started: {:#}", e);
+            }
+        }
+        Ok(vision)
+    }
+
+    pub fn screenshot_dir(&self) -> &Path {
+        &self.screenshot_dir
     }
 
-    pub async fn capture_screen(&self, show_cursor: bool) -> Result<String> {
-        // TODO: Implement screenshot with cursor reticle
-        let timestamp = chrono::Utc::now().format("%Y%m%d_%H%M%S");
-        Ok(format!("screenshot_{}.png", timestamp))
+    /// Capture the screen and save it as a PNG under the screenshot directory
+    pub async fn capture_screen(&self, params: &ScreenshotParams) -> Result<Screenshot> {
+        let params = params.clone();
+        let dir = self.screenshot_dir.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut frame = capture::grab(params.monitor, params.region)?;
+            if params.show_cursor {
+                frame.draw_cursor_reticle();
+            }
+
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            let captured_at = Utc::now();
+            let path = dir.join(format!(
+                "screenshot_{}.png",
+                captured_at.format("%Y%m%d_%H%M%S_%3f")
+            ));
+            frame
+                .image
+                .save(&path)
+                .with_context(|| format!("Failed to save {}", path.display()))?;
+
+            Ok(Screenshot {
+                path,
+                x: frame.origin.0,
+                y: frame.origin.1,
+                width: frame.image.width(),
+                height: frame.image.height(),
+                cursor: frame.cursor_in_frame(),
+                captured_at,
+            })
+        })
+        .await?
     }
 
+    /// The configured OCR engine, or an error when OCR is turned off
+    pub fn ocr_engine(&self) -> Result<Arc<dyn OcrEngine>> {
+        self.ocr
+            .clone()
+            .ok_or_else(|| anyhow!("OCR is disabled (features.ocr)"))
+    }
+
+    /// Recognize the text on screen, with boxes in screen coordinates
+    pub async fn recognize_text(
+        &self,
+        monitor: Option<usize>,
+        region: Option<ScreenRegion>,
+        min_confidence: f32,
+    ) -> Result<OcrResult> {
+        let engine = self.ocr_engine()?;
+        tokio::task::spawn_blocking(move || {
+            let frame = capture::grab(monitor, region)?;
+            let lines = engine.recognize(&frame.image)?;
+            Ok(OcrResult::new(engine.name(), lines)
+                .offset(frame.origin.0, frame.origin.1)
+                .with_min_confidence(min_confidence))
+        })
+        .await?
+    }
+
+    /// Read the whole screen; with a `query`, also where that text appears
     pub async fn analyze_screen(&self, query: Option<&str>) -> Result<Value> {
-        // TODO: Implement AI vision analysis
+        let result = self.recognize_text(None, None, 0.0).await?;
+        let matches = query.map(|q| result.find(q, false));
         Ok(serde_json::json!({
-            "objects": [],
-            "analysis": "Vision analysis not yet implemented"
+            "text": result.text,
+            "lines": result.lines,
+            "query": query,
+            "matches": matches,
         }))
     }
 
+    /// Every place the image at `needle` appears on screen, best first, with
+    /// boxes in screen coordinates
+    ///
+    /// The image is searched at each of `scales` (1.0 is its own size) and
+    /// matched in grayscale, so small color differences don't matter.
+    pub async fn find_template(
+        &self,
+        needle: &str,
+        monitor: Option<usize>,
+        region: Option<ScreenRegion>,
+        threshold: f32,
+        scales: &[f32],
+    ) -> Result<Vec<TemplateMatch>> {
+        let needle = paths::expand_home(needle);
+        let scales = scales.to_vec();
+        tokio::task::spawn_blocking(move || {
+            let needle = template::load(&needle)?;
+            let frame = capture::grab(monitor, region)?;
+            let haystack = image::DynamicImage::ImageRgba8(frame.image).into_luma8();
+            let mut matches = template::find(&haystack, &needle, threshold, &scales);
+            template::offset(&mut matches, frame.origin.0, frame.origin.1);
+            Ok(matches)
+        })
+        .await?
+    }
+
+    /// Capture the screen with numbered marks over UI elements and/or a
+    /// labelled grid drawn on it, saved under the screenshot directory
+    ///
+    /// The annotation is kept so that `click_mark` can later turn "mark 17"
+    /// or "cell C7" back into screen coordinates. A mark source that can't be
+    /// used here, such as text with OCR turned off, is skipped with a warning.
+    pub async fn annotate(&self, params: &AnnotateScreenParams) -> Result<Arc<Annotation>> {
+        let params = params.clone();
+        let dir = self.screenshot_dir.clone();
+        let ocr = if params.marks.contains(&MarkSource::Text) {
+            Some(self.ocr_engine())
+        } else {
+            None
+        };
+        // The accessibility bus is async, so its nodes are fetched up front
+        let a11y = if params.marks.contains(&MarkSource::Accessibility) {
+            Some(accessibility::interactive().await)
+        } else {
+            None
+        };
+        let annotation = tokio::task::spawn_blocking(move || {
+            let mut frame = capture::grab(params.monitor, params.region)?;
+            let (width, height) = frame.image.dimensions();
+            let grid = params
+                .grid
+                .map(|cell_size| Grid::new(cell_size, width, height))
+                .transpose()?;
+
+            let mut candidates = Vec::new();
+            let mut warnings = Vec::new();
+            for source in &params.marks {
+                let found = match source {
+                    MarkSource::Windows => window_marks(),
+                    MarkSource::Text => match &ocr {
+                        Some(Ok(engine)) => engine.recognize(&frame.image).map(|lines| {
+                            text_marks(
+                                OcrResult::new(engine.name(), lines)
+                                    .offset(frame.origin.0, frame.origin.1)
+                                    .with_min_confidence(params.min_confidence),
+                            )
+                        }),
+                        Some(Err(e)) => Err(anyhow!("{:#}", e)),
+                        None => Ok(Vec::new()),
+                    },
+                    MarkSource::Accessibility => match &a11y {
+                        Some(Ok(nodes)) => Ok(accessibility_marks(nodes)),
+                        Some(Err(e)) => Err(anyhow!("{:#}", e)),
+                        None => Ok(Vec::new()),
+                    },
+                };
+                match found {
+                    Ok(found) => candidates.extend(found),
+                    Err(e) => warnings.push(format!("{:?} marks skipped: {:#}", source, e)),
+                }
+            }
+            let bounds = (frame.origin.0, frame.origin.1, width, height);
+            let marks = marks::number(candidates, bounds);
+            marks::draw(&mut frame.image, frame.origin, &marks, grid.as_ref());
+
+            std::fs::create_dir_all(&dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+            let captured_at = Utc::now();
+            let path = dir.join(format!(
+                "annotated_{}.png",
+                captured_at.format("%Y%m%d_%H%M%S_%3f")
+            ));
+            frame
+                .image
+                .save(&path)
+                .with_context(|| format!("Failed to save {}", path.display()))?;
+
+            anyhow::Ok(Annotation {
+                id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+                path,
+                captured_at,
+                x: frame.origin.0,
+                y: frame.origin.1,
+                width,
+                height,
+                marks,
+                grid,
+                warnings,
+            })
+        })
+        .await??;
+
+        let annotation = Arc::new(annotation);
+        if let Ok(mut annotations) = self.annotations.lock() {
+            if annotations.len() == MAX_ANNOTATIONS {
+                annotations.pop_front();
+            }
+            annotations.push_back(annotation.clone());
+        }
+        Ok(annotation)
+    }
+
+    /// A recent annotation by id, or the latest one
+    pub fn annotation(&self, id: Option<&str>) -> Result<Arc<Annotation>> {
+        let annotations = self
+            .annotations
+            .lock()
+            .map_err(|_| anyhow!("Annotation lock poisoned"))?;
+        match id {
+            Some(id) => annotations
+                .iter()
+                .find(|a| a.id == id)
+                .cloned()
+                .ok_or_else(|| anyhow!("No recent annotation {}", id)),
+            None => annotations
+                .back()
+                .cloned()
+                .ok_or_else(|| anyhow!("No screenshot has been annotated yet")),
+        }
+    }
+
+    /// The accessibility tree of running applications, optionally only those
+    /// whose name contains `app`, down to `max_depth` levels
+    pub async fn accessibility_tree(
+        &self,
+        app: Option<&str>,
+        max_depth: usize,
+    ) -> Result<Vec<A11yNode>> {
+        accessibility::tree(app, max_depth).await
+    }
+
+    /// Accessibility nodes matching `query`, at most `limit` of them
+    pub async fn find_accessible(&self, query: &A11yQuery, limit: usize) -> Result<Vec<A11yNode>> {
+        accessibility::find(query, limit).await
+    }
+
+    /// Press, fill in or focus the one node matching `target` through the
+    /// accessibility API instead of synthesized input
+    pub async fn act_accessible(
+        &self,
+        target: &A11yQuery,
+        operation: A11yOperation,
+    ) -> Result<A11yNode> {
+        let name = operation.name();
+        let node = accessibility::perform(target, operation).await?;
+        info!("Accessibility {} on {} {:?}", name, node.role, node.name);
+        Ok(node)
+    }
+
+    /// Compare the screenshot at `before` with the one at `after`, or with the
+    /// screen as it is now
+    ///
+    /// Against the live screen, `monitor` and `region` choose what is captured
+    /// (it should match what `before` shows) and regions come back in screen
+    /// coordinates; between two files they are relative to the images.
+    pub async fn diff_screen(
+        &self,
+        before: &str,
+        after: Option<&str>,
+        monitor: Option<usize>,
+        region: Option<ScreenRegion>,
+    ) -> Result<ScreenDiff> {
+        let before = paths::expand_home(before);
+        let after = after.map(paths::expand_home);
+        tokio::task::spawn_blocking(move || {
+            let load = |path: &Path| {
+                image::open(path)
+                    .with_context(|| format!("Failed to load {}", path.display()))
+                    .map(|image| image.to_rgba8())
+            };
+            let before = load(&before)?;
+            Ok(match after {
+                Some(after) => diff::diff(&before, &load(&after)?),
+                None => {
+                    let frame = capture::grab(monitor, region)?;
+                    diff::diff(&before, &frame.image).offset(frame.origin.0, frame.origin.1)
+                }
+            })
+        })
+        .await?
+    }
+
+    /// Start comparing part of the screen with itself, publishing
+    /// `screen_changed` whenever it changed enough since the previous capture
+    pub fn start_monitoring(&self, spec: ScreenMonitorSpec) -> Result<Value> {
+        if !(0.0..=1.0).contains(&spec.threshold) {
+            bail!("Threshold must be between 0 and 1, got {}", spec.threshold);
+        }
+        let mut monitor = self
+            .monitor
+            .lock()
+            .map_err(|_| anyhow!("Screen monitor lock poisoned"))?;
+        if monitor.is_some() {
+            bail!("The screen is already being monitored");
+        }
+        info!(
+            "Monitoring the screen every {} ms for {:.1}% change",
+            spec.interval_ms,
+            spec.threshold * 100.0
+        );
+        let started_at = Utc::now();
+        let status = serde_json::json!({"monitoring": spec, "started_at": started_at});
+        let id = uuid::Uuid::new_v4();
+        let (slot, events, task_spec) = (self.monitor.clone(), self.events.clone(), spec.clone());
+        let task = tokio::spawn(async move {
+            monitor_screen(task_spec, events).await;
+            // Only reached when a capture or diff task failed; stopping aborts
+            // the task before this, and a newer monitor must stay
+            if let Ok(mut monitor) = slot.lock() {
+                if monitor.as_ref().is_some_and(|m| m.id == id) {
+                    warn!("Screen monitor stopped unexpectedly");
+                    *monitor = None;
+                }
+            }
+        });
+        *monitor = Some(ScreenMonitor {
+            id,
+            spec,
+            started_at,
+            task,
+        });
+        Ok(status)
+    }
+
+    /// The monitoring in progress, if any
+    pub fn monitoring(&self) -> Option<Value> {
+        let monitor = self.monitor.lock().ok()?;
+        monitor
+            .as_ref()
+            .map(|m| serde_json::json!({"monitoring": m.spec, "started_at": m.started_at}))
+    }
+
+    pub fn stop_monitoring(&self) -> Result<ScreenMonitorSpec> {
+        let monitor = self
+            .monitor
+            .lock()
+            .map_err(|_| anyhow!("Screen monitor lock poisoned"))?
+            .take()
+            .ok_or_else(|| anyhow!("The screen is not being monitored"))?;
+        monitor.task.abort();
+        info!("Stopped monitoring the screen");
+        Ok(monitor.spec)
+    }
+
     pub fn get_capabilities(&self) -> Value {
         serde_json::json!({
             "screenshot": true,
-            "ocr": false,
+            "monitors": capture::monitors().unwrap_or_default(),
+            "ocr": self.ocr.as_ref().is_some_and(|engine| engine.available()),
+            "ocr_engine": self.ocr.as_ref().map(|engine| engine.name()),
+            "template_matching": true,
+            "annotation": true,
+            "accessibility": cfg!(target_os = "linux"),
             "object_detection": false
         })
     }
 }
 
+/// A mark per visible window, labelled with its title
+///
+/// Each mark covers the largest part of its window that no window stacked
+/// above hides, so its centre lands on the window itself.
+fn window_marks() -> Result<Vec<Mark>> {
+    // Bottom to top, so the windows after each one are those above it
+    let windows: Vec<WindowInfo> = WindowSystem::list()?
+        .into_iter()
+        .filter(|w| !w.minimized)
+        .collect();
+    let areas: Vec<_> = windows.iter().map(|w| (w.x, w.y, w.width, w.height)).collect();
+    Ok(windows
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, w)| {
+            let (x, y, width, height) = marks::visible_part(areas[index], &areas[index + 1..])?;
+            Some(Mark {
+                window: Some(w.id),
+                ..Mark::new(MarkSource::Windows, w.title, x, y, width, height)
+            })
+        })
+        .collect())
+}
+
+/// A mark per line of recognized text
+fn text_marks(result: OcrResult) -> Vec<Mark> {
+    result
+        .lines
+        .into_iter()
+        .map(|l| Mark::new(MarkSource::Text, l.text, l.x, l.y, l.width, l.height))
+        .collect()
+}
+
+/// A mark per interactive accessibility node, labelled with its role and name
+fn accessibility_marks(nodes: &[A11yNode]) -> Vec<Mark> {
+    nodes
+        .iter()
+        .filter_map(|node| {
+            let bounds = node.bounds.as_ref()?;
+            let label = if node.name.is_empty() {
+                node.role.clone()
+            } else {
+                format!("{} {}", node.role, node.name)
+            };
+            Some(Mark::new(
+                MarkSource::Accessibility,
+                label,
+                bounds.x,
+                bounds.y,
+                bounds.width,
+                bounds.height,
+            ))
+        })
+        .collect()
+}
+
+/// Capture the monitored area every interval and publish what changed since
+/// the previous capture
+async fn monitor_screen(spec: ScreenMonitorSpec, events: Arc<EventSystem>) {
+    let mut interval = tokio::time::interval(Duration::from_millis(
+        spec.interval_ms.max(MIN_MONITOR_INTERVAL_MS),
+    ));
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    let mut previous = None;
+    let mut failing = false;
+    loop {
+        interval.tick().await;
+        let (monitor, region) = (spec.monitor, spec.region);
+        let frame = match tokio::task::spawn_blocking(move || capture::grab(monitor, region)).await {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(e)) => {
+                // Report a failing capture once, not every interval
+                if !failing {
+                    warn!("Screen monitor capture failed: {:#}", e);
+                    failing = true;
+                }
+                continue;
+            }
+            Err(_) => return,
+        };
+        failing = false;
+
+        let Some(before) = previous.replace(Arc::new(frame.image)) else {
+            continue;
+        };
+        let Some(after) = previous.clone() else {
+            continue;
+        };
+        let Ok(changes) = tokio::task::spawn_blocking(move || diff::diff(&before, &after)).await
+        else {
+            return;
+        };
+        if changes.changed_fraction < spec.threshold || changes.changed_pixels == 0 {
+            continue;
+        }
+        debug!(
+            "Screen changed: {:.2}% of pixels in {} region(s)",
+            changes.changed_fraction * 100.0,
+            changes.regions.len()
+        );
+        let changes = changes.offset(frame.origin.0, frame.origin.1);
+        events.publish(Event::ScreenChanged {
+            timestamp: Utc::now(),
+            monitor: spec.monitor,
+            changed_fraction: changes.changed_fraction,
+            perceptual_difference: changes.perceptual_difference,
+            regions: changes.regions,
+        });
+    }
+}
@@ -0,0 +1,320 @@
+use anyhow::{bail, Context, Result};
+use image::imageops::{self, FilterType};
+use image::{GrayImage, Luma};
+use imageproc::integral_image::{integral_image, integral_squared_image, sum_image_pixels};
+use imageproc::template_matching::{match_template, MatchTemplateMethod};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Smallest side a template is shrunk to for the coarse pass
+const COARSE_MIN_SIDE: u32 = 8;
+/// Largest factor the coarse pass shrinks by
+const MAX_COARSE_FACTOR: u32 = 8;
+/// How far below the threshold a coarse score may be and still get refined;
+/// shrinking blurs detail, so true matches score lower there
+const COARSE_SLACK: f32 = 0.15;
+/// Coarse candidates refined per scale, best first
+const MAX_CANDIDATES: usize = 64;
+/// Templates scaled below this many pixels on a side are skipped
+const MIN_TEMPLATE_SIDE: u32 = 4;
+/// Matches overlapping a better one by more than this fraction of the
+/// smaller box are dropped
+const MAX_OVERLAP: f32 = 0.5;
+
+/// Where a template was found; coordinates are relative to the searched image
+/// until [`offset`] moves them into screen space
+#[derive(Debug, Clone, Serialize)]
+pub struct TemplateMatch {
+    pub x: i32,
+    pub y: i32,
+    pub width: u32,
+    pub height: u32,
+    /// Zero-mean normalized cross-correlation, -1 to 1
+    pub score: f32,
+    /// Size of the match relative to the template image
+    pub scale: f32,
+    pub center: (i32, i32),
+}
+
+/// A template image to look for, loaded as grayscale
+pub fn load(path: &Path) -> Result<GrayImage> {
+    let needle = image::open(path)
+        .with_context(|| format!("Failed to load template image {}", path.display()))?
+        .to_luma8();
+    let (first, rest) = needle
+        .as_raw()
+        .split_first()
+        .context("Template image is empty")?;
+    if rest.iter().all(|p| p == first) {
+        bail!(
+            "Template image {} is a single flat color and would match anywhere",
+            path.display()
+        );
+    }
+    Ok(needle)
+}
+
+/// Every place `needle` appears in `haystack` at one of `scales` with a score
+/// of at least `threshold`, best first
+///
+/// Each scale is searched on a shrunk copy of both images first; the places
+/// that come close to the threshold are then refined at full resolution.
+pub fn find(
+    haystack: &GrayImage,
+    needle: &GrayImage,
+    threshold: f32,
+    scales: &[f32],
+) -> Vec<TemplateMatch> {
+    let mut shrunk: HashMap<u32, GrayImage> = HashMap::new();
+    let mut matches = Vec::new();
+    for &scale in scales {
+        if !scale.is_finite() || scale <= 0.0 {
+            continue;
+        }
+        let width = (needle.width() as f32 * scale).round() as u32;
+        let height = (needle.height() as f32 * scale).round() as u32;
+        if width.min(height) < MIN_TEMPLATE_SIDE
+            || width > haystack.width()
+            || height > haystack.height()
+        {
+            continue;
+        }
+        let template = if width == needle.width() && height == needle.height() {
+            needle.clone()
+        } else {
+            imageops::resize(needle, width, height, FilterType::Triangle)
+        };
+        let Some(stats) = TemplateStats::of(&template) else {
+            continue;
+        };
+
+        let factor = (width.min(height) / COARSE_MIN_SIDE).clamp(1, MAX_COARSE_FACTOR);
+        if factor == 1 {
+            for (x, y, score) in candidates(haystack, &template, threshold) {
+                matches.push(found(x, y, &template, score, scale));
+            }
+            continue;
+        }
+
+        let coarse_haystack = shrunk.entry(factor).or_insert_with(|| {
+            imageops::resize(
+                haystack,
+                (haystack.width() / factor).max(1),
+                (haystack.height() / factor).max(1),
+                FilterType::Triangle,
+            )
+        });
+        let coarse_template = imageops::resize(
+            &template,
+            (width / factor).max(1),
+            (height / factor).max(1),
+            FilterType::Triangle,
+        );
+        if coarse_template.width() > coarse_haystack.width()
+            || coarse_template.height() > coarse_haystack.height()
+        {
+            continue;
+        }
+        for (x, y, _) in candidates(coarse_haystack, &coarse_template, threshold - COARSE_SLACK) {
+            let (x, y, score) = refine(haystack, &template, &stats, x * factor, y * factor, factor);
+            if score >= threshold {
+                matches.push(found(x, y, &template, score, scale));
+            }
+        }
+    }
+    suppress(matches)
+}
+
+/// Move every match by `(dx, dy)`, e.g. from capture to screen coordinates
+pub fn offset(matches: &mut [TemplateMatch], dx: i32, dy: i32) {
+    for m in matches {
+        m.x += dx;
+        m.y += dy;
+        m.center = (m.center.0 + dx, m.center.1 + dy);
+    }
+}
+
+fn found(x: u32, y: u32, template: &GrayImage, score: f32, scale: f32) -> TemplateMatch {
+    let (width, height) = template.dimensions();
+    TemplateMatch {
+        x: x as i32,
+        y: y as i32,
+        width,
+        height,
+        score,
+        scale,
+        center: ((x + width / 2) as i32, (y + height / 2) as i32),
+    }
+}
+
+/// Mean and spread of a template's pixels; `None` for a flat template
+struct TemplateStats {
+    mean: f64,
+    /// Sum of squared deviations from the mean
+    deviation: f64,
+}
+
+impl TemplateStats {
+    fn of(template: &GrayImage) -> Option<Self> {
+        let n = template.len() as f64;
+        let sum: f64 = template.as_raw().iter().map(|&p| p as f64).sum();
+        let squares: f64 = template
+            .as_raw()
+            .iter()
+            .map(|&p| (p as f64) * (p as f64))
+            .sum();
+        let mean = sum / n;
+        let deviation = squares - sum * mean;
+        (deviation > f64::EPSILON).then_some(Self { mean, deviation })
+    }
+}
+
+/// Zero-mean normalized cross-correlation from the raw sums over a window
+fn correlation(stats: &TemplateStats, n: f64, sum: f64, squares: f64, products: f64) -> f32 {
+    let deviation = squares - sum * sum / n;
+    // A flat patch of screen has nothing to correlate with
+    if deviation <= 1.0 {
+        return 0.0;
+    }
+    let numerator = products - stats.mean * sum;
+    (numerator / (deviation * stats.deviation).sqrt()).clamp(-1.0, 1.0) as f32
+}
+
+/// Local maxima of the score map at or above `threshold`, best first
+fn candidates(haystack: &GrayImage, template: &GrayImage, threshold: f32) -> Vec<(u32, u32, f32)> {
+    let Some(stats) = TemplateStats::of(template) else {
+        return Vec::new();
+    };
+    let (width, height) = template.dimensions();
+    let n = (width * height) as f64;
+    let products = match_template(haystack, template, MatchTemplateMethod::CrossCorrelation);
+    let sums = integral_image::<_, u64>(haystack);
+    let squares = integral_squared_image::<_, u64>(haystack);
+
+    let (cols, rows) = products.dimensions();
+    let mut scores = vec![0f32; (cols * rows) as usize];
+    for y in 0..rows {
+        for x in 0..cols {
+            let (right, bottom) = (x + width - 1, y + height - 1);
+            let sum = sum_image_pixels(&sums, x, y, right, bottom)[0] as f64;
+            let square = sum_image_pixels(&squares, x, y, right, bottom)[0] as f64;
+            let product = products.get_pixel(x, y)[0] as f64;
+            scores[(y * cols + x) as usize] = correlation(&stats, n, sum, square, product);
+        }
+    }
+
+    let score = |x: u32, y: u32| scores[(y * cols + x) as usize];
+    let mut peaks = Vec::new();
+    for y in 0..rows {
+        for x in 0..cols {
+            let value = score(x, y);
+            if value < threshold {
+                continue;
+            }
+            let peak = (y.saturating_sub(1)..=(y + 1).min(rows - 1)).all(|ny| {
+                (x.saturating_sub(1)..=(x + 1).min(cols - 1)).all(|nx| {
+                    let other = score(nx, ny);
+                    // Ties on a plateau go to the first position in reading order
+                    other < value || (other == value && (ny, nx) >= (y, x))
+                })
+            });
+            if peak {
+                peaks.push((x, y, value));
+            }
+        }
+    }
+    peaks.sort_by(|a, b| b.2.total_cmp(&a.2));
+    peaks.truncate(MAX_CANDIDATES);
+    peaks
+}
+
+/// Score of `template` with its top-left corner at `(x, y)` in `haystack`
+fn score_at(
+    haystack: &GrayImage,
+    template: &GrayImage,
+    stats: &TemplateStats,
+    x: u32,
+    y: u32,
+) -> f32 {
+    let (width, height) = template.dimensions();
+    let (mut sum, mut squares, mut products) = (0u64, 0u64, 0u64);
+    for ty in 0..height {
+        for tx in 0..width {
+            let Luma([pixel]) = *haystack.get_pixel(x + tx, y + ty);
+            let Luma([wanted]) = *template.get_pixel(tx, ty);
+            let pixel = pixel as u64;
+            sum += pixel;
+            squares += pixel * pixel;
+            products += pixel * wanted as u64;
+        }
+    }
+    correlation(
+        stats,
+        (width * height) as f64,
+        sum as f64,
+        squares as f64,
+        products as f64,
+    )
+}
+
+/// Best full-resolution position near a coarse hit, found by a search whose
+/// step halves until it reaches one pixel
+fn refine(
+    haystack: &GrayImage,
+    template: &GrayImage,
+    stats: &TemplateStats,
+    x: u32,
+    y: u32,
+    factor: u32,
+) -> (u32, u32, f32) {
+    let max_x = (haystack.width() - template.width()) as i64;
+    let max_y = (haystack.height() - template.height()) as i64;
+    let (mut x, mut y) = ((x as i64).min(max_x), (y as i64).min(max_y));
+    let mut best = score_at(haystack, template, stats, x as u32, y as u32);
+    let mut step = factor.div_ceil(2);
+    loop {
+        let step_i = step as i64;
+        let (center_x, center_y) = (x, y);
+        for dy in [-step_i, 0, step_i] {
+            for dx in [-step_i, 0, step_i] {
+                let (nx, ny) = (center_x + dx, center_y + dy);
+                if (dx, dy) == (0, 0) || !(0..=max_x).contains(&nx) || !(0..=max_y).contains(&ny) {
+                    continue;
+                }
+                let score = score_at(haystack, template, stats, nx as u32, ny as u32);
+                if score > best {
+                    (x, y, best) = (nx, ny, score);
+                }
+            }
+        }
+        if step == 1 {
+            break;
+        }
+        step = step.div_ceil(2);
+    }
+    (x as u32, y as u32, best)
+}
+
+/// Keep the best of each group of overlapping matches
+fn suppress(mut matches: Vec<TemplateMatch>) -> Vec<TemplateMatch> {
+    matches.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept: Vec<TemplateMatch> = Vec::new();
+    for candidate in matches {
+        if kept.iter().all(|m| overlap(m, &candidate) <= MAX_OVERLAP) {
+            kept.push(candidate);
+        }
+    }
+    kept
+}
+
+/// Shared area as a fraction of the smaller box
+fn overlap(a: &TemplateMatch, b: &TemplateMatch) -> f32 {
+    let width = (a.x + a.width as i32).min(b.x + b.width as i32) - a.x.max(b.x);
+    let height = (a.y + a.height as i32).min(b.y + b.height as i32) - a.y.max(b.y);
+    if width <= 0 || height <= 0 {
+        return 0.0;
+    }
+    let smaller = (a.width * a.height).min(b.width * b.height).max(1);
+    (width * height) as f32 / smaller as f32
+}